@@ -13,7 +13,9 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "mountain, snow, nature",
-    "is_ai_generated": false
+    "is_ai_generated": false,
+    "popularity": 812,
+    "updated_at": "2025-06-04T12:01:00.000Z"
   },
   {
     "id": "adf02e5b-4242-48a3-85ec-c4b5efc8b9a3",
@@ -24,7 +26,9 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "city, night, lights",
-    "is_ai_generated": false
+    "is_ai_generated": false,
+    "popularity": 954,
+    "updated_at": "2025-06-04T12:02:00.000Z"
   },
   {
     "id": "c1d703e4-b2f6-4418-9d7e-fc0a2f9e5e0a",
@@ -35,7 +39,9 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "forest, path, green",
-    "is_ai_generated": false
+    "is_ai_generated": false,
+    "popularity": 433,
+    "updated_at": "2025-06-04T12:03:00.000Z"
   },
   {
     "id": "f3a5db64-43a4-4970-8185-989f7403289d",
@@ -46,7 +52,9 @@ const wallpapers = [
     "width": 2560,
     "height": 1440,
     "tags": "space, nebula, stars",
-    "is_ai_generated": true
+    "is_ai_generated": true,
+    "popularity": 1210,
+    "updated_at": "2025-06-04T12:04:00.000Z"
   },
   {
     "id": "3ab2df97-4076-4d86-98a7-4f5dca99a6b4",
@@ -57,7 +65,9 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "desert, dunes, sand",
-    "is_ai_generated": false
+    "is_ai_generated": false,
+    "popularity": 287,
+    "updated_at": "2025-06-04T12:05:00.000Z"
   },
   {
     "id": "709b7f12-6d42-4b8f-a693-2e98e3d6a38c",
@@ -68,7 +78,9 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "ocean, sea, waves",
-    "is_ai_generated": false
+    "is_ai_generated": false,
+    "popularity": 665,
+    "updated_at": "2025-06-04T12:06:00.000Z"
   },
   {
     "id": "51d7d36e-3a62-49dc-9d5f-d9dfabcaaac2",
@@ -79,7 +91,9 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "aurora, sky, night",
-    "is_ai_generated": true
+    "is_ai_generated": true,
+    "popularity": 1502,
+    "updated_at": "2025-06-04T12:07:00.000Z"
   },
   {
     "id": "d1b7186a-2b43-4aa3-a012-30a06ecfcaa2",
@@ -90,7 +104,9 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "sunset, field, grass",
-    "is_ai_generated": false
+    "is_ai_generated": false,
+    "popularity": 390,
+    "updated_at": "2025-06-04T12:08:00.000Z"
   },
   {
     "id": "8c5de2b7-88a6-49e2-a67a-35ae96cc760f",
@@ -101,7 +117,9 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "lake, frozen, snow",
-    "is_ai_generated": false
+    "is_ai_generated": false,
+    "popularity": 578,
+    "updated_at": "2025-06-04T12:09:00.000Z"
   },
   {
     "id": "f712313b-cb9f-49d5-8f46-446b1ad295f2",
@@ -112,12 +130,67 @@ const wallpapers = [
     "width": 1920,
     "height": 1080,
     "tags": "abstract, neon, glow",
-    "is_ai_generated": true
+    "is_ai_generated": true,
+    "popularity": 940,
+    "updated_at": "2025-06-04T12:10:00.000Z"
   }
 ]
 
+// Wallpapers taken down on the server. Clients receive these as tombstones
+// from the change feed so they can drop their local copies.
+const deletedWallpapers = [
+  {
+    "mongo_id": "665fd801c234de001a51aaff",
+    "updated_at": "2025-06-04T12:30:00.000Z",
+    "deleted": true
+  }
+]
+
+// Cursors are "<updated_at>|<mongo_id>" so entries sharing a timestamp are
+// never skipped between pages.
+const changeKey = (item) => `${item.updated_at}|${item.mongo_id}`;
+
+// Screen-sized renditions, served by the image CDN's resize parameters.
+const variantSizes = [
+  { kind: "thumbnail", width: 400, height: 225 },
+  { kind: "1080p", width: 1920, height: 1080 },
+  { kind: "1440p", width: 2560, height: 1440 },
+  { kind: "4k", width: 3840, height: 2160 },
+];
+
+const withVariants = (item) => {
+  if (item.deleted) return item;
+  const variants = variantSizes.map(({ kind, width, height }) => ({
+    kind,
+    url: `${item.url}?w=${width}&h=${height}&fit=crop`,
+    width,
+    height,
+  }));
+  variants.push({ kind: "original", url: item.url, width: item.width, height: item.height });
+  return { ...item, variants };
+};
+
 app.get("/api/wallpapers", (req, res) => {
-  res.json(wallpapers); // Only send the wallpaper array
+  res.json(wallpapers.map(withVariants)); // Only send the wallpaper array
+});
+
+// Change feed: everything updated or deleted after `since`, oldest first.
+app.get("/api/wallpapers/changes", (req, res) => {
+  const since = req.query.since || "";
+  const limit = Math.max(1, Math.min(parseInt(req.query.limit, 10) || 100, 500));
+
+  const pending = [...wallpapers, ...deletedWallpapers]
+    .filter((item) => changeKey(item) > since)
+    .sort((a, b) => changeKey(a).localeCompare(changeKey(b)));
+
+  const changes = pending.slice(0, limit).map(withVariants);
+  const last = changes[changes.length - 1];
+
+  res.json({
+    changes,
+    next_cursor: last ? changeKey(last) : since || null,
+    has_more: pending.length > changes.length,
+  });
 });
 
 app.listen(port, () => {
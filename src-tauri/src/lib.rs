@@ -7,12 +7,63 @@ use services::wallpaper_service::{
 };
 
 use services::db_services::{
-    add_to_favorites, delete_favorite_wallpaper_command, fetch_favorite_wallpapers,
-    fetch_wallpapers, get_app_settings, get_wallpaper_count_command, get_wallpapers,
-    get_wallpapers_by_tag_command, prune_wallpaper_db_command, reset_wallpaper_settings,
-    update_app_setting, update_app_setting_command, update_favorite_command,
+    add_to_favorites, block_wallpaper_command, delete_favorite_wallpaper_command,
+    fetch_favorite_wallpapers, fetch_wallpapers, get_app_settings, get_db_path,
+    get_wallpaper_count_command, get_wallpapers, get_wallpapers_by_tag_command,
+    get_wallpapers_by_tags_command, init_pool, list_blocked_wallpapers_command, list_tags_command,
+    prune_wallpaper_db_command, reset_wallpaper_settings, set_wallpaper_hidden_command,
+    set_wallpaper_rating_command, unblock_wallpaper_command, update_app_setting,
+    update_app_setting_command, update_favorite_command,
 };
 
+use services::crop_service::set_focal_point_command;
+
+use services::duplicate_service::{find_duplicates_command, start_hash_backfill};
+
+use services::import_service::import_folder;
+
+use services::library_service::{
+    add_library_folder_command, list_library_folders_command, remove_library_folder_command,
+    rescan_library_command, start_library_watcher,
+};
+
+use services::metadata_service::get_wallpaper_metadata;
+
+use services::collection_service::{
+    add_to_collection_command, create_collection_command, delete_collection_command,
+    export_collection, get_collection_wallpapers_command, list_collections_command,
+    remove_from_collection_command, rename_collection_command, reorder_collection_items_command,
+    reorder_collections_command, set_collection_cover_command, start_collection_rotation,
+};
+
+use services::smart_collection_service::{
+    create_smart_collection_command, delete_smart_collection_command, export_smart_collection,
+    list_smart_collections_command, query_smart_collection_command,
+    start_smart_collection_rotation, update_smart_collection_command,
+};
+
+use services::palette_service::{get_accent_color, get_palette, start_palette_backfill};
+
+use services::overlay_service::{get_overlay_settings_command, set_overlay_settings_command};
+
+use services::color_scheme_service::{
+    get_color_scheme_settings_command, set_color_scheme_settings_command,
+};
+
+use services::effects_service::{
+    get_global_effects_command, get_wallpaper_effects_command, set_global_effects_command,
+    set_wallpaper_effects_command,
+};
+
+use services::query_service::query_wallpapers_command;
+
+use services::search_service::search_wallpapers_command;
+
+use services::sync_service::{start_background_sync, sync_now};
+
+use services::thumbnail_service::{serve_thumbnail, THUMBNAIL_SCHEME};
+
+use tauri::Manager;
 use tauri_plugin_autostart::MacosLauncher;
 
 #[tauri::command]
@@ -30,6 +81,19 @@ pub fn run() {
             MacosLauncher::LaunchAgent,
             None,
         ))
+        .register_asynchronous_uri_scheme_protocol(THUMBNAIL_SCHEME, |_ctx, request, responder| {
+            // Reading and generating files shouldn't hold up the webview.
+            std::thread::spawn(move || responder.respond(serve_thumbnail(&request)));
+        })
+        .setup(|app| {
+            let pool = init_pool(&get_db_path())?;
+            app.manage(pool);
+            start_background_sync(app.handle().clone());
+            start_palette_backfill(app.handle().clone());
+            start_hash_backfill(app.handle().clone());
+            start_library_watcher(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             apply_wallpaper,
@@ -47,9 +111,57 @@ pub fn run() {
             get_wallpaper_count_command,
             prune_wallpaper_db_command,
             get_wallpapers_by_tag_command,
+            get_wallpapers_by_tags_command,
+            list_tags_command,
+            query_wallpapers_command,
+            search_wallpapers_command,
             delete_favorite_wallpaper_command,
+            set_wallpaper_rating_command,
+            set_wallpaper_hidden_command,
+            block_wallpaper_command,
+            unblock_wallpaper_command,
+            list_blocked_wallpapers_command,
             update_favorite_command,
-            reset_wallpaper_settings
+            reset_wallpaper_settings,
+            set_focal_point_command,
+            get_global_effects_command,
+            set_global_effects_command,
+            get_wallpaper_effects_command,
+            set_wallpaper_effects_command,
+            get_overlay_settings_command,
+            set_overlay_settings_command,
+            get_palette,
+            get_accent_color,
+            get_color_scheme_settings_command,
+            set_color_scheme_settings_command,
+            find_duplicates_command,
+            import_folder,
+            list_library_folders_command,
+            add_library_folder_command,
+            remove_library_folder_command,
+            rescan_library_command,
+            get_wallpaper_metadata,
+            list_collections_command,
+            create_collection_command,
+            rename_collection_command,
+            delete_collection_command,
+            reorder_collections_command,
+            get_collection_wallpapers_command,
+            add_to_collection_command,
+            remove_from_collection_command,
+            reorder_collection_items_command,
+            set_collection_cover_command,
+            start_collection_rotation,
+            export_collection,
+            list_smart_collections_command,
+            create_smart_collection_command,
+            update_smart_collection_command,
+            delete_smart_collection_command,
+            query_smart_collection_command,
+            start_smart_collection_rotation,
+            export_smart_collection,
+            // Sync
+            sync_now
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
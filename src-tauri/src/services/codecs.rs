@@ -0,0 +1,306 @@
+use image::DynamicImage;
+
+/// Encodings wallpapers are read from. Animated GIF and APNG count as their
+/// first frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Bmp,
+    Tiff,
+    Avif,
+    Heif,
+    Jxl,
+}
+
+/// ISO-BMFF brands of AVIF files.
+const AVIF_BRANDS: [&[u8]; 2] = [b"avif", b"avis"];
+
+/// ISO-BMFF brands of HEIF files, HEIC included.
+const HEIF_BRANDS: [&[u8]; 8] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+
+/// Formats outside the image crate this build was compiled to decode.
+const HEAVY_DECODERS: &[SourceFormat] = &[
+    #[cfg(feature = "avif")]
+    SourceFormat::Avif,
+    #[cfg(feature = "heic")]
+    SourceFormat::Heif,
+    #[cfg(feature = "jxl")]
+    SourceFormat::Jxl,
+];
+
+impl SourceFormat {
+    pub const ALL: [SourceFormat; 9] = [
+        SourceFormat::Jpeg,
+        SourceFormat::Png,
+        SourceFormat::Gif,
+        SourceFormat::Webp,
+        SourceFormat::Bmp,
+        SourceFormat::Tiff,
+        SourceFormat::Avif,
+        SourceFormat::Heif,
+        SourceFormat::Jxl,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceFormat::Jpeg => "JPEG",
+            SourceFormat::Png => "PNG",
+            SourceFormat::Gif => "GIF",
+            SourceFormat::Webp => "WebP",
+            SourceFormat::Bmp => "BMP",
+            SourceFormat::Tiff => "TIFF",
+            SourceFormat::Avif => "AVIF",
+            SourceFormat::Heif => "HEIF",
+            SourceFormat::Jxl => "JPEG XL",
+        }
+    }
+
+    /// File extensions of the format, lowercase.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            SourceFormat::Jpeg => &["jpg", "jpeg", "jpe", "jfif"],
+            SourceFormat::Png => &["png", "apng"],
+            SourceFormat::Gif => &["gif"],
+            SourceFormat::Webp => &["webp"],
+            SourceFormat::Bmp => &["bmp", "dib"],
+            SourceFormat::Tiff => &["tif", "tiff"],
+            SourceFormat::Avif => &["avif"],
+            SourceFormat::Heif => &["heic", "heif", "hif"],
+            SourceFormat::Jxl => &["jxl"],
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        let ext = ext.to_ascii_lowercase();
+        SourceFormat::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&ext.as_str()))
+    }
+
+    /// The format of an encoded image, from its leading bytes.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        let starts = |magic: &[u8]| bytes.starts_with(magic);
+        if starts(b"\xFF\xD8\xFF") {
+            Some(SourceFormat::Jpeg)
+        } else if starts(b"\x89PNG\r\n\x1a\n") {
+            Some(SourceFormat::Png)
+        } else if starts(b"GIF87a") || starts(b"GIF89a") {
+            Some(SourceFormat::Gif)
+        } else if starts(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+            Some(SourceFormat::Webp)
+        } else if starts(b"BM") {
+            Some(SourceFormat::Bmp)
+        } else if starts(b"II*\0") || starts(b"MM\0*") {
+            Some(SourceFormat::Tiff)
+        } else if starts(b"\xFF\x0A") || starts(b"\0\0\0\x0CJXL \r\n\x87\n") {
+            Some(SourceFormat::Jxl)
+        } else if bytes.get(4..8) == Some(b"ftyp") {
+            iso_format(bytes)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the image crate decodes the format itself.
+    pub fn is_builtin(self) -> bool {
+        !matches!(
+            self,
+            SourceFormat::Avif | SourceFormat::Heif | SourceFormat::Jxl
+        )
+    }
+
+    /// Whether this build can decode the format. The heavy decoders are
+    /// cargo features.
+    pub fn is_enabled(self) -> bool {
+        self.is_builtin() || HEAVY_DECODERS.contains(&self)
+    }
+}
+
+/// AVIF or HEIF from the brands of the `ftyp` box. AVIF files often list
+/// HEIF brands as well, so AVIF is checked first.
+fn iso_format(bytes: &[u8]) -> Option<SourceFormat> {
+    let size = u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let ftyp = bytes.get(8..size.min(bytes.len()))?;
+    // Major brand, minor version, then compatible brands.
+    let brands: Vec<&[u8]> = ftyp
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|&(i, _)| i != 1)
+        .map(|(_, brand)| brand)
+        .collect();
+    if brands.iter().any(|b| AVIF_BRANDS.contains(b)) {
+        Some(SourceFormat::Avif)
+    } else if brands.iter().any(|b| HEIF_BRANDS.contains(b)) {
+        Some(SourceFormat::Heif)
+    } else {
+        None
+    }
+}
+
+/// Decodes a format the image crate doesn't handle.
+#[cfg_attr(
+    not(any(feature = "avif", feature = "heic", feature = "jxl")),
+    allow(unused_variables)
+)]
+pub fn decode_heavy(format: SourceFormat, bytes: &[u8]) -> Result<DynamicImage, String> {
+    if !format.is_enabled() {
+        return Err(format!(
+            "Unsupported image: {} support is not enabled in this build",
+            format.name()
+        ));
+    }
+    #[cfg(any(feature = "avif", feature = "heic"))]
+    if matches!(format, SourceFormat::Avif | SourceFormat::Heif) {
+        return decode_heif(bytes);
+    }
+    #[cfg(feature = "jxl")]
+    if format == SourceFormat::Jxl {
+        return decode_jxl(bytes);
+    }
+    Err(format!("Unsupported image: {}", format.name()))
+}
+
+/// AVIF and HEIC through libheif, which also applies the container's
+/// rotation and mirroring.
+#[cfg(any(feature = "avif", feature = "heic"))]
+fn decode_heif(bytes: &[u8]) -> Result<DynamicImage, String> {
+    use image::RgbaImage;
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let lib = LibHeif::new();
+    let context = HeifContext::read_from_bytes(bytes).map_err(|e| e.to_string())?;
+    let handle = context.primary_image_handle().map_err(|e| e.to_string())?;
+    let image = lib
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .map_err(|e| e.to_string())?;
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or("Decoded image has no pixels")?;
+
+    let (width, height) = (plane.width, plane.height);
+    let row_len = width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_len * height as usize);
+    for row in plane.data.chunks(plane.stride).take(height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    RgbaImage::from_raw(width, height, pixels)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| "Decoded image has the wrong size".to_string())
+}
+
+/// JPEG XL through the reference `djxl`, which has to be installed.
+#[cfg(feature = "jxl")]
+fn decode_jxl(bytes: &[u8]) -> Result<DynamicImage, String> {
+    use std::fs;
+    use std::process::Command;
+
+    let dir = std::env::temp_dir().join(format!("wallpaper-jxl-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let (input, output) = (dir.join("in.jxl"), dir.join("out.png"));
+
+    let result = fs::write(&input, bytes)
+        .map_err(|e| e.to_string())
+        .and_then(|_| {
+            Command::new("djxl")
+                .arg(&input)
+                .arg(&output)
+                .output()
+                .map_err(|e| format!("Failed to run djxl: {e}"))
+        })
+        .and_then(|run| {
+            if !run.status.success() {
+                return Err(format!(
+                    "djxl failed: {}",
+                    String::from_utf8_lossy(&run.stderr).trim()
+                ));
+            }
+            let png = fs::read(&output).map_err(|e| e.to_string())?;
+            image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+                .map_err(|e| e.to_string())
+        });
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::image_pipeline::decode;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    fn ftyp(major: &[u8], compatible: &[&[u8]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len() as u32;
+        let mut out = size.to_be_bytes().to_vec();
+        out.extend_from_slice(b"ftyp");
+        out.extend_from_slice(major);
+        out.extend_from_slice(&[0; 4]);
+        for brand in compatible {
+            out.extend_from_slice(brand);
+        }
+        out
+    }
+
+    #[test]
+    fn detects_formats_from_their_leading_bytes() {
+        assert_eq!(SourceFormat::detect(b"GIF89a..."), Some(SourceFormat::Gif));
+        assert_eq!(
+            SourceFormat::detect(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(SourceFormat::Webp)
+        );
+        assert_eq!(SourceFormat::detect(b"MM\0*\0\0"), Some(SourceFormat::Tiff));
+        assert_eq!(
+            SourceFormat::detect(b"\xFF\x0A\x01"),
+            Some(SourceFormat::Jxl)
+        );
+        assert_eq!(
+            SourceFormat::detect(b"\0\0\0\x0CJXL \r\n\x87\n\0\0"),
+            Some(SourceFormat::Jxl)
+        );
+        assert_eq!(
+            SourceFormat::detect(&ftyp(b"mif1", &[b"mif1", b"avif", b"miaf"])),
+            Some(SourceFormat::Avif)
+        );
+        assert_eq!(
+            SourceFormat::detect(&ftyp(b"heic", &[b"mif1", b"heic"])),
+            Some(SourceFormat::Heif)
+        );
+        assert_eq!(SourceFormat::detect(&ftyp(b"isom", &[b"mp41"])), None);
+        assert_eq!(SourceFormat::detect(b"plain text"), None);
+
+        assert_eq!(
+            SourceFormat::from_extension("HEIC"),
+            Some(SourceFormat::Heif)
+        );
+        assert_eq!(SourceFormat::from_extension("txt"), None);
+    }
+
+    #[test]
+    fn decodes_every_builtin_format() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(12, 8, |x, y| {
+            Rgb([x as u8 * 20, y as u8 * 30, 90])
+        }));
+        for format in [ImageFormat::Bmp, ImageFormat::Tiff, ImageFormat::Gif] {
+            let mut bytes = Vec::new();
+            img.write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+            assert!(SourceFormat::detect(&bytes).unwrap().is_builtin());
+            let decoded = decode(&bytes).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (12, 8));
+        }
+    }
+
+    #[cfg(not(feature = "heic"))]
+    #[test]
+    fn disabled_decoders_are_reported() {
+        let err = decode(&ftyp(b"heic", &[b"mif1"])).unwrap_err();
+        assert!(err.contains("HEIF support is not enabled"));
+        assert!(!SourceFormat::Heif.is_enabled());
+        assert!(SourceFormat::Tiff.is_enabled());
+    }
+}
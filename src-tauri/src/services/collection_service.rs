@@ -0,0 +1,688 @@
+use crate::services::db_services::{
+    get_setting, wallpaper_from_row, DbPool, Wallpaper, NOT_BLOCKED, WALLPAPER_COLUMNS,
+};
+use crate::services::effects::Effect;
+use crate::services::image_pipeline::OutputFormat;
+use crate::services::wallpaper_service::{save_wallpaper_file, start_wallpaper_rotation};
+use dirs::download_dir;
+use rusqlite::{params, Connection, OptionalExtension, Params, Result as SqlResult};
+use sanitize_filename::sanitize;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// The built-in collection mirroring favorites.
+pub const FAVORITES_COLLECTION: &str = "favorites";
+
+#[derive(Serialize)]
+pub struct Collection {
+    pub id: String,
+    pub name: String,
+    pub is_builtin: bool,
+    pub item_count: u32,
+    /// The cover the user picked, if any.
+    pub cover_wallpaper_id: Option<String>,
+    /// What to show for the collection: the picked cover, else the first
+    /// item. Hidden and blocked wallpapers are never shown.
+    pub cover: Option<Wallpaper>,
+}
+
+/// Outcome of an export, also sent as `export-progress` events.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct ExportReport {
+    pub directory: String,
+    /// Paths written.
+    pub written: Vec<String>,
+    /// One message per wallpaper that could not be written.
+    pub failed: Vec<String>,
+    pub total: u32,
+}
+
+/// Built-in collections first, then in the user's order.
+const COLLECTION_ORDER: &str = "c.is_builtin DESC, c.position, c.created_at";
+
+/// Condition on `w` for items that show in listings: neither hidden nor
+/// blocked.
+fn visible() -> String {
+    format!("w.is_hidden = 0 AND {NOT_BLOCKED}")
+}
+
+fn wallpaper_by_id(conn: &Connection, id: &str) -> SqlResult<Option<Wallpaper>> {
+    conn.query_row(
+        &format!("SELECT {WALLPAPER_COLUMNS} FROM wallpapers w WHERE w.id = ?1"),
+        params![id],
+        wallpaper_from_row,
+    )
+    .optional()
+}
+
+/// Collections matching `condition` on `c` (collections), in display order.
+fn select_collections(
+    conn: &Connection,
+    condition: &str,
+    values: impl Params,
+) -> SqlResult<Vec<Collection>> {
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT c.id, c.name, c.is_builtin, c.cover_wallpaper_id,
+               (SELECT COUNT(*) FROM collection_items i
+                JOIN wallpapers w ON w.id = i.wallpaper_id
+                WHERE i.collection_id = c.id AND {VISIBLE}),
+               COALESCE(
+                   (SELECT i.wallpaper_id FROM collection_items i
+                    JOIN wallpapers w ON w.id = i.wallpaper_id
+                    WHERE i.collection_id = c.id AND i.wallpaper_id = c.cover_wallpaper_id
+                      AND {VISIBLE}),
+                   (SELECT i.wallpaper_id FROM collection_items i
+                    JOIN wallpapers w ON w.id = i.wallpaper_id
+                    WHERE i.collection_id = c.id AND {VISIBLE}
+                    ORDER BY i.position, i.added_at LIMIT 1)
+               )
+        FROM collections c
+        WHERE {condition}
+        ORDER BY {COLLECTION_ORDER}
+        "#,
+        VISIBLE = visible()
+    ))?;
+    let rows = stmt
+        .query_map(values, |row| {
+            Ok((
+                Collection {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    is_builtin: row.get(2)?,
+                    cover_wallpaper_id: row.get(3)?,
+                    item_count: row.get(4)?,
+                    cover: None,
+                },
+                row.get::<_, Option<String>>(5)?,
+            ))
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    rows.into_iter()
+        .map(|(mut collection, cover)| {
+            if let Some(cover) = cover {
+                collection.cover = wallpaper_by_id(conn, &cover)?;
+            }
+            Ok(collection)
+        })
+        .collect()
+}
+
+pub fn list_collections(conn: &Connection) -> SqlResult<Vec<Collection>> {
+    select_collections(conn, "1", [])
+}
+
+pub fn get_collection(conn: &Connection, id: &str) -> SqlResult<Option<Collection>> {
+    Ok(select_collections(conn, "c.id = ?1", params![id])?.pop())
+}
+
+pub fn create_collection(conn: &Connection, name: &str) -> SqlResult<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO collections (id, name, position)
+         VALUES (?1, ?2, (SELECT COALESCE(MAX(position) + 1, 0) FROM collections))",
+        params![id, name.trim()],
+    )?;
+    Ok(id)
+}
+
+/// Renames a user collection; built-in ones keep their name. Returns
+/// whether anything changed.
+pub fn rename_collection(conn: &Connection, id: &str, name: &str) -> SqlResult<bool> {
+    let changed = conn.execute(
+        "UPDATE collections SET name = ?2 WHERE id = ?1 AND is_builtin = 0",
+        params![id, name.trim()],
+    )?;
+    Ok(changed > 0)
+}
+
+/// Deletes a user collection; the wallpapers in it stay in the library.
+pub fn delete_collection(conn: &Connection, id: &str) -> SqlResult<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM collections WHERE id = ?1 AND is_builtin = 0",
+        params![id],
+    )?;
+    Ok(deleted > 0)
+}
+
+/// Puts collections in the order of `ids`; any left out follow in their
+/// current order. The new order is written all at once or not at all.
+pub fn reorder_collections(conn: &Connection, ids: &[String]) -> SqlResult<()> {
+    let tx = conn.unchecked_transaction()?;
+    let current: Vec<String> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT c.id FROM collections c ORDER BY {COLLECTION_ORDER}"
+        ))?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect::<SqlResult<_>>()?
+    };
+    {
+        let mut update = tx.prepare("UPDATE collections SET position = ?2 WHERE id = ?1")?;
+        for (position, id) in reordered(&current, ids).iter().enumerate() {
+            update.execute(params![id, position as i64])?;
+        }
+    }
+    tx.commit()
+}
+
+/// `current` with the ids in `order` moved to the front, in that order.
+/// Ids not in `current` are ignored.
+fn reordered(current: &[String], order: &[String]) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::with_capacity(current.len());
+    for id in order.iter().chain(current) {
+        if current.contains(id) && !ids.contains(id) {
+            ids.push(id.clone());
+        }
+    }
+    ids
+}
+
+/// The wallpapers in a collection in its order, leaving out hidden and
+/// blocked ones.
+pub fn collection_wallpapers(conn: &Connection, id: &str) -> SqlResult<Vec<Wallpaper>> {
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT {WALLPAPER_COLUMNS}
+        FROM collection_items i
+        JOIN wallpapers w ON w.id = i.wallpaper_id
+        WHERE i.collection_id = ?1 AND {VISIBLE}
+        ORDER BY i.position, i.added_at
+        "#,
+        VISIBLE = visible()
+    ))?;
+    let rows = stmt.query_map(params![id], wallpaper_from_row)?;
+    rows.collect()
+}
+
+/// Appends wallpapers to a collection, skipping those already in it.
+/// Returns how many were added, or `None` if there is no such collection.
+pub fn add_to_collection(
+    conn: &Connection,
+    id: &str,
+    wallpaper_ids: &[String],
+) -> SqlResult<Option<u32>> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM collections WHERE id = ?1)",
+        params![id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(None);
+    }
+    let mut insert = conn.prepare(
+        "INSERT OR IGNORE INTO collection_items (collection_id, wallpaper_id, position)
+         SELECT c.id, w.id,
+                (SELECT COALESCE(MAX(position) + 1, 0) FROM collection_items
+                 WHERE collection_id = ?1)
+         FROM wallpapers w
+         JOIN collections c ON c.id = ?1
+         WHERE w.id = ?2",
+    )?;
+    let mut added = 0;
+    for wallpaper_id in wallpaper_ids {
+        added += insert.execute(params![id, wallpaper_id])? as u32;
+    }
+    Ok(Some(added))
+}
+
+pub fn remove_from_collection(
+    conn: &Connection,
+    id: &str,
+    wallpaper_ids: &[String],
+) -> SqlResult<u32> {
+    let mut delete = conn
+        .prepare("DELETE FROM collection_items WHERE collection_id = ?1 AND wallpaper_id = ?2")?;
+    let mut removed = 0;
+    for wallpaper_id in wallpaper_ids {
+        removed += delete.execute(params![id, wallpaper_id])? as u32;
+    }
+    Ok(removed)
+}
+
+/// Puts the items of a collection in the order of `wallpaper_ids`; any left
+/// out follow in their current order. The new order is written all at once
+/// or not at all.
+pub fn reorder_collection_items(
+    conn: &Connection,
+    id: &str,
+    wallpaper_ids: &[String],
+) -> SqlResult<()> {
+    let tx = conn.unchecked_transaction()?;
+    let current: Vec<String> = {
+        let mut stmt = tx.prepare(
+            "SELECT wallpaper_id FROM collection_items
+             WHERE collection_id = ?1 ORDER BY position, added_at",
+        )?;
+        let rows = stmt.query_map(params![id], |row| row.get(0))?;
+        rows.collect::<SqlResult<_>>()?
+    };
+    {
+        let mut update = tx.prepare(
+            "UPDATE collection_items SET position = ?3 WHERE collection_id = ?1 AND wallpaper_id = ?2",
+        )?;
+        for (position, wallpaper_id) in reordered(&current, wallpaper_ids).iter().enumerate() {
+            update.execute(params![id, wallpaper_id, position as i64])?;
+        }
+    }
+    tx.commit()
+}
+
+/// Picks the cover of a collection from its items, or goes back to the
+/// first item with `None`. Returns false if the collection doesn't exist or
+/// the wallpaper isn't in it.
+pub fn set_collection_cover(
+    conn: &Connection,
+    id: &str,
+    wallpaper_id: Option<&str>,
+) -> SqlResult<bool> {
+    let changed = conn.execute(
+        "UPDATE collections SET cover_wallpaper_id = ?2
+         WHERE id = ?1
+           AND (?2 IS NULL OR EXISTS (
+               SELECT 1 FROM collection_items WHERE collection_id = ?1 AND wallpaper_id = ?2
+           ))",
+        params![id, wallpaper_id],
+    )?;
+    Ok(changed > 0)
+}
+
+/// Default folder exports go to: a folder named after them in Downloads.
+pub(crate) fn export_dir(name: &str) -> Result<PathBuf, String> {
+    let downloads = download_dir().ok_or("Failed to resolve system download directory")?;
+    Ok(downloads.join(sanitize(name)))
+}
+
+/// Writes `wallpapers` into `dir` in their order, numbered so file
+/// managers keep it, in the image format from settings. `on_progress` is
+/// called after each one.
+pub fn export_wallpapers<F>(
+    app: &AppHandle,
+    wallpapers: &[Wallpaper],
+    dir: &Path,
+    mut on_progress: F,
+) -> Result<ExportReport, String>
+where
+    F: FnMut(&ExportReport),
+{
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let format = {
+        let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+        let settings = get_setting(&conn).map_err(|e| e.to_string())?;
+        settings
+            .get("imageFormat")
+            .and_then(|f| OutputFormat::parse(f))
+            .unwrap_or_default()
+    };
+
+    let mut report = ExportReport {
+        directory: dir.to_string_lossy().into_owned(),
+        total: wallpapers.len() as u32,
+        ..Default::default()
+    };
+    let width = wallpapers.len().to_string().len().max(2);
+    for (index, wallpaper) in wallpapers.iter().enumerate() {
+        let title = match sanitize(&wallpaper.title) {
+            title if title.trim().is_empty() => wallpaper.id.clone(),
+            title => title,
+        };
+        let path = dir.join(format!(
+            "{:0width$}-{}.{}",
+            index + 1,
+            title,
+            format.extension()
+        ));
+        match save_wallpaper_file(app, &wallpaper.url, &path) {
+            Ok(()) => report.written.push(path.to_string_lossy().into_owned()),
+            Err(e) => report.failed.push(format!("{}: {}", wallpaper.title, e)),
+        }
+        on_progress(&report);
+    }
+    Ok(report)
+}
+
+fn not_found(id: &str) -> String {
+    format!("No collection {id}, or it is built in")
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn list_collections_command(db: State<'_, DbPool>) -> Result<Vec<Collection>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    list_collections(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_collection_command(
+    db: State<'_, DbPool>,
+    name: String,
+) -> Result<Collection, String> {
+    if name.trim().is_empty() {
+        return Err("Collection name is empty".into());
+    }
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let id = create_collection(&conn, &name).map_err(|e| e.to_string())?;
+    get_collection(&conn, &id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| not_found(&id))
+}
+
+#[tauri::command]
+pub async fn rename_collection_command(
+    db: State<'_, DbPool>,
+    id: String,
+    name: String,
+) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Collection name is empty".into());
+    }
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match rename_collection(&conn, &id, &name).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(not_found(&id)),
+    }
+}
+
+#[tauri::command]
+pub async fn delete_collection_command(db: State<'_, DbPool>, id: String) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match delete_collection(&conn, &id).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(not_found(&id)),
+    }
+}
+
+#[tauri::command]
+pub async fn reorder_collections_command(
+    db: State<'_, DbPool>,
+    ids: Vec<String>,
+) -> Result<Vec<Collection>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    reorder_collections(&conn, &ids).map_err(|e| e.to_string())?;
+    list_collections(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_collection_wallpapers_command(
+    db: State<'_, DbPool>,
+    id: String,
+) -> Result<Vec<Wallpaper>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    collection_wallpapers(&conn, &id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn add_to_collection_command(
+    db: State<'_, DbPool>,
+    id: String,
+    wallpaper_ids: Vec<String>,
+) -> Result<u32, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    add_to_collection(&conn, &id, &wallpaper_ids)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| not_found(&id))
+}
+
+#[tauri::command]
+pub async fn remove_from_collection_command(
+    db: State<'_, DbPool>,
+    id: String,
+    wallpaper_ids: Vec<String>,
+) -> Result<u32, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    remove_from_collection(&conn, &id, &wallpaper_ids).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reorder_collection_items_command(
+    db: State<'_, DbPool>,
+    id: String,
+    wallpaper_ids: Vec<String>,
+) -> Result<Vec<Wallpaper>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    reorder_collection_items(&conn, &id, &wallpaper_ids).map_err(|e| e.to_string())?;
+    collection_wallpapers(&conn, &id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_collection_cover_command(
+    db: State<'_, DbPool>,
+    id: String,
+    wallpaper_id: Option<String>,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match set_collection_cover(&conn, &id, wallpaper_id.as_deref()).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => match wallpaper_id {
+            Some(wallpaper_id) => Err(format!(
+                "Wallpaper {wallpaper_id} is not in collection {id}"
+            )),
+            None => Err(format!("No collection {id}")),
+        },
+    }
+}
+
+/// Rotates through the available wallpapers of a collection, in its order.
+#[tauri::command]
+pub async fn start_collection_rotation(
+    app: AppHandle,
+    id: String,
+    interval_sec: u64,
+    effects: Option<Vec<Effect>>,
+) -> Result<(), String> {
+    let urls: Vec<String> = {
+        let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+        collection_wallpapers(&conn, &id)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|w| w.is_available)
+            .map(|w| w.url)
+            .collect()
+    };
+    if urls.is_empty() {
+        return Err("The collection has no wallpapers to rotate".into());
+    }
+    start_wallpaper_rotation(app, urls, interval_sec, effects)
+}
+
+/// Exports a collection to `destination`, or to a folder named after it in
+/// Downloads, emitting `export-progress` as it goes.
+#[tauri::command]
+pub async fn export_collection(
+    app: AppHandle,
+    id: String,
+    destination: Option<String>,
+) -> Result<ExportReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let (collection, wallpapers) = {
+            let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+            let collection = get_collection(&conn, &id)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("No collection {id}"))?;
+            let wallpapers = collection_wallpapers(&conn, &id).map_err(|e| e.to_string())?;
+            (collection, wallpapers)
+        };
+        let dir = match destination {
+            Some(path) => PathBuf::from(path),
+            None => export_dir(&collection.name)?,
+        };
+        export_wallpapers(&app, &wallpapers, &dir, |progress| {
+            let _ = app.emit("export-progress", progress);
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::db_services::{add_favorite, get_favorite_wallpapers, remove_favorite};
+    use crate::services::migrations::migrate;
+
+    fn seeded() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO wallpapers (id, mongo_id, title, url, thumbnail, width, height, tags)
+            VALUES
+                ('a', 'm1', 'A', 'https://x/a.jpg', '', 10, 10, ''),
+                ('b', 'm2', 'B', 'https://x/b.jpg', '', 10, 10, ''),
+                ('c', 'm3', 'C', 'https://x/c.jpg', '', 10, 10, '');
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn ids(wallpapers: Vec<Wallpaper>) -> Vec<String> {
+        wallpapers.into_iter().map(|w| w.id).collect()
+    }
+
+    fn strings(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn collections_keep_their_items_in_order() {
+        let conn = seeded();
+        let trips = create_collection(&conn, " Trips ").unwrap();
+        let cities = create_collection(&conn, "Cities").unwrap();
+
+        assert_eq!(
+            add_to_collection(&conn, &trips, &strings(&["c", "a", "a", "zz"])).unwrap(),
+            Some(2)
+        );
+        assert_eq!(
+            add_to_collection(&conn, &trips, &strings(&["b"])).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            ids(collection_wallpapers(&conn, &trips).unwrap()),
+            ["c", "a", "b"]
+        );
+
+        reorder_collection_items(&conn, &trips, &strings(&["b", "c"])).unwrap();
+        assert_eq!(
+            ids(collection_wallpapers(&conn, &trips).unwrap()),
+            ["b", "c", "a"]
+        );
+
+        let listed = list_collections(&conn).unwrap();
+        let names: Vec<&str> = listed.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Favorites", "Trips", "Cities"]);
+        assert_eq!(listed[1].item_count, 3);
+        assert_eq!(listed[1].cover.as_ref().unwrap().id, "b");
+
+        assert!(!set_collection_cover(&conn, &trips, Some("zz")).unwrap());
+        assert!(!set_collection_cover(&conn, &cities, Some("a")).unwrap());
+        assert!(set_collection_cover(&conn, &trips, Some("a")).unwrap());
+        assert_eq!(
+            get_collection(&conn, &trips)
+                .unwrap()
+                .unwrap()
+                .cover
+                .unwrap()
+                .id,
+            "a"
+        );
+        // A hidden or blocked cover isn't shown.
+        conn.execute("UPDATE wallpapers SET is_hidden = 1 WHERE id = 'a'", [])
+            .unwrap();
+        let collection = get_collection(&conn, &trips).unwrap().unwrap();
+        assert_eq!(collection.cover_wallpaper_id.as_deref(), Some("a"));
+        assert_eq!(collection.cover.unwrap().id, "b");
+        conn.execute("UPDATE wallpapers SET is_hidden = 0 WHERE id = 'a'", [])
+            .unwrap();
+
+        // A deleted cover falls back to the first item.
+        conn.execute("DELETE FROM wallpapers WHERE id = 'a'", [])
+            .unwrap();
+        let collection = get_collection(&conn, &trips).unwrap().unwrap();
+        assert_eq!(collection.cover_wallpaper_id, None);
+        assert_eq!(collection.cover.unwrap().id, "b");
+        assert_eq!(collection.item_count, 2);
+
+        assert_eq!(
+            remove_from_collection(&conn, &trips, &strings(&["b"])).unwrap(),
+            1
+        );
+        assert!(rename_collection(&conn, &trips, "Holidays").unwrap());
+        reorder_collections(&conn, &strings(&[&cities, FAVORITES_COLLECTION])).unwrap();
+        let names: Vec<String> = list_collections(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        // Built-in collections stay first.
+        assert_eq!(names, ["Favorites", "Cities", "Holidays"]);
+
+        assert!(delete_collection(&conn, &trips).unwrap());
+        assert_eq!(
+            add_to_collection(&conn, &trips, &strings(&["c"])).unwrap(),
+            None
+        );
+        assert!(!delete_collection(&conn, FAVORITES_COLLECTION).unwrap());
+        assert!(!rename_collection(&conn, FAVORITES_COLLECTION, "Likes").unwrap());
+        let items: u32 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM collection_items WHERE collection_id = ?1",
+                params![trips],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(items, 0);
+    }
+
+    #[test]
+    fn failed_reorders_leave_the_old_order() {
+        let conn = seeded();
+        let trips = create_collection(&conn, "Trips").unwrap();
+        add_to_collection(&conn, &trips, &strings(&["a", "b", "c"])).unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER fail_c BEFORE UPDATE OF position ON collection_items
+             WHEN NEW.wallpaper_id = 'c'
+             BEGIN SELECT RAISE(ABORT, 'forced failure'); END;",
+        )
+        .unwrap();
+
+        assert!(reorder_collection_items(&conn, &trips, &strings(&["b", "a", "c"])).is_err());
+        assert_eq!(
+            ids(collection_wallpapers(&conn, &trips).unwrap()),
+            ["a", "b", "c"]
+        );
+        assert!(get_collection(&conn, "missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn favorites_and_their_collection_stay_in_step() {
+        let conn = seeded();
+        add_favorite(&conn, "b").unwrap();
+        add_favorite(&conn, "a").unwrap();
+        assert_eq!(
+            ids(collection_wallpapers(&conn, FAVORITES_COLLECTION).unwrap()),
+            ["b", "a"]
+        );
+
+        add_to_collection(&conn, FAVORITES_COLLECTION, &strings(&["c"])).unwrap();
+        assert_eq!(ids(get_favorite_wallpapers(&conn).unwrap()).len(), 3);
+        assert!(collection_wallpapers(&conn, FAVORITES_COLLECTION)
+            .unwrap()
+            .iter()
+            .all(|w| w.is_favorite));
+
+        remove_favorite(&conn, "b").unwrap();
+        remove_from_collection(&conn, FAVORITES_COLLECTION, &strings(&["c"])).unwrap();
+        assert_eq!(
+            ids(collection_wallpapers(&conn, FAVORITES_COLLECTION).unwrap()),
+            ["a"]
+        );
+        assert_eq!(ids(get_favorite_wallpapers(&conn).unwrap()), ["a"]);
+    }
+}
@@ -0,0 +1,433 @@
+use crate::services::effects::parse_hex_color;
+use crate::services::palette::{to_hex, vividness, Palette};
+use serde::{Deserialize, Serialize};
+
+/// Colors sampled from the image to build a scheme from.
+pub const SCHEME_SOURCE_COLORS: usize = 16;
+
+/// A 16-color terminal scheme plus the special colors, all `#rrggbb`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColorScheme {
+    pub background: String,
+    pub foreground: String,
+    pub cursor: String,
+    /// `color0` to `color15`: background, six accents, a light grey, then
+    /// the bright versions of each.
+    pub colors: Vec<String>,
+}
+
+/// Built-in export targets.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BuiltinTemplate {
+    Xresources,
+    Kitty,
+    Alacritty,
+    Foot,
+    Json,
+    Css,
+}
+
+impl BuiltinTemplate {
+    pub const ALL: [BuiltinTemplate; 6] = [
+        BuiltinTemplate::Xresources,
+        BuiltinTemplate::Kitty,
+        BuiltinTemplate::Alacritty,
+        BuiltinTemplate::Foot,
+        BuiltinTemplate::Json,
+        BuiltinTemplate::Css,
+    ];
+
+    /// File name written when the target has no path of its own.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            BuiltinTemplate::Xresources => "colors.Xresources",
+            BuiltinTemplate::Kitty => "colors-kitty.conf",
+            BuiltinTemplate::Alacritty => "colors-alacritty.toml",
+            BuiltinTemplate::Foot => "colors-foot.ini",
+            BuiltinTemplate::Json => "colors.json",
+            BuiltinTemplate::Css => "colors.css",
+        }
+    }
+
+    /// Writes `scheme` in this target's format.
+    pub fn render(self, scheme: &ColorScheme, wallpaper: &str) -> String {
+        match self {
+            BuiltinTemplate::Xresources => render_template(XRESOURCES, scheme, wallpaper),
+            BuiltinTemplate::Kitty => render_template(KITTY, scheme, wallpaper),
+            BuiltinTemplate::Alacritty => render_template(ALACRITTY, scheme, wallpaper),
+            BuiltinTemplate::Foot => render_template(FOOT, scheme, wallpaper),
+            BuiltinTemplate::Css => render_template(CSS, scheme, wallpaper),
+            // Built with serde rather than a template so the wallpaper path
+            // is escaped properly.
+            BuiltinTemplate::Json => {
+                let colors: serde_json::Map<String, serde_json::Value> = scheme
+                    .colors
+                    .iter()
+                    .enumerate()
+                    .map(|(i, color)| (format!("color{i}"), color.clone().into()))
+                    .collect();
+                let json = serde_json::json!({
+                    "wallpaper": wallpaper,
+                    "special": {
+                        "background": scheme.background,
+                        "foreground": scheme.foreground,
+                        "cursor": scheme.cursor,
+                    },
+                    "colors": colors,
+                });
+                serde_json::to_string_pretty(&json).unwrap_or_default() + "\n"
+            }
+        }
+    }
+}
+
+const XRESOURCES: &str = "\
+*.background: {background}
+*.foreground: {foreground}
+*.cursorColor: {cursor}
+*.color0: {color0}
+*.color1: {color1}
+*.color2: {color2}
+*.color3: {color3}
+*.color4: {color4}
+*.color5: {color5}
+*.color6: {color6}
+*.color7: {color7}
+*.color8: {color8}
+*.color9: {color9}
+*.color10: {color10}
+*.color11: {color11}
+*.color12: {color12}
+*.color13: {color13}
+*.color14: {color14}
+*.color15: {color15}
+";
+
+const KITTY: &str = "\
+background {background}
+foreground {foreground}
+cursor {cursor}
+selection_background {color8}
+selection_foreground {foreground}
+color0 {color0}
+color1 {color1}
+color2 {color2}
+color3 {color3}
+color4 {color4}
+color5 {color5}
+color6 {color6}
+color7 {color7}
+color8 {color8}
+color9 {color9}
+color10 {color10}
+color11 {color11}
+color12 {color12}
+color13 {color13}
+color14 {color14}
+color15 {color15}
+";
+
+const ALACRITTY: &str = "\
+[colors.primary]
+background = \"{background}\"
+foreground = \"{foreground}\"
+
+[colors.cursor]
+cursor = \"{cursor}\"
+text = \"{background}\"
+
+[colors.normal]
+black = \"{color0}\"
+red = \"{color1}\"
+green = \"{color2}\"
+yellow = \"{color3}\"
+blue = \"{color4}\"
+magenta = \"{color5}\"
+cyan = \"{color6}\"
+white = \"{color7}\"
+
+[colors.bright]
+black = \"{color8}\"
+red = \"{color9}\"
+green = \"{color10}\"
+yellow = \"{color11}\"
+blue = \"{color12}\"
+magenta = \"{color13}\"
+cyan = \"{color14}\"
+white = \"{color15}\"
+";
+
+const FOOT: &str = "\
+[colors]
+background={background.strip}
+foreground={foreground.strip}
+regular0={color0.strip}
+regular1={color1.strip}
+regular2={color2.strip}
+regular3={color3.strip}
+regular4={color4.strip}
+regular5={color5.strip}
+regular6={color6.strip}
+regular7={color7.strip}
+bright0={color8.strip}
+bright1={color9.strip}
+bright2={color10.strip}
+bright3={color11.strip}
+bright4={color12.strip}
+bright5={color13.strip}
+bright6={color14.strip}
+bright7={color15.strip}
+
+[cursor]
+color={background.strip} {cursor.strip}
+";
+
+const CSS: &str = "\
+:root {
+    --wallpaper: url(\"{wallpaper}\");
+    --background: {background};
+    --foreground: {foreground};
+    --cursor: {cursor};
+    --color0: {color0};
+    --color1: {color1};
+    --color2: {color2};
+    --color3: {color3};
+    --color4: {color4};
+    --color5: {color5};
+    --color6: {color6};
+    --color7: {color7};
+    --color8: {color8};
+    --color9: {color9};
+    --color10: {color10};
+    --color11: {color11};
+    --color12: {color12};
+    --color13: {color13};
+    --color14: {color14};
+    --color15: {color15};
+}
+";
+
+/// Fills `{name}` placeholders in a user template. Names are `background`,
+/// `foreground`, `cursor`, `color0`–`color15` and `wallpaper`; colors take
+/// `.strip` for hex without `#` and `.rgb` for `r,g,b`. `{{` and `}}` are
+/// literal braces, and anything else in braces is left alone, so CSS and
+/// TOML templates need no escaping.
+pub fn render_template(template: &str, scheme: &ColorScheme, wallpaper: &str) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(index) = rest.find(['{', '}']) {
+        out.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        if rest.starts_with('{') {
+            if let Some(end) = rest.find('}') {
+                if let Some(value) = lookup(&rest[1..end], scheme, wallpaper) {
+                    out.push_str(&value);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        out.push_str(&rest[..1]);
+        rest = &rest[1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn lookup(name: &str, scheme: &ColorScheme, wallpaper: &str) -> Option<String> {
+    if name == "wallpaper" {
+        return Some(wallpaper.to_string());
+    }
+    let (base, modifier) = match name.split_once('.') {
+        Some((base, modifier)) => (base, Some(modifier)),
+        None => (name, None),
+    };
+    let color = match base {
+        "background" => &scheme.background,
+        "foreground" => &scheme.foreground,
+        "cursor" => &scheme.cursor,
+        _ => {
+            let index: usize = base.strip_prefix("color")?.parse().ok()?;
+            scheme.colors.get(index)?
+        }
+    };
+    match modifier {
+        None => Some(color.clone()),
+        Some("strip") => Some(color.trim_start_matches('#').to_string()),
+        Some("rgb") => {
+            let [r, g, b] = parse_hex_color(color)?;
+            Some(format!("{r},{g},{b}"))
+        }
+        Some(_) => None,
+    }
+}
+
+fn luma(rgb: [f32; 3]) -> f32 {
+    0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2]
+}
+
+fn mix(from: [f32; 3], to: [f32; 3], amount: f32) -> [f32; 3] {
+    let t = amount.clamp(0.0, 1.0);
+    [0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * t)
+}
+
+/// Mixes `rgb` towards white until its luma reaches `min_luma`.
+fn at_least(rgb: [f32; 3], min_luma: f32) -> [f32; 3] {
+    let current = luma(rgb);
+    if current >= min_luma {
+        return rgb;
+    }
+    mix(rgb, [255.0; 3], (min_luma - current) / (255.0 - current))
+}
+
+/// Scales `rgb` down until its luma is at most `max_luma`.
+fn at_most(rgb: [f32; 3], max_luma: f32) -> [f32; 3] {
+    let current = luma(rgb);
+    if current <= max_luma {
+        return rgb;
+    }
+    rgb.map(|c| c * max_luma / current)
+}
+
+fn hex(rgb: [f32; 3]) -> String {
+    to_hex(rgb.map(|c| c.round().clamp(0.0, 255.0) as u8))
+}
+
+fn hue(rgb: [f32; 3]) -> f32 {
+    let [r, g, b] = rgb;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max == min {
+        return 0.0;
+    }
+    let h = if max == r {
+        (g - b) / (max - min)
+    } else if max == g {
+        2.0 + (b - r) / (max - min)
+    } else {
+        4.0 + (r - g) / (max - min)
+    };
+    (h * 60.0).rem_euclid(360.0)
+}
+
+/// Builds a dark terminal scheme from an image palette: the darkest color
+/// becomes the background, the lightest the foreground, and the most vivid
+/// the six accents, ordered by hue and kept readable on the background.
+pub fn scheme_from_palette(palette: &Palette) -> ColorScheme {
+    let mut colors: Vec<[f32; 3]> = palette
+        .colors
+        .iter()
+        .filter_map(|swatch| parse_hex_color(&swatch.color))
+        .collect();
+    if colors.is_empty() {
+        colors.push([128.0; 3]);
+    }
+    colors.sort_by(|a, b| luma(*a).total_cmp(&luma(*b)));
+
+    let background = at_most(colors[0], 24.0);
+    let foreground = at_least(colors[colors.len() - 1], 220.0);
+
+    let mut by_vividness = colors.clone();
+    by_vividness.sort_by(|a, b| {
+        let score = |rgb: &[f32; 3]| vividness(rgb.map(|c| c as u8));
+        score(b).total_cmp(&score(a))
+    });
+    let mut accents: Vec<[f32; 3]> = by_vividness.iter().cycle().take(6).copied().collect();
+    accents.sort_by(|a, b| hue(*a).total_cmp(&hue(*b)));
+    let accents: Vec<[f32; 3]> = accents.into_iter().map(|c| at_least(c, 110.0)).collect();
+
+    let mut scheme = vec![background];
+    scheme.extend(&accents);
+    scheme.push(mix(foreground, background, 0.2));
+    scheme.push(mix(background, foreground, 0.3));
+    scheme.extend(accents.iter().map(|&c| mix(c, [255.0; 3], 0.2)));
+    scheme.push(foreground);
+
+    ColorScheme {
+        background: hex(background),
+        foreground: hex(foreground),
+        cursor: hex(foreground),
+        colors: scheme.into_iter().map(hex).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::palette::Swatch;
+
+    fn scheme() -> ColorScheme {
+        let palette = Palette {
+            dominant: "#203040".into(),
+            accent: "#f07814".into(),
+            colors: ["#203040", "#f07814", "#e0e0d0", "#2080c0"]
+                .iter()
+                .map(|c| Swatch {
+                    color: c.to_string(),
+                    population: 0.25,
+                })
+                .collect(),
+        };
+        scheme_from_palette(&palette)
+    }
+
+    #[test]
+    fn schemes_are_dark_with_readable_colors() {
+        let scheme = scheme();
+        assert_eq!(scheme.colors.len(), 16);
+        assert_eq!(scheme.colors[0], scheme.background);
+        assert_eq!(scheme.colors[15], scheme.foreground);
+
+        let luma_of = |hex: &str| luma(parse_hex_color(hex).unwrap());
+        assert!(luma_of(&scheme.background) <= 24.5);
+        assert!(luma_of(&scheme.foreground) >= 219.5);
+        for accent in &scheme.colors[1..7] {
+            assert!(luma_of(accent) >= 109.5, "{accent} is too dark");
+        }
+    }
+
+    #[test]
+    fn templates_fill_known_names_only() {
+        let scheme = scheme();
+        let out = render_template(
+            "a {{b}} {color1} {color1.strip} {background.rgb} {wallpaper} {nope} {color99} .x {\n}",
+            &scheme,
+            "/w.jpg",
+        );
+        let [r, g, b] = parse_hex_color(&scheme.background).unwrap();
+        assert_eq!(
+            out,
+            format!(
+                "a {{b}} {} {} {},{},{} /w.jpg {{nope}} {{color99}} .x {{\n}}",
+                scheme.colors[1],
+                &scheme.colors[1][1..],
+                r,
+                g,
+                b
+            )
+        );
+    }
+
+    #[test]
+    fn builtins_render_every_placeholder() {
+        let scheme = scheme();
+        for builtin in BuiltinTemplate::ALL {
+            let out = builtin.render(&scheme, "C:\\walls\\a.jpg");
+            assert!(!out.contains("{color"), "{builtin:?} left a placeholder");
+            assert!(out.contains(&scheme.colors[15][1..]));
+        }
+        let json: serde_json::Value =
+            serde_json::from_str(&BuiltinTemplate::Json.render(&scheme, "C:\\walls\\a.jpg"))
+                .unwrap();
+        assert_eq!(json["wallpaper"], "C:\\walls\\a.jpg");
+        assert_eq!(json["colors"]["color15"], scheme.colors[15]);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::services::color_scheme::{
+    render_template, scheme_from_palette, BuiltinTemplate, ColorScheme, SCHEME_SOURCE_COLORS,
+};
+use crate::services::db_services::{set_setting, DbPool};
+use crate::services::image_pipeline::decode;
+use crate::services::palette::extract_palette;
+use crate::services::wallpaper_service::APP_INFO;
+use app_dirs2::{app_dir, AppDataType};
+use rusqlite::{Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, State};
+
+/// Where a target's text comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateRef {
+    Builtin(BuiltinTemplate),
+    /// A user template file; see `render_template` for its placeholders.
+    File(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SchemeTarget {
+    pub template: TemplateRef,
+    /// Where to write it. `~` is the home directory and relative paths are
+    /// inside the default schemes directory; built-in templates default to
+    /// their usual file name there.
+    #[serde(default)]
+    pub output: Option<String>,
+}
+
+/// The `colorSchemes` setting.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SchemeSettings {
+    pub enabled: bool,
+    pub targets: Vec<SchemeTarget>,
+}
+
+impl Default for SchemeSettings {
+    fn default() -> Self {
+        SchemeSettings {
+            enabled: false,
+            targets: BuiltinTemplate::ALL
+                .into_iter()
+                .map(|builtin| SchemeTarget {
+                    template: TemplateRef::Builtin(builtin),
+                    output: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Outcome of one export, sent with the `color-scheme-exported` event.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct SchemeExport {
+    pub scheme: Option<ColorScheme>,
+    /// Paths written.
+    pub written: Vec<String>,
+    /// One message per target that could not be written.
+    pub failed: Vec<String>,
+}
+
+pub fn get_scheme_settings(conn: &Connection) -> SqlResult<SchemeSettings> {
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = 'colorSchemes'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(json
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+pub fn set_scheme_settings(conn: &Connection, settings: &SchemeSettings) -> SqlResult<()> {
+    let json = serde_json::to_string(settings).unwrap_or_else(|_| "{}".to_string());
+    set_setting(conn, "colorSchemes", &json, "appearance")
+}
+
+pub fn default_scheme_dir() -> Result<PathBuf, String> {
+    app_dir(AppDataType::UserCache, &APP_INFO, "schemes")
+        .map_err(|e| format!("Failed to resolve schemes directory: {e}"))
+}
+
+fn output_path(target: &SchemeTarget, default_dir: &Path) -> Result<PathBuf, String> {
+    let output = match (&target.output, &target.template) {
+        (Some(output), _) if !output.trim().is_empty() => output.trim(),
+        (_, TemplateRef::Builtin(builtin)) => return Ok(default_dir.join(builtin.file_name())),
+        (_, TemplateRef::File(template)) => {
+            return Err(format!("No output path for template {template}"))
+        }
+    };
+    let path = match output.strip_prefix("~/").or(output.strip_prefix("~\\")) {
+        Some(rest) => dirs::home_dir()
+            .ok_or("Failed to resolve home directory")?
+            .join(rest),
+        None => PathBuf::from(output),
+    };
+    Ok(if path.is_absolute() {
+        path
+    } else {
+        default_dir.join(path)
+    })
+}
+
+fn export_target(
+    target: &SchemeTarget,
+    scheme: &ColorScheme,
+    wallpaper: &str,
+    default_dir: &Path,
+) -> Result<PathBuf, String> {
+    let contents = match &target.template {
+        TemplateRef::Builtin(builtin) => builtin.render(scheme, wallpaper),
+        TemplateRef::File(template) => {
+            let template = fs::read_to_string(template)
+                .map_err(|e| format!("Failed to read template {template}: {e}"))?;
+            render_template(&template, scheme, wallpaper)
+        }
+    };
+    let path = output_path(target, default_dir)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(&path, contents).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+/// Writes `scheme` to every target. A failing target doesn't stop the rest.
+pub fn export_color_scheme(
+    settings: &SchemeSettings,
+    scheme: &ColorScheme,
+    wallpaper: &Path,
+    default_dir: &Path,
+) -> SchemeExport {
+    let wallpaper = wallpaper.to_string_lossy();
+    let mut export = SchemeExport {
+        scheme: Some(scheme.clone()),
+        ..Default::default()
+    };
+    for target in &settings.targets {
+        match export_target(target, scheme, &wallpaper, default_dir) {
+            Ok(path) => export.written.push(path.to_string_lossy().into_owned()),
+            Err(e) => export.failed.push(e),
+        }
+    }
+    export
+}
+
+/// Exports a scheme for the wallpaper just applied from `wallpaper_path`,
+/// if schemes are on, and reports it with a `color-scheme-exported` event.
+pub fn export_applied_scheme(app: &AppHandle, conn: &Connection, wallpaper_path: &Path) {
+    let Ok(settings) = get_scheme_settings(conn) else {
+        return;
+    };
+    if !settings.enabled || settings.targets.is_empty() {
+        return;
+    }
+
+    let result = default_scheme_dir().and_then(|dir| {
+        let img = decode(&fs::read(wallpaper_path).map_err(|e| e.to_string())?)?;
+        let palette =
+            extract_palette(&img, SCHEME_SOURCE_COLORS).ok_or("Wallpaper has no visible pixels")?;
+        let scheme = scheme_from_palette(&palette);
+        Ok(export_color_scheme(
+            &settings,
+            &scheme,
+            wallpaper_path,
+            &dir,
+        ))
+    });
+    let export = result.unwrap_or_else(|e| SchemeExport {
+        failed: vec![e],
+        ..Default::default()
+    });
+    for failure in &export.failed {
+        eprintln!("Color scheme export failed: {}", failure);
+    }
+    let _ = app.emit("color-scheme-exported", &export);
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn get_color_scheme_settings_command(
+    db: State<'_, DbPool>,
+) -> Result<SchemeSettings, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    get_scheme_settings(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_color_scheme_settings_command(
+    db: State<'_, DbPool>,
+    settings: SchemeSettings,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    set_scheme_settings(&conn, &settings).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::migrations::migrate;
+
+    fn scheme() -> ColorScheme {
+        ColorScheme {
+            background: "#101010".into(),
+            foreground: "#f0f0f0".into(),
+            cursor: "#f0f0f0".into(),
+            colors: (0..16).map(|i| format!("#0000{:02x}", i * 16)).collect(),
+        }
+    }
+
+    #[test]
+    fn settings_default_to_every_builtin() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        let settings = get_scheme_settings(&conn).unwrap();
+        assert!(!settings.enabled);
+        assert_eq!(settings.targets.len(), BuiltinTemplate::ALL.len());
+
+        let parsed: SchemeSettings = serde_json::from_str(
+            r#"{"enabled":true,"targets":[{"template":{"builtin":"kitty"}},
+                {"template":{"file":"/t.txt"},"output":"out.txt"}]}"#,
+        )
+        .unwrap();
+        set_scheme_settings(&conn, &parsed).unwrap();
+        assert_eq!(get_scheme_settings(&conn).unwrap(), parsed);
+    }
+
+    #[test]
+    fn exports_builtin_and_user_templates() {
+        let dir = std::env::temp_dir().join(format!("wallpaper-schemes-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("template.txt");
+        fs::write(&template, "bg={background.strip} wp={wallpaper}\n").unwrap();
+
+        let settings = SchemeSettings {
+            enabled: true,
+            targets: vec![
+                SchemeTarget {
+                    template: TemplateRef::Builtin(BuiltinTemplate::Kitty),
+                    output: None,
+                },
+                SchemeTarget {
+                    template: TemplateRef::File(template.to_string_lossy().into_owned()),
+                    output: Some("nested/custom.conf".into()),
+                },
+                SchemeTarget {
+                    template: TemplateRef::File(template.to_string_lossy().into_owned()),
+                    output: None,
+                },
+            ],
+        };
+        let export = export_color_scheme(&settings, &scheme(), Path::new("/w.png"), &dir);
+        assert_eq!(export.written.len(), 2);
+        assert_eq!(export.failed.len(), 1);
+
+        let kitty = fs::read_to_string(dir.join("colors-kitty.conf")).unwrap();
+        assert!(kitty.starts_with("background #101010\n"));
+        assert_eq!(
+            fs::read_to_string(dir.join("nested/custom.conf")).unwrap(),
+            "bg=101010 wp=/w.png\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use crate::services::db_services::{DbPool, FocalPoint, Wallpaper};
+use crate::services::image_pipeline::{CropRect, Focus};
+use crate::services::variant_service::Resolution;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use tauri::State;
+
+/// How a stored crop was placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CropMethod {
+    FocalPoint,
+    Saliency,
+}
+
+impl CropMethod {
+    fn as_str(self) -> &'static str {
+        match self {
+            CropMethod::FocalPoint => "focal_point",
+            CropMethod::Saliency => "saliency",
+        }
+    }
+}
+
+/// Key crops are stored under: the reduced aspect ratio, e.g. `16:9` for
+/// both 1920x1080 and 3840x2160.
+pub fn aspect_key(target: Resolution) -> String {
+    fn gcd(a: u32, b: u32) -> u32 {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+    let divisor = gcd(target.width, target.height).max(1);
+    format!("{}:{}", target.width / divisor, target.height / divisor)
+}
+
+pub fn get_wallpaper_crop(
+    conn: &Connection,
+    wallpaper_id: &str,
+    aspect: &str,
+) -> SqlResult<Option<CropRect>> {
+    conn.query_row(
+        "SELECT x, y, width, height FROM wallpaper_crops WHERE wallpaper_id = ?1 AND aspect = ?2",
+        params![wallpaper_id, aspect],
+        |row| {
+            Ok(CropRect {
+                x: row.get(0)?,
+                y: row.get(1)?,
+                width: row.get(2)?,
+                height: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+pub fn save_wallpaper_crop(
+    conn: &Connection,
+    wallpaper_id: &str,
+    aspect: &str,
+    rect: CropRect,
+    method: CropMethod,
+) -> SqlResult<()> {
+    conn.execute(
+        r#"
+        INSERT INTO wallpaper_crops (wallpaper_id, aspect, x, y, width, height, method)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        ON CONFLICT(wallpaper_id, aspect) DO UPDATE SET
+            x = excluded.x,
+            y = excluded.y,
+            width = excluded.width,
+            height = excluded.height,
+            method = excluded.method,
+            created_at = CURRENT_TIMESTAMP
+        "#,
+        params![
+            wallpaper_id,
+            aspect,
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            method.as_str()
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn clear_wallpaper_crops(conn: &Connection, wallpaper_id: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM wallpaper_crops WHERE wallpaper_id = ?1",
+        params![wallpaper_id],
+    )?;
+    Ok(())
+}
+
+/// Sets the user's focal point for a wallpaper, clamped into the image, or
+/// clears it with `None` so the API's point (if any) applies again from the
+/// next sync. Stored crops are dropped either way. Returns `false` if there
+/// is no such wallpaper.
+pub fn set_focal_point(
+    conn: &Connection,
+    wallpaper_id: &str,
+    point: Option<FocalPoint>,
+) -> SqlResult<bool> {
+    let point = point.map(|p| FocalPoint {
+        x: p.x.clamp(0.0, 1.0),
+        y: p.y.clamp(0.0, 1.0),
+    });
+    let updated = conn.execute(
+        "UPDATE wallpapers SET focal_x = ?2, focal_y = ?3, focal_source = ?4 WHERE id = ?1",
+        params![
+            wallpaper_id,
+            point.map(|p| p.x),
+            point.map(|p| p.y),
+            point.map(|_| "user")
+        ],
+    )?;
+    clear_wallpaper_crops(conn, wallpaper_id)?;
+    Ok(updated > 0)
+}
+
+/// Where to crop a stored wallpaper for one target, and what to remember
+/// once the crop is chosen.
+#[derive(Debug)]
+pub struct CropPlan {
+    pub focus: Focus,
+    wallpaper_id: String,
+    aspect: String,
+    /// `None` when reusing a stored crop.
+    method: Option<CropMethod>,
+}
+
+impl CropPlan {
+    /// Stores the crop the pipeline chose so the next apply for this aspect
+    /// ratio reproduces it.
+    pub fn remember(&self, conn: &Connection, chosen: Option<CropRect>) -> SqlResult<()> {
+        match (self.method, chosen) {
+            (Some(method), Some(rect)) => {
+                save_wallpaper_crop(conn, &self.wallpaper_id, &self.aspect, rect, method)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Plans the crop of `wallpaper` for `target`: the crop stored for that
+/// aspect ratio if there is one, otherwise around its focal point or,
+/// failing that, its most detailed region.
+pub fn plan_crop(
+    conn: &Connection,
+    wallpaper: &Wallpaper,
+    target: Resolution,
+) -> SqlResult<CropPlan> {
+    let aspect = aspect_key(target);
+    let (focus, method) = match get_wallpaper_crop(conn, &wallpaper.id, &aspect)? {
+        Some(rect) => (Focus::Region(rect), None),
+        None => match wallpaper.focal_point {
+            Some(FocalPoint { x, y }) => (Focus::Point { x, y }, Some(CropMethod::FocalPoint)),
+            None => (Focus::Auto, Some(CropMethod::Saliency)),
+        },
+    };
+    Ok(CropPlan {
+        focus,
+        wallpaper_id: wallpaper.id.clone(),
+        aspect,
+        method,
+    })
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn set_focal_point_command(
+    db: State<'_, DbPool>,
+    id: String,
+    focal_point: Option<FocalPoint>,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    if set_focal_point(&conn, &id, focal_point).map_err(|e| e.to_string())? {
+        Ok(())
+    } else {
+        Err(format!("No wallpaper with id {}", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::db_services::{
+        add_or_update_wallpaper, get_wallpaper_by_url, WallpaperSource,
+    };
+    use crate::services::migrations::migrate;
+
+    fn remote(focal_point: Option<FocalPoint>) -> Wallpaper {
+        Wallpaper {
+            id: "local".into(),
+            mongo_id: Some("m1".into()),
+            title: "Dunes".into(),
+            url: "https://example.com/dunes.jpg".into(),
+            thumbnail: String::new(),
+            width: 1920,
+            height: 1080,
+            tags: String::new(),
+            is_ai_generated: false,
+            is_favorite: false,
+            is_available: true,
+            popularity: 0,
+            variants: Vec::new(),
+            focal_point,
+            dominant_color: None,
+            accent_color: None,
+            source: WallpaperSource::Remote,
+            file_path: None,
+            rating: None,
+            is_hidden: false,
+        }
+    }
+
+    fn stored(conn: &Connection) -> Wallpaper {
+        get_wallpaper_by_url(conn, "https://example.com/dunes.jpg")
+            .unwrap()
+            .unwrap()
+    }
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        conn
+    }
+
+    const RECT: CropRect = CropRect {
+        x: 0.1,
+        y: 0.0,
+        width: 0.5,
+        height: 1.0,
+    };
+
+    #[test]
+    fn aspect_keys_reduce() {
+        let key = |s| aspect_key(Resolution::parse(s).unwrap());
+        assert_eq!(key("1920x1080"), "16:9");
+        assert_eq!(key("3840x2160"), "16:9");
+        assert_eq!(key("1080x1920"), "9:16");
+    }
+
+    #[test]
+    fn stored_crops_are_reused_until_the_focus_changes() {
+        let conn = setup();
+        add_or_update_wallpaper(&conn, &remote(None), None).unwrap();
+        let target = Resolution::parse("1920x1080").unwrap();
+
+        let plan = plan_crop(&conn, &stored(&conn), target).unwrap();
+        assert_eq!(plan.focus, Focus::Auto);
+        plan.remember(&conn, Some(RECT)).unwrap();
+
+        let plan = plan_crop(&conn, &stored(&conn), target).unwrap();
+        assert_eq!(plan.focus, Focus::Region(RECT));
+
+        let api_point = FocalPoint { x: 0.8, y: 0.4 };
+        add_or_update_wallpaper(&conn, &remote(Some(api_point)), None).unwrap();
+        let plan = plan_crop(&conn, &stored(&conn), target).unwrap();
+        assert_eq!(plan.focus, Focus::Point { x: 0.8, y: 0.4 });
+        assert_eq!(plan.method, Some(CropMethod::FocalPoint));
+    }
+
+    #[test]
+    fn sync_keeps_user_focal_points() {
+        let conn = setup();
+        add_or_update_wallpaper(&conn, &remote(None), None).unwrap();
+        assert!(set_focal_point(&conn, "local", Some(FocalPoint { x: 1.5, y: 0.2 })).unwrap());
+        save_wallpaper_crop(&conn, "local", "16:9", RECT, CropMethod::FocalPoint).unwrap();
+
+        let api_point = FocalPoint { x: 0.5, y: 0.5 };
+        add_or_update_wallpaper(&conn, &remote(Some(api_point)), None).unwrap();
+        assert_eq!(
+            stored(&conn).focal_point,
+            Some(FocalPoint { x: 1.0, y: 0.2 })
+        );
+        assert_eq!(
+            get_wallpaper_crop(&conn, "local", "16:9").unwrap(),
+            Some(RECT)
+        );
+
+        assert!(set_focal_point(&conn, "local", None).unwrap());
+        assert_eq!(get_wallpaper_crop(&conn, "local", "16:9").unwrap(), None);
+        add_or_update_wallpaper(&conn, &remote(Some(api_point)), None).unwrap();
+        assert_eq!(stored(&conn).focal_point, Some(api_point));
+        assert!(!set_focal_point(&conn, "missing", None).unwrap());
+    }
+}
@@ -1,54 +1,68 @@
-use rusqlite::{params, Connection, Error as SqlError, OptionalExtension, Result as SqlResult};
+use crate::services::crop_service::clear_wallpaper_crops;
+use crate::services::migrations;
+use crate::services::query_service::{
+    query_wallpapers, WallpaperFilter, WallpaperPage, WallpaperSort, MAX_PAGE_SIZE,
+};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, types::Value, Connection, OptionalExtension, Result as SqlResult, Row};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, State};
 use uuid::Uuid;
 
 pub type Settings = HashMap<String, String>;
 
+pub const DEFAULT_SYNC_INTERVAL_MS: u64 = 60 * 60 * 1000;
+
+/// `preferredResolution` value that follows the primary monitor.
+pub const AUTO_RESOLUTION: &str = "auto";
+
+/// Columns for `wallpaper_from_row` over `w` (wallpapers). `favorites` may
+/// hold a wallpaper more than once, so it is probed rather than joined.
+pub(crate) const WALLPAPER_COLUMNS: &str = r#"
+    w.*, EXISTS (SELECT 1 FROM favorites f WHERE f.wallpaper_id = w.id) AS is_favorite
+"#;
+
+/// SQL condition on `w` (wallpapers) leaving out those on the blocklist.
+pub(crate) const NOT_BLOCKED: &str = "NOT EXISTS (
+    SELECT 1 FROM blocklist b
+    WHERE b.wallpaper_id = w.id OR b.mongo_id = w.mongo_id OR b.file_path = w.file_path
+)";
+
 pub fn get_db_path() -> PathBuf {
     dirs::data_dir()
         .unwrap_or_else(|| PathBuf::from("."))
         .join("wallpaper_app/localdb.sqlite")
 }
 
-pub fn get_connection() -> SqlResult<Connection> {
-    let path = get_db_path();
+/// Pooled SQLite connections, held in Tauri state.
+pub type DbPool = Pool<SqliteConnectionManager>;
+
+/// Opens the pool for the database at `path`. WAL mode and the busy timeout
+/// are applied to every pooled connection; schema migrations run once here
+/// rather than on every checkout, backing the old file up first.
+pub fn init_pool(path: &Path) -> Result<DbPool, String> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)
-            .map_err(|e| SqlError::ToSqlConversionFailure(Box::new(e)))?;
+            .map_err(|e| format!("Failed to create database directory: {}", e))?;
     }
-    let conn = Connection::open(path)?;
-    conn.execute_batch(
-        "
-        CREATE TABLE IF NOT EXISTS wallpapers (
-            id TEXT PRIMARY KEY,
-            mongo_id TEXT UNIQUE,
-            title TEXT,
-            url TEXT,
-            thumbnail TEXT,
-            width INTEGER,
-            height INTEGER,
-            tags TEXT,
-            is_ai_generated BOOLEAN DEFAULT 0,
-            created_at TEXT DEFAULT CURRENT_TIMESTAMP
-        );
 
-        CREATE TABLE IF NOT EXISTS favorites (
-            id TEXT PRIMARY KEY,
-            wallpaper_id TEXT,
-            added_at TEXT DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (wallpaper_id) REFERENCES wallpapers(id) ON DELETE CASCADE
-        );
+    let manager = SqliteConnectionManager::file(path).with_init(configure_connection);
+    let pool = Pool::new(manager).map_err(|e| format!("Failed to open database: {}", e))?;
 
-        CREATE TABLE IF NOT EXISTS settings (
-            key TEXT PRIMARY KEY,
-            value TEXT,
-            category TEXT
-        );
-        ",
-    )?;
-    Ok(conn)
+    let mut conn = pool.get().map_err(|e| e.to_string())?;
+    migrations::migrate(&mut conn, Some(&path.with_extension("sqlite.bak")))?;
+
+    Ok(pool)
+}
+
+/// Per-connection pragmas.
+pub fn configure_connection(conn: &mut Connection) -> SqlResult<()> {
+    conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+    conn.busy_timeout(Duration::from_secs(5))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -63,6 +77,125 @@ pub struct Wallpaper {
     pub tags: String,
     pub is_ai_generated: bool,
     pub is_favorite: bool,
+    pub is_available: bool,
+    pub popularity: i64,
+    /// Alternative renditions offered by the API.
+    #[serde(default)]
+    pub variants: Vec<ImageVariant>,
+    /// Point to keep in frame when cropping, set by the user or the API.
+    #[serde(default)]
+    pub focal_point: Option<FocalPoint>,
+    /// `#rrggbb`, once a palette has been extracted from a cached copy.
+    #[serde(default)]
+    pub dominant_color: Option<String>,
+    #[serde(default)]
+    pub accent_color: Option<String>,
+    #[serde(default)]
+    pub source: WallpaperSource,
+    /// Where an imported wallpaper lives on disk.
+    #[serde(default)]
+    pub file_path: Option<String>,
+    /// 1 to 5 stars, once the user has rated it.
+    #[serde(default)]
+    pub rating: Option<u8>,
+    /// Hidden wallpapers are left out of listings and rotations until shown
+    /// again.
+    #[serde(default)]
+    pub is_hidden: bool,
+}
+
+/// Where a wallpaper came from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WallpaperSource {
+    /// The wallpaper API.
+    #[default]
+    Remote,
+    /// A file imported from disk.
+    Local,
+}
+
+impl WallpaperSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WallpaperSource::Remote => "remote",
+            WallpaperSource::Local => "local",
+        }
+    }
+}
+
+/// A point in an image as fractions of its width and height, from the
+/// top-left corner.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FocalPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+fn focal_point_from(x: Option<f64>, y: Option<f64>) -> Option<FocalPoint> {
+    Some(FocalPoint { x: x?, y: y? })
+}
+
+/// Which rendition of a wallpaper an [`ImageVariant`] is.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariantKind {
+    #[serde(rename = "thumbnail")]
+    Thumbnail,
+    #[serde(rename = "1080p")]
+    Hd,
+    #[serde(rename = "1440p")]
+    Qhd,
+    #[serde(rename = "4k")]
+    Uhd,
+    #[serde(rename = "original")]
+    Original,
+}
+
+/// One downloadable rendition of a wallpaper.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImageVariant {
+    pub kind: VariantKind,
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Whether an upsert created a new row or refreshed an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    Added,
+    Updated,
+    /// Left alone because the wallpaper is on the blocklist.
+    Blocked,
+}
+
+/// Maps a `wallpapers` row joined with an `is_favorite` column.
+pub(crate) fn wallpaper_from_row(row: &Row) -> SqlResult<Wallpaper> {
+    Ok(Wallpaper {
+        id: row.get("id")?,
+        mongo_id: row.get("mongo_id")?,
+        title: row.get("title")?,
+        url: row.get("url")?,
+        thumbnail: row.get("thumbnail")?,
+        width: row.get("width")?,
+        height: row.get("height")?,
+        tags: row.get("tags")?,
+        is_ai_generated: row.get("is_ai_generated")?,
+        is_favorite: row.get("is_favorite")?,
+        is_available: row.get("is_available")?,
+        popularity: row.get("popularity")?,
+        variants: serde_json::from_str(&row.get::<_, String>("variants")?).unwrap_or_default(),
+        focal_point: focal_point_from(row.get("focal_x")?, row.get("focal_y")?),
+        dominant_color: row.get("dominant_color")?,
+        accent_color: row.get("accent_color")?,
+        source: match row.get::<_, String>("source")?.as_str() {
+            "local" => WallpaperSource::Local,
+            _ => WallpaperSource::Remote,
+        },
+        file_path: row.get("file_path")?,
+        rating: row.get("rating")?,
+        is_hidden: row.get("is_hidden")?,
+    })
 }
 
 #[derive(Serialize, Deserialize)]
@@ -75,16 +208,61 @@ pub struct WallpaperSettings {
     pub auto_start: bool,
     pub experimental: bool,
     pub language: String,
+    /// `"WIDTHxHEIGHT"`, or `"auto"` to follow the primary monitor.
+    pub preferred_resolution: String,
+}
+
+#[derive(Serialize)]
+pub struct TagCount {
+    pub name: String,
+    pub count: u32,
+}
+
+/// How a multi-tag filter combines its tags.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    All,
+    Any,
 }
 
 // Basic wallpaper storage
-pub fn add_or_update_wallpaper(wp: &Wallpaper) -> SqlResult<()> {
-    let conn = get_connection()?;
-    conn.execute(
+
+/// Inserts or refreshes a wallpaper keyed by `mongo_id`, recording the
+/// server-side `updated_at`. A wallpaper that reappears on the server is made
+/// available again. Blocked wallpapers are never written, so a sync can't
+/// bring one back. Takes the caller's connection so sync can batch a whole
+/// page into one transaction.
+pub fn add_or_update_wallpaper(
+    conn: &Connection,
+    wp: &Wallpaper,
+    updated_at: Option<&str>,
+) -> SqlResult<UpsertOutcome> {
+    if is_blocked(conn, wp.mongo_id.as_deref(), None)? {
+        return Ok(UpsertOutcome::Blocked);
+    }
+
+    let existing: Option<(String, Option<FocalPoint>, bool)> = conn
+        .prepare_cached(
+            "SELECT id, focal_x, focal_y, focal_source = 'user' FROM wallpapers WHERE mongo_id = ?1",
+        )?
+        .query_row(params![wp.mongo_id], |row| {
+            Ok((
+                row.get(0)?,
+                focal_point_from(row.get(1)?, row.get(2)?),
+                row.get::<_, Option<bool>>(3)?.unwrap_or(false),
+            ))
+        })
+        .optional()?;
+    let existing_id = existing.as_ref().map(|(id, _, _)| id.clone());
+
+    conn.prepare_cached(
         r#"
         INSERT INTO wallpapers (
-            id, mongo_id, title, url, thumbnail, width, height, tags, is_ai_generated
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            id, mongo_id, title, url, thumbnail, width, height, tags, is_ai_generated,
+            popularity, variants, focal_x, focal_y, focal_source, is_available, updated_at
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, 1, ?15)
         ON CONFLICT(mongo_id) DO UPDATE SET
             title = excluded.title,
             url = excluded.url,
@@ -92,31 +270,91 @@ pub fn add_or_update_wallpaper(wp: &Wallpaper) -> SqlResult<()> {
             width = excluded.width,
             height = excluded.height,
             tags = excluded.tags,
-            is_ai_generated = excluded.is_ai_generated
+            is_ai_generated = excluded.is_ai_generated,
+            popularity = excluded.popularity,
+            variants = excluded.variants,
+            focal_x = CASE WHEN focal_source = 'user' THEN focal_x ELSE excluded.focal_x END,
+            focal_y = CASE WHEN focal_source = 'user' THEN focal_y ELSE excluded.focal_y END,
+            focal_source = CASE
+                WHEN focal_source = 'user' THEN 'user' ELSE excluded.focal_source
+            END,
+            is_available = 1,
+            updated_at = excluded.updated_at
         "#,
-        params![
-            wp.id,
-            wp.mongo_id,
-            wp.title,
-            wp.url,
-            wp.thumbnail,
-            wp.width,
-            wp.height,
-            wp.tags,
-            wp.is_ai_generated
-        ],
-    )?;
-    Ok(())
+    )?
+    .execute(params![
+        wp.id,
+        wp.mongo_id,
+        wp.title,
+        wp.url,
+        wp.thumbnail,
+        wp.width,
+        wp.height,
+        wp.tags,
+        wp.is_ai_generated,
+        wp.popularity,
+        serde_json::to_string(&wp.variants).unwrap_or_else(|_| "[]".to_string()),
+        wp.focal_point.map(|p| p.x),
+        wp.focal_point.map(|p| p.y),
+        wp.focal_point.map(|_| "api"),
+        updated_at
+    ])?;
+
+    // The conflict branch keeps the original local id.
+    let id = existing_id.as_deref().unwrap_or(&wp.id);
+    set_wallpaper_tags(conn, id, &wp.tags)?;
+
+    // Crops placed around an old API focal point no longer apply.
+    if let Some((_, old_focal, false)) = &existing {
+        if *old_focal != wp.focal_point {
+            clear_wallpaper_crops(conn, id)?;
+        }
+    }
+
+    Ok(if existing_id.is_some() {
+        UpsertOutcome::Updated
+    } else {
+        UpsertOutcome::Added
+    })
+}
+
+/// Applies a server-side tombstone. Favorited wallpapers, and those in a
+/// collection, are kept but marked unavailable so they don't silently vanish
+/// from the user's favorites; all others are deleted. Returns `false` if
+/// nothing was stored for `mongo_id`.
+pub fn remove_wallpaper_by_mongo_id(conn: &Connection, mongo_id: &str) -> SqlResult<bool> {
+    let marked = conn
+        .prepare_cached(
+            r#"
+            UPDATE wallpapers SET is_available = 0
+            WHERE mongo_id = ?1
+              AND is_available = 1
+              AND id IN (
+                  SELECT wallpaper_id FROM favorites
+                  UNION SELECT wallpaper_id FROM collection_items
+              )
+            "#,
+        )?
+        .execute(params![mongo_id])?;
+    let deleted = conn
+        .prepare_cached(
+            r#"
+            DELETE FROM wallpapers
+            WHERE mongo_id = ?1
+              AND id NOT IN (SELECT wallpaper_id FROM favorites WHERE wallpaper_id IS NOT NULL)
+              AND id NOT IN (SELECT wallpaper_id FROM collection_items)
+            "#,
+        )?
+        .execute(params![mongo_id])?;
+    Ok(marked + deleted > 0)
 }
 
-pub fn clear_wallpapers() -> SqlResult<()> {
-    let conn = get_connection()?;
+pub fn clear_wallpapers(conn: &Connection) -> SqlResult<()> {
     conn.execute("DELETE FROM wallpapers", [])?;
     Ok(())
 }
 
-pub fn add_favorite(wallpaper_id: &str) -> SqlResult<()> {
-    let conn = get_connection()?;
+pub fn add_favorite(conn: &Connection, wallpaper_id: &str) -> SqlResult<()> {
     let uuid = Uuid::new_v4().to_string();
     conn.execute(
         "INSERT OR REPLACE INTO favorites (id, wallpaper_id) VALUES (?1, ?2)",
@@ -125,8 +363,7 @@ pub fn add_favorite(wallpaper_id: &str) -> SqlResult<()> {
     Ok(())
 }
 
-pub fn remove_favorite(wallpaper_id: &str) -> SqlResult<()> {
-    let conn = get_connection()?;
+pub fn remove_favorite(conn: &Connection, wallpaper_id: &str) -> SqlResult<()> {
     conn.execute(
         "DELETE FROM favorites WHERE wallpaper_id = ?1",
         params![wallpaper_id],
@@ -134,66 +371,45 @@ pub fn remove_favorite(wallpaper_id: &str) -> SqlResult<()> {
     Ok(())
 }
 
-pub fn get_wallpapers_with_fav(limit: u32, offset: u32) -> SqlResult<Vec<Wallpaper>> {
-    let conn = get_connection()?;
-    let mut stmt = conn.prepare(
+/// Favorited wallpapers, once each, leaving out unavailable, hidden and
+/// blocked ones.
+pub fn get_favorite_wallpapers(conn: &Connection) -> SqlResult<Vec<Wallpaper>> {
+    let mut stmt = conn.prepare(&format!(
         r#"
-        SELECT w.*, f.wallpaper_id IS NOT NULL AS is_favorite 
-        FROM wallpapers w
-        LEFT JOIN favorites f ON w.id = f.wallpaper_id
-        ORDER BY created_at DESC
-        LIMIT ?1 OFFSET ?2
-        "#,
-    )?;
+        SELECT {WALLPAPER_COLUMNS} FROM wallpapers w
+        WHERE EXISTS (SELECT 1 FROM favorites f WHERE f.wallpaper_id = w.id)
+          AND w.is_available = 1 AND w.is_hidden = 0 AND {NOT_BLOCKED}
+        "#
+    ))?;
 
-    let rows = stmt.query_map(params![limit, offset], |row| {
-        Ok(Wallpaper {
-            id: row.get("id")?,
-            mongo_id: row.get("mongo_id")?,
-            title: row.get("title")?,
-            url: row.get("url")?,
-            thumbnail: row.get("thumbnail")?,
-            width: row.get("width")?,
-            height: row.get("height")?,
-            tags: row.get("tags")?,
-            is_ai_generated: row.get("is_ai_generated")?,
-            is_favorite: row.get("is_favorite")?, // <- fetch from the alias
-        })
-    })?;
+    let rows = stmt.query_map([], wallpaper_from_row)?;
 
     Ok(rows.filter_map(Result::ok).collect())
 }
 
-pub fn get_favorite_wallpapers() -> SqlResult<Vec<Wallpaper>> {
-    let conn = get_connection()?;
-    let mut stmt = conn.prepare(
-        r#"
-        SELECT w.* FROM wallpapers w
-        INNER JOIN favorites f ON w.id = f.wallpaper_id
-        "#,
-    )?;
-
-    let rows = stmt.query_map([], |row| {
-        Ok(Wallpaper {
-            id: row.get("id")?,
-            mongo_id: row.get("mongo_id")?,
-            title: row.get("title")?,
-            url: row.get("url")?,
-            thumbnail: row.get("thumbnail")?,
-            width: row.get("width")?,
-            height: row.get("height")?,
-            tags: row.get("tags")?,
-            is_ai_generated: row.get("is_ai_generated")?,
-            is_favorite: true,
-        })
-    })?;
-
-    Ok(rows.filter_map(Result::ok).collect())
+/// The stored wallpaper that `url` is the original, thumbnail or a variant of.
+pub fn get_wallpaper_by_url(conn: &Connection, url: &str) -> SqlResult<Option<Wallpaper>> {
+    conn.query_row(
+        &format!(
+            r#"
+        SELECT {WALLPAPER_COLUMNS}
+        FROM wallpapers w
+        WHERE w.url = ?1
+           OR w.thumbnail = ?1
+           OR EXISTS (
+               SELECT 1 FROM json_each(w.variants) v
+               WHERE json_extract(v.value, '$.url') = ?1
+           )
+        LIMIT 1
+        "#
+        ),
+        params![url],
+        wallpaper_from_row,
+    )
+    .optional()
 }
 
-pub fn get_setting() -> Result<Settings, String> {
-    let conn = get_connection().map_err(|e| e.to_string())?;
-
+pub fn get_setting(conn: &Connection) -> Result<Settings, String> {
     let mut stmt = conn
         .prepare("SELECT key, value FROM settings")
         .map_err(|e| format!("Failed to prepare statement: {}", e))?;
@@ -207,17 +423,14 @@ pub fn get_setting() -> Result<Settings, String> {
         .map_err(|e| format!("Query execution failed: {}", e))?;
 
     let mut settings = Settings::new();
-    for row in rows {
-        if let Ok((key, value)) = row {
-            settings.insert(key, value);
-        }
+    for (key, value) in rows.flatten() {
+        settings.insert(key, value);
     }
 
     Ok(settings)
 }
 
-pub fn set_setting(key: &str, value: &str, category: &str) -> SqlResult<()> {
-    let conn = get_connection()?;
+pub fn set_setting(conn: &Connection, key: &str, value: &str, category: &str) -> SqlResult<()> {
     conn.execute(
         r#"
         INSERT INTO settings (key, value, category)
@@ -229,9 +442,7 @@ pub fn set_setting(key: &str, value: &str, category: &str) -> SqlResult<()> {
     Ok(())
 }
 
-pub fn reset_settings() -> SqlResult<()> {
-    let conn = get_connection()?;
-
+pub fn reset_settings(conn: &Connection) -> SqlResult<()> {
     let defaults = vec![
         ("autoUpdate", "true", "wallpaper"),
         ("saveWallpaper", "false", "wallpaper"),
@@ -241,6 +452,13 @@ pub fn reset_settings() -> SqlResult<()> {
         ("autoStart", "false", "preferences"),
         ("experimental", "false", "preferences"),
         ("language", "en-US", "general"),
+        ("preferredResolution", AUTO_RESOLUTION, "wallpaper"),
+        ("imageFormat", "jpeg", "wallpaper"),
+        ("imageQuality", "90", "wallpaper"),
+        ("scaleFilter", "lanczos", "wallpaper"),
+        ("syncInterval", "3600000", "general"),
+        ("skipDuplicates", "false", "general"),
+        ("stripLocation", "true", "general"),
     ];
 
     for (key, value, category) in defaults {
@@ -250,17 +468,16 @@ pub fn reset_settings() -> SqlResult<()> {
             VALUES (?1, ?2, ?3)
             ON CONFLICT(key) DO UPDATE SET value = excluded.value, category = excluded.category
             "#,
-            &[key, value, category],
+            [key, value, category],
         )?;
     }
 
     Ok(())
 }
 
-pub fn update_favorite(wallpaper_id: &str, is_fav: bool) -> SqlResult<()> {
-    let conn = get_connection()?;
+pub fn update_favorite(conn: &Connection, wallpaper_id: &str, is_fav: bool) -> SqlResult<()> {
     if is_fav {
-        add_favorite(wallpaper_id)?;
+        add_favorite(conn, wallpaper_id)?;
     } else {
         conn.execute(
             "DELETE FROM favorites WHERE wallpaper_id = ?1",
@@ -270,8 +487,7 @@ pub fn update_favorite(wallpaper_id: &str, is_fav: bool) -> SqlResult<()> {
     Ok(())
 }
 
-pub fn delete_favorite_wallpaper(wallpaper_id: &str) -> SqlResult<()> {
-    let conn = get_connection()?;
+pub fn delete_favorite_wallpaper(conn: &Connection, wallpaper_id: &str) -> SqlResult<()> {
     conn.execute(
         "DELETE FROM favorites WHERE wallpaper_id = ?1",
         params![wallpaper_id],
@@ -279,9 +495,116 @@ pub fn delete_favorite_wallpaper(wallpaper_id: &str) -> SqlResult<()> {
     Ok(())
 }
 
-pub fn update_wallpaper_settings(settings: WallpaperSettings) -> SqlResult<()> {
-    let conn = get_connection()?;
+// Ratings, hiding and the blocklist
 
+/// A blocklist entry. The wallpaper itself may be gone from the library.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BlockedWallpaper {
+    pub id: i64,
+    pub title: String,
+    pub mongo_id: Option<String>,
+    pub file_path: Option<String>,
+    pub blocked_at: String,
+    /// While the wallpaper is still stored.
+    pub thumbnail: Option<String>,
+}
+
+/// Sets a wallpaper's star rating, 1 to 5, or clears it with `None`.
+/// Returns whether the wallpaper exists.
+pub fn set_rating(conn: &Connection, wallpaper_id: &str, rating: Option<u8>) -> SqlResult<bool> {
+    let changed = conn.execute(
+        "UPDATE wallpapers SET rating = ?2 WHERE id = ?1",
+        params![wallpaper_id, rating],
+    )?;
+    Ok(changed > 0)
+}
+
+pub fn set_hidden(conn: &Connection, wallpaper_id: &str, hidden: bool) -> SqlResult<bool> {
+    let changed = conn.execute(
+        "UPDATE wallpapers SET is_hidden = ?2 WHERE id = ?1",
+        params![wallpaper_id, hidden],
+    )?;
+    Ok(changed > 0)
+}
+
+/// Whether a wallpaper with this server id or file path is blocked.
+pub fn is_blocked(
+    conn: &Connection,
+    mongo_id: Option<&str>,
+    file_path: Option<&str>,
+) -> SqlResult<bool> {
+    conn.prepare_cached(
+        "SELECT EXISTS (SELECT 1 FROM blocklist WHERE mongo_id = ?1 OR file_path = ?2)",
+    )?
+    .query_row(params![mongo_id, file_path], |row| row.get(0))
+}
+
+/// Whether the stored wallpaper at `url` is hidden or blocked. Anything not
+/// stored isn't.
+pub fn is_excluded_url(conn: &Connection, url: &str) -> SqlResult<bool> {
+    conn.query_row(
+        &format!(
+            "SELECT EXISTS (
+                 SELECT 1 FROM wallpapers w
+                 WHERE w.url = ?1 AND (w.is_hidden = 1 OR NOT {NOT_BLOCKED})
+             )"
+        ),
+        params![url],
+        |row| row.get(0),
+    )
+}
+
+/// Blocks a wallpaper for good. It stays in the database until synced or
+/// rescanned away, but no listing, rotation, sync or import shows it again.
+/// Returns whether the wallpaper exists.
+pub fn block_wallpaper(conn: &Connection, wallpaper_id: &str) -> SqlResult<bool> {
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO blocklist (wallpaper_id, mongo_id, file_path, title)
+         SELECT id, mongo_id, file_path, COALESCE(title, '') FROM wallpapers WHERE id = ?1",
+        params![wallpaper_id],
+    )?;
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM wallpapers WHERE id = ?1",
+            params![wallpaper_id],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    Ok(inserted > 0 || exists)
+}
+
+/// Takes an entry off the blocklist. A wallpaper that is still stored shows
+/// up again straight away; others come back with the next sync or rescan
+/// that sees them.
+pub fn unblock_wallpaper(conn: &Connection, blocked_id: i64) -> SqlResult<bool> {
+    let deleted = conn.execute("DELETE FROM blocklist WHERE id = ?1", params![blocked_id])?;
+    Ok(deleted > 0)
+}
+
+pub fn list_blocked_wallpapers(conn: &Connection) -> SqlResult<Vec<BlockedWallpaper>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT b.id, b.title, b.mongo_id, b.file_path, b.blocked_at, w.thumbnail
+        FROM blocklist b
+        LEFT JOIN wallpapers w ON w.id = b.wallpaper_id
+        ORDER BY b.blocked_at DESC, b.id DESC
+        "#,
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(BlockedWallpaper {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            mongo_id: row.get(2)?,
+            file_path: row.get(3)?,
+            blocked_at: row.get(4)?,
+            thumbnail: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn update_wallpaper_settings(conn: &Connection, settings: WallpaperSettings) -> SqlResult<()> {
     let items = vec![
         ("autoUpdate", settings.auto_update.to_string(), "general"),
         (
@@ -303,6 +626,11 @@ pub fn update_wallpaper_settings(settings: WallpaperSettings) -> SqlResult<()> {
         ("autoStart", settings.auto_start.to_string(), "general"),
         ("experimental", settings.experimental.to_string(), "general"),
         ("language", settings.language.to_string(), "general"),
+        (
+            "preferredResolution",
+            settings.preferred_resolution.to_string(),
+            "wallpaper",
+        ),
     ];
 
     for (key, val, category) in items {
@@ -319,8 +647,7 @@ pub fn update_wallpaper_settings(settings: WallpaperSettings) -> SqlResult<()> {
     Ok(())
 }
 
-pub fn get_wallpaper_settings() -> SqlResult<WallpaperSettings> {
-    let conn = get_connection()?;
+pub fn get_wallpaper_settings(conn: &Connection) -> SqlResult<WallpaperSettings> {
     let mut stmt = conn.prepare("SELECT key, value FROM settings")?;
     let rows = stmt.query_map([], |row| {
         Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
@@ -335,6 +662,7 @@ pub fn get_wallpaper_settings() -> SqlResult<WallpaperSettings> {
         auto_start: false,
         experimental: false,
         language: "en-US".to_string(),
+        preferred_resolution: AUTO_RESOLUTION.to_string(),
     };
 
     for row in rows {
@@ -348,6 +676,7 @@ pub fn get_wallpaper_settings() -> SqlResult<WallpaperSettings> {
             "autoStart" => settings.auto_start = value.parse().unwrap_or(false),
             "experimental" => settings.experimental = value.parse().unwrap_or(false),
             "language" => settings.language = value,
+            "preferredResolution" => settings.preferred_resolution = value,
             _ => {}
         }
     }
@@ -355,68 +684,150 @@ pub fn get_wallpaper_settings() -> SqlResult<WallpaperSettings> {
     Ok(settings)
 }
 
-pub fn get_last_synced_mongo_id() -> SqlResult<Option<String>> {
-    let conn = get_connection()?;
+/// Background sync interval in milliseconds; `0` disables periodic sync.
+pub fn get_sync_interval_ms(conn: &Connection) -> SqlResult<u64> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = 'syncInterval'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_SYNC_INTERVAL_MS))
+}
+
+/// The `preferredResolution` setting, if set.
+pub fn get_preferred_resolution(conn: &Connection) -> SqlResult<Option<String>> {
     conn.query_row(
-        "SELECT mongo_id FROM wallpapers WHERE mongo_id IS NOT NULL ORDER BY created_at DESC LIMIT 1",
+        "SELECT value FROM settings WHERE key = 'preferredResolution'",
         [],
         |row| row.get(0),
-    ).optional()
+    )
+    .optional()
 }
 
-pub fn get_wallpaper_count() -> Result<u32, rusqlite::Error> {
-    let conn = get_connection()?;
+/// Opaque position in the server's change feed, as returned by the last sync.
+pub fn get_sync_cursor(conn: &Connection) -> SqlResult<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM sync_state WHERE key = 'change_cursor'",
+        [],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn set_sync_cursor(conn: &Connection, cursor: &str) -> SqlResult<()> {
+    conn.execute(
+        r#"
+        INSERT INTO sync_state (key, value) VALUES ('change_cursor', ?1)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value
+        "#,
+        params![cursor],
+    )?;
+    Ok(())
+}
+
+pub fn get_wallpaper_count(conn: &Connection) -> Result<u32, rusqlite::Error> {
     let mut stmt = conn.prepare("SELECT COUNT(*) FROM wallpapers")?;
     let count: u32 = stmt.query_row([], |row| row.get(0))?;
     Ok(count)
 }
 
-pub fn get_wallpapers_by_tag(tag: &str, limit: u32, offset: u32) -> SqlResult<Vec<Wallpaper>> {
-    let conn = get_connection()?;
+/// SQL condition on `w.id` selecting wallpapers tagged with `names` per
+/// `mode`, plus its bound values. `names` must be non-empty and normalized
+/// with [`parse_tags`].
+pub(crate) fn tag_filter_clause(names: &[String], mode: TagMatch) -> (String, Vec<Value>) {
+    let required = match mode {
+        TagMatch::All => names.len(),
+        TagMatch::Any => 1,
+    };
+    let placeholders = vec!["?"; names.len()].join(", ");
+    let clause = format!(
+        r#"w.id IN (
+            SELECT wt.wallpaper_id
+            FROM wallpaper_tags wt
+            JOIN tags t ON t.id = wt.tag_id
+            WHERE t.name IN ({})
+            GROUP BY wt.wallpaper_id
+            HAVING COUNT(DISTINCT wt.tag_id) >= ?
+        )"#,
+        placeholders
+    );
+
+    let mut values: Vec<Value> = names.iter().cloned().map(Value::from).collect();
+    values.push(Value::from(required as i64));
+    (clause, values)
+}
+
+/// Splits a comma-separated tag string into trimmed names, dropping empties
+/// and case-insensitive repeats. The first spelling seen wins.
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Replaces the `wallpaper_tags` links of a wallpaper with the tags in the
+/// comma-separated `tags` string.
+pub fn set_wallpaper_tags(conn: &Connection, wallpaper_id: &str, tags: &str) -> SqlResult<()> {
+    conn.prepare_cached("DELETE FROM wallpaper_tags WHERE wallpaper_id = ?1")?
+        .execute(params![wallpaper_id])?;
+
+    for name in parse_tags(tags) {
+        conn.prepare_cached("INSERT INTO tags (name) VALUES (?1) ON CONFLICT(name) DO NOTHING")?
+            .execute(params![name])?;
+        conn.prepare_cached(
+            r#"
+            INSERT OR IGNORE INTO wallpaper_tags (wallpaper_id, tag_id)
+            SELECT ?1, id FROM tags WHERE name = ?2
+            "#,
+        )?
+        .execute(params![wallpaper_id, name])?;
+    }
+    Ok(())
+}
+
+/// Every tag used by an available wallpaper, with how many use it.
+pub fn list_tags(conn: &Connection) -> SqlResult<Vec<TagCount>> {
     let mut stmt = conn.prepare(
         r#"
-        SELECT 
-            w.*, 
-            CASE WHEN f.wallpaper_id IS NOT NULL THEN 1 ELSE 0 END AS is_favorite
-        FROM wallpapers w
-        LEFT JOIN favorites f ON w.id = f.wallpaper_id
-        WHERE w.tags LIKE ?1
-        ORDER BY w.created_at DESC
-        LIMIT ?2 OFFSET ?3
+        SELECT t.name, COUNT(*) AS count
+        FROM tags t
+        JOIN wallpaper_tags wt ON wt.tag_id = t.id
+        JOIN wallpapers w ON w.id = wt.wallpaper_id
+        WHERE w.is_available = 1
+        GROUP BY t.id
+        ORDER BY count DESC, t.name COLLATE NOCASE
         "#,
     )?;
-    let tag_like = format!("%{}%", tag);
-    let rows = stmt.query_map(params![tag_like, limit, offset], |row| {
-        Ok(Wallpaper {
-            id: row.get("id")?,
-            mongo_id: row.get("mongo_id")?,
-            title: row.get("title")?,
-            url: row.get("url")?,
-            thumbnail: row.get("thumbnail")?,
-            width: row.get("width")?,
-            height: row.get("height")?,
-            tags: row.get("tags")?,
-            is_ai_generated: row.get("is_ai_generated")?,
-            is_favorite: row.get("is_favorite")?,
+    let rows = stmt.query_map([], |row| {
+        Ok(TagCount {
+            name: row.get(0)?,
+            count: row.get(1)?,
         })
     })?;
 
     Ok(rows.filter_map(Result::ok).collect())
 }
 
-/// Delete old wallpapers that are not marked as favorites.
+/// Delete old wallpapers that are not marked as favorites or in a collection.
 /// Keeps the most recent `keep_limit` wallpapers (by `created_at`).
-pub fn prune_old_wallpapers(keep_limit: usize) -> SqlResult<()> {
-    let conn = get_connection()?;
-
+pub fn prune_old_wallpapers(conn: &Connection, keep_limit: usize) -> SqlResult<()> {
     conn.execute(
         r#"
         DELETE FROM wallpapers
         WHERE id NOT IN (
             SELECT w.id
             FROM wallpapers w
-            LEFT JOIN favorites f ON w.id = f.wallpaper_id
-            ORDER BY f.wallpaper_id IS NOT NULL DESC, w.created_at DESC
+            ORDER BY EXISTS (SELECT 1 FROM favorites f WHERE f.wallpaper_id = w.id)
+                  OR EXISTS (SELECT 1 FROM collection_items i WHERE i.wallpaper_id = w.id) DESC,
+                  w.created_at DESC
             LIMIT ?1
         )
         "#,
@@ -431,112 +842,397 @@ pub fn prune_old_wallpapers(keep_limit: usize) -> SqlResult<()> {
 //
 
 #[tauri::command]
-pub fn add_to_favorites(wallpaper_id: String) -> Result<(), String> {
-    add_favorite(&wallpaper_id).map_err(|e| e.to_string())
+pub fn add_to_favorites(db: State<'_, DbPool>, wallpaper_id: String) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    add_favorite(&conn, &wallpaper_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn fetch_wallpapers(limit: u32, offset: u32) -> Result<Vec<Wallpaper>, String> {
-    use crate::services::{
-        db_services::get_wallpapers_with_fav, sync_service::fetch_from_mongo_and_cache,
+pub async fn fetch_wallpapers(
+    app: AppHandle,
+    db: State<'_, DbPool>,
+    limit: u32,
+    cursor: Option<String>,
+) -> Result<WallpaperPage, String> {
+    use crate::services::sync_service::sync_with_events;
+
+    // The sync takes connections of its own; none is held across it.
+    let filter = WallpaperFilter::default();
+    let sort = WallpaperSort::Newest;
+    let local = {
+        let conn = db.get().map_err(|e| e.to_string())?;
+        query_wallpapers(&conn, &filter, sort, limit, cursor.as_deref())?
     };
 
-    let mut local = get_wallpapers_with_fav(limit, offset).map_err(|e| e.to_string())?;
-
-    if local.len() < limit as usize {
-        fetch_from_mongo_and_cache(100)
-            .await
-            .map_err(|e| e.to_string())?;
-        local = get_wallpapers_with_fav(limit, offset).map_err(|e| e.to_string())?;
+    if local.wallpapers.len() < limit.min(MAX_PAGE_SIZE) as usize {
+        sync_with_events(&app).await?;
+        let conn = db.get().map_err(|e| e.to_string())?;
+        return query_wallpapers(&conn, &filter, sort, limit, cursor.as_deref());
     }
 
     Ok(local)
 }
 
 #[tauri::command]
-pub fn fetch_favorite_wallpapers() -> Result<Vec<Wallpaper>, String> {
-    get_favorite_wallpapers().map_err(|e| e.to_string())
+pub fn fetch_favorite_wallpapers(db: State<'_, DbPool>) -> Result<Vec<Wallpaper>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    get_favorite_wallpapers(&conn).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub fn get_app_settings() -> Result<Settings, String> {
-    let settings = get_setting().map_err(|e| e.to_string())?;
+pub fn get_app_settings(db: State<'_, DbPool>) -> Result<Settings, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let settings = get_setting(&conn).map_err(|e| e.to_string())?;
     Ok(settings)
 }
 
 #[tauri::command]
-pub fn update_app_setting(key: String, value: String, category: String) -> Result<(), String> {
-    set_setting(&key, &value, &category).map_err(|e| e.to_string())
+pub fn update_app_setting(
+    db: State<'_, DbPool>,
+    key: String,
+    value: String,
+    category: String,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    set_setting(&conn, &key, &value, &category).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn get_wallpapers(limit: u32, offset: u32) -> Result<Vec<Wallpaper>, String> {
-    use crate::services::sync_service::fetch_from_mongo_and_cache;
+pub async fn get_wallpapers(
+    app: AppHandle,
+    db: State<'_, DbPool>,
+    limit: u32,
+    cursor: Option<String>,
+) -> Result<WallpaperPage, String> {
+    use crate::services::sync_service::sync_with_events;
 
-    let count = get_wallpaper_count().map_err(|e| e.to_string())?;
+    let count = {
+        let conn = db.get().map_err(|e| e.to_string())?;
+        get_wallpaper_count(&conn).map_err(|e| e.to_string())?
+    };
 
     // If DB is empty, sync from Mongo
     if count == 0 {
-        fetch_from_mongo_and_cache(100)
+        sync_with_events(&app)
             .await
             .map_err(|e| format!("Mongo sync failed: {}", e))?;
     }
 
     // Return wallpapers from local SQLite
-    get_wallpapers_with_fav(limit, offset).map_err(|e| e.to_string())
+    let conn = db.get().map_err(|e| e.to_string())?;
+    query_wallpapers(
+        &conn,
+        &WallpaperFilter::default(),
+        WallpaperSort::Newest,
+        limit,
+        cursor.as_deref(),
+    )
 }
 
 #[tauri::command]
 pub async fn update_app_setting_command(
+    db: State<'_, DbPool>,
     key: String,
     value: String,
     category: String,
 ) -> Result<(), String> {
-    set_setting(&key, &value, &category).map_err(|e| e.to_string())
+    let conn = db.get().map_err(|e| e.to_string())?;
+    set_setting(&conn, &key, &value, &category).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn get_wallpaper_count_command() -> Result<u32, String> {
-    get_wallpaper_count().map_err(|e| e.to_string())
+pub async fn get_wallpaper_count_command(db: State<'_, DbPool>) -> Result<u32, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    get_wallpaper_count(&conn).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn prune_wallpaper_db_command(limit: usize) -> Result<(), String> {
-    prune_old_wallpapers(limit).map_err(|e| e.to_string())
+pub async fn prune_wallpaper_db_command(db: State<'_, DbPool>, limit: usize) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    prune_old_wallpapers(&conn, limit).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub async fn get_wallpapers_by_tag_command(
+    db: State<'_, DbPool>,
     tag: String,
     limit: u32,
-    offset: u32,
-) -> Result<Vec<Wallpaper>, String> {
-    get_wallpapers_by_tag(&tag, limit, offset).map_err(|e| e.to_string())
+    cursor: Option<String>,
+) -> Result<WallpaperPage, String> {
+    get_wallpapers_by_tags_command(db, vec![tag], None, limit, cursor).await
+}
+
+/// Wallpapers carrying every one (`All`) or at least one (`Any`) of `tags`.
+/// Tag names match exactly, ignoring case.
+#[tauri::command]
+pub async fn get_wallpapers_by_tags_command(
+    db: State<'_, DbPool>,
+    tags: Vec<String>,
+    match_mode: Option<TagMatch>,
+    limit: u32,
+    cursor: Option<String>,
+) -> Result<WallpaperPage, String> {
+    if parse_tags(&tags.join(",")).is_empty() {
+        return Ok(WallpaperPage {
+            wallpapers: Vec::new(),
+            next_cursor: None,
+        });
+    }
+
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let filter = WallpaperFilter {
+        tags,
+        tag_match: match_mode.unwrap_or_default(),
+        ..Default::default()
+    };
+    query_wallpapers(
+        &conn,
+        &filter,
+        WallpaperSort::Newest,
+        limit,
+        cursor.as_deref(),
+    )
+}
+
+#[tauri::command]
+pub async fn list_tags_command(db: State<'_, DbPool>) -> Result<Vec<TagCount>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    list_tags(&conn).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub async fn update_favorite_command(
+    db: State<'_, DbPool>,
     id: String,
     is_favorite: bool,
 ) -> Result<Vec<Wallpaper>, String> {
-    update_favorite(&id, is_favorite).map_err(|e| e.to_string())?;
+    let conn = db.get().map_err(|e| e.to_string())?;
+    update_favorite(&conn, &id, is_favorite).map_err(|e| e.to_string())?;
 
-    let favorites = get_favorite_wallpapers().map_err(|e| e.to_string())?;
+    let favorites = get_favorite_wallpapers(&conn).map_err(|e| e.to_string())?;
     Ok(favorites)
 }
 
 #[tauri::command]
-pub async fn delete_favorite_wallpaper_command(id: String) -> Result<Vec<Wallpaper>, String> {
-    delete_favorite_wallpaper(&id).map_err(|e| e.to_string())?;
+pub async fn delete_favorite_wallpaper_command(
+    db: State<'_, DbPool>,
+    id: String,
+) -> Result<Vec<Wallpaper>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    delete_favorite_wallpaper(&conn, &id).map_err(|e| e.to_string())?;
 
-    let favorites = get_favorite_wallpapers().map_err(|e| e.to_string())?;
+    let favorites = get_favorite_wallpapers(&conn).map_err(|e| e.to_string())?;
     Ok(favorites)
 }
 
 #[tauri::command]
-pub async fn reset_wallpaper_settings() -> Result<(), String> {
-    match reset_settings() {
+pub async fn set_wallpaper_rating_command(
+    db: State<'_, DbPool>,
+    id: String,
+    rating: Option<u8>,
+) -> Result<(), String> {
+    if rating.is_some_and(|stars| !(1..=5).contains(&stars)) {
+        return Err("Rating must be between 1 and 5 stars".into());
+    }
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match set_rating(&conn, &id, rating).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("No wallpaper {id}")),
+    }
+}
+
+#[tauri::command]
+pub async fn set_wallpaper_hidden_command(
+    db: State<'_, DbPool>,
+    id: String,
+    hidden: bool,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match set_hidden(&conn, &id, hidden).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("No wallpaper {id}")),
+    }
+}
+
+#[tauri::command]
+pub async fn block_wallpaper_command(db: State<'_, DbPool>, id: String) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match block_wallpaper(&conn, &id).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("No wallpaper {id}")),
+    }
+}
+
+#[tauri::command]
+pub async fn unblock_wallpaper_command(
+    db: State<'_, DbPool>,
+    id: i64,
+) -> Result<Vec<BlockedWallpaper>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    unblock_wallpaper(&conn, id).map_err(|e| e.to_string())?;
+    list_blocked_wallpapers(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_blocked_wallpapers_command(
+    db: State<'_, DbPool>,
+) -> Result<Vec<BlockedWallpaper>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    list_blocked_wallpapers(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reset_wallpaper_settings(db: State<'_, DbPool>) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match reset_settings(&conn) {
         Ok(_) => Ok(()),
         Err(e) => Err(e.to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::query_service::count_wallpapers;
+
+    fn remote(mongo_id: &str) -> Wallpaper {
+        Wallpaper {
+            id: format!("local-{mongo_id}"),
+            mongo_id: Some(mongo_id.into()),
+            title: mongo_id.into(),
+            url: format!("https://example.com/{mongo_id}.jpg"),
+            thumbnail: String::new(),
+            width: 1920,
+            height: 1080,
+            tags: String::new(),
+            is_ai_generated: false,
+            is_favorite: false,
+            is_available: true,
+            popularity: 0,
+            variants: Vec::new(),
+            focal_point: None,
+            dominant_color: None,
+            accent_color: None,
+            source: WallpaperSource::Remote,
+            file_path: None,
+            rating: None,
+            is_hidden: false,
+        }
+    }
+
+    fn ids(conn: &Connection, filter: &WallpaperFilter) -> Vec<String> {
+        query_wallpapers(conn, filter, WallpaperSort::Title, 10, None)
+            .unwrap()
+            .wallpapers
+            .into_iter()
+            .map(|w| w.id)
+            .collect()
+    }
+
+    #[test]
+    fn ratings_hiding_and_blocks_shape_listings() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn, None).unwrap();
+        for mongo_id in ["a", "b", "c"] {
+            add_or_update_wallpaper(&conn, &remote(mongo_id), None).unwrap();
+            add_favorite(&conn, &format!("local-{mongo_id}")).unwrap();
+        }
+
+        assert!(set_rating(&conn, "local-a", Some(5)).unwrap());
+        assert!(set_rating(&conn, "local-b", Some(2)).unwrap());
+        assert!(set_rating(&conn, "local-a", Some(6)).is_err());
+        let rated = WallpaperFilter {
+            min_rating: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(ids(&conn, &rated), ["local-a"]);
+
+        assert!(set_hidden(&conn, "local-b", true).unwrap());
+        let all = WallpaperFilter::default();
+        assert_eq!(ids(&conn, &all), ["local-a", "local-c"]);
+        let with_hidden = WallpaperFilter {
+            include_hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(ids(&conn, &with_hidden), ["local-a", "local-b", "local-c"]);
+        assert!(is_excluded_url(&conn, "https://example.com/b.jpg").unwrap());
+
+        assert!(block_wallpaper(&conn, "local-c").unwrap());
+        assert!(block_wallpaper(&conn, "local-c").unwrap());
+        assert!(!block_wallpaper(&conn, "missing").unwrap());
+        assert_eq!(ids(&conn, &with_hidden), ["local-a", "local-b"]);
+        assert_eq!(get_favorite_wallpapers(&conn).unwrap().len(), 1);
+
+        // Deleted and synced in again, it stays blocked.
+        remove_favorite(&conn, "local-c").unwrap();
+        assert!(remove_wallpaper_by_mongo_id(&conn, "c").unwrap());
+        assert_eq!(
+            add_or_update_wallpaper(&conn, &remote("c"), None).unwrap(),
+            UpsertOutcome::Blocked
+        );
+        assert_eq!(count_wallpapers(&conn, &with_hidden).unwrap(), 2);
+
+        let blocked = list_blocked_wallpapers(&conn).unwrap();
+        assert_eq!(blocked.len(), 1);
+        assert_eq!(blocked[0].thumbnail, None);
+        assert!(unblock_wallpaper(&conn, blocked[0].id).unwrap());
+        assert_eq!(
+            add_or_update_wallpaper(&conn, &remote("c"), None).unwrap(),
+            UpsertOutcome::Added
+        );
+        assert_eq!(ids(&conn, &all), ["local-a", "local-c"]);
+    }
+
+    #[test]
+    fn pooled_connections_share_a_configured_database() {
+        let dir = std::env::temp_dir().join(format!("wallpaper-pool-{}", uuid::Uuid::new_v4()));
+        let pool = init_pool(&dir.join("data").join("wallpapers.sqlite")).unwrap();
+
+        let writer = pool.get().unwrap();
+        let reader = pool.get().unwrap();
+        for conn in [&writer, &reader] {
+            let mode: String = conn
+                .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+                .unwrap();
+            let timeout: u32 = conn
+                .query_row("PRAGMA busy_timeout", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!((mode.as_str(), timeout), ("wal", 5000));
+        }
+
+        assert_eq!(
+            add_or_update_wallpaper(&writer, &remote("a"), None).unwrap(),
+            UpsertOutcome::Added
+        );
+        assert_eq!(get_wallpaper_count(&reader).unwrap(), 1);
+
+        drop((writer, reader, pool));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn favorites_are_listed_once_while_available() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn, None).unwrap();
+        for mongo_id in ["a", "b"] {
+            add_or_update_wallpaper(&conn, &remote(mongo_id), None).unwrap();
+            add_favorite(&conn, &format!("local-{mongo_id}")).unwrap();
+        }
+        add_favorite(&conn, "local-a").unwrap();
+
+        let ids = |conn: &Connection| -> Vec<String> {
+            let mut ids: Vec<String> = get_favorite_wallpapers(conn)
+                .unwrap()
+                .into_iter()
+                .map(|w| w.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&conn), ["local-a", "local-b"]);
+
+        // Kept by the tombstone for the favorite, but not listed.
+        assert!(remove_wallpaper_by_mongo_id(&conn, "b").unwrap());
+        assert_eq!(ids(&conn), ["local-a"]);
+    }
+}
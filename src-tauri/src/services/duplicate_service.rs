@@ -0,0 +1,267 @@
+use crate::services::db_services::{
+    wallpaper_from_row, DbPool, Wallpaper, NOT_BLOCKED, WALLPAPER_COLUMNS,
+};
+use crate::services::image_pipeline::decode;
+use crate::services::phash::{dhash, hamming};
+use crate::services::thumbnail_service::{stored_thumbnail, thumbnail_dir};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Manager, State};
+
+/// Hashes this many bits apart or fewer count as the same image.
+pub const DEFAULT_MAX_DISTANCE: u32 = 6;
+
+/// Setting that makes sync and imports leave out near-duplicates of
+/// wallpapers already in the library.
+const SKIP_DUPLICATES_SETTING: &str = "skipDuplicates";
+
+/// Thumbnail size hashes are computed from; the hash only needs 9×8 pixels.
+const HASH_SOURCE_SIZE: u32 = 128;
+
+pub fn save_hash(conn: &Connection, wallpaper_id: &str, hash: u64) -> SqlResult<()> {
+    // SQLite integers are signed; the bits are kept as they are.
+    conn.execute(
+        "UPDATE wallpapers SET phash = ?2 WHERE id = ?1",
+        params![wallpaper_id, hash as i64],
+    )?;
+    Ok(())
+}
+
+pub fn load_hash(conn: &Connection, wallpaper_id: &str) -> SqlResult<Option<u64>> {
+    let hash: Option<Option<i64>> = conn
+        .query_row(
+            "SELECT phash FROM wallpapers WHERE id = ?1",
+            params![wallpaper_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(hash.flatten().map(|hash| hash as u64))
+}
+
+/// Hash of an encoded image.
+pub fn hash_image(bytes: &[u8]) -> Result<u64, String> {
+    Ok(dhash(&decode(bytes)?))
+}
+
+/// The wallpaper's hash, computed from its local thumbnail the first time.
+/// `None` while there is no thumbnail to compute it from.
+pub fn ensure_hash(
+    conn: &Connection,
+    thumbnails: &Path,
+    wallpaper_id: &str,
+) -> Result<Option<u64>, String> {
+    if let Some(hash) = load_hash(conn, wallpaper_id).map_err(|e| e.to_string())? {
+        return Ok(Some(hash));
+    }
+    let Some(path) = stored_thumbnail(thumbnails, wallpaper_id, HASH_SOURCE_SIZE) else {
+        return Ok(None);
+    };
+    let hash = hash_image(&fs::read(path).map_err(|e| e.to_string())?)?;
+    save_hash(conn, wallpaper_id, hash).map_err(|e| e.to_string())?;
+    Ok(Some(hash))
+}
+
+/// Hashes every wallpaper that has a thumbnail but no hash yet. Returns how
+/// many were added.
+pub fn backfill_hashes(conn: &Connection, thumbnails: &Path) -> Result<usize, String> {
+    let ids: Vec<String> = {
+        let mut stmt = conn
+            .prepare("SELECT id FROM wallpapers WHERE phash IS NULL")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut added = 0;
+    for id in ids {
+        if let Ok(Some(_)) = ensure_hash(conn, thumbnails, &id) {
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+/// Hashes images cached before hashes existed, once per launch.
+pub fn start_hash_backfill(app: AppHandle) {
+    std::thread::spawn(move || {
+        let result = thumbnail_dir().and_then(|dir| {
+            let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+            backfill_hashes(&conn, &dir)
+        });
+        if let Err(e) = result {
+            eprintln!("Perceptual hash backfill failed: {}", e);
+        }
+    });
+}
+
+/// Hashes of the wallpapers listings show: a copy of one whose file is gone,
+/// or that is blocked, isn't a duplicate of anything the user can see.
+fn stored_hashes(conn: &Connection) -> SqlResult<Vec<(String, u64)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT w.id, w.phash FROM wallpapers w
+         WHERE w.phash IS NOT NULL AND w.is_available = 1 AND {NOT_BLOCKED}"
+    ))?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))?;
+    rows.collect()
+}
+
+/// A stored wallpaper whose hash is within `max_distance` of `hash`, other
+/// than `except`.
+pub fn find_duplicate_of(
+    conn: &Connection,
+    hash: u64,
+    max_distance: u32,
+    except: Option<&str>,
+) -> SqlResult<Option<String>> {
+    Ok(stored_hashes(conn)?
+        .into_iter()
+        .filter(|(id, _)| Some(id.as_str()) != except)
+        .map(|(id, stored)| (id, hamming(hash, stored)))
+        .filter(|&(_, distance)| distance <= max_distance)
+        .min_by_key(|&(_, distance)| distance)
+        .map(|(id, _)| id))
+}
+
+/// Whether sync and imports should leave out near-duplicates.
+pub fn skip_duplicates_enabled(conn: &Connection) -> SqlResult<bool> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![SKIP_DUPLICATES_SETTING],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value.is_some_and(|v| v.trim() == "true"))
+}
+
+/// Groups of two or more wallpapers whose hashes are within `max_distance`
+/// of another in the group. Each group starts with the one best worth
+/// keeping: a favorite if there is one, then the largest.
+pub fn find_duplicates(conn: &Connection, max_distance: u32) -> SqlResult<Vec<Vec<Wallpaper>>> {
+    let hashes = stored_hashes(conn)?;
+
+    // Union-find over every pair close enough to match.
+    let mut parent: Vec<usize> = (0..hashes.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            if hamming(hashes[i].1, hashes[j].1) <= max_distance {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<&str>> = HashMap::new();
+    for (i, (id, _)) in hashes.iter().enumerate() {
+        let group = root(&mut parent, i);
+        groups.entry(group).or_default().push(id);
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {WALLPAPER_COLUMNS} FROM wallpapers w WHERE w.id = ?1"
+    ))?;
+    let mut duplicates = Vec::new();
+    for ids in groups.into_values().filter(|ids| ids.len() > 1) {
+        let mut group = ids
+            .into_iter()
+            .map(|id| stmt.query_row(params![id], wallpaper_from_row))
+            .collect::<SqlResult<Vec<_>>>()?;
+        group.sort_by(|a, b| {
+            b.is_favorite
+                .cmp(&a.is_favorite)
+                .then((b.width as i64 * b.height as i64).cmp(&(a.width as i64 * a.height as i64)))
+                .then(a.id.cmp(&b.id))
+        });
+        duplicates.push(group);
+    }
+    duplicates.sort_by(|a, b| a[0].id.cmp(&b[0].id));
+    Ok(duplicates)
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn find_duplicates_command(
+    db: State<'_, DbPool>,
+    max_distance: Option<u32>,
+) -> Result<Vec<Vec<Wallpaper>>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    find_duplicates(&conn, max_distance.unwrap_or(DEFAULT_MAX_DISTANCE)).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::db_services::add_favorite;
+    use crate::services::migrations::migrate;
+
+    #[test]
+    fn groups_near_duplicates_keeping_the_best_first() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO wallpapers (id, mongo_id, title, url, thumbnail, width, height, tags)
+            VALUES
+                ('small', 'm1', 'Small', 'a', '', 1280, 720, ''),
+                ('large', 'm2', 'Large', 'b', '', 3840, 2160, ''),
+                ('fav', 'm3', 'Fav', 'c', '', 1920, 1080, ''),
+                ('other', 'm4', 'Other', 'd', '', 1920, 1080, ''),
+                ('unhashed', 'm5', 'Unhashed', 'e', '', 1920, 1080, '');
+            ",
+        )
+        .unwrap();
+        let base = 0xf0f0_1234_5678_9abc_u64;
+        save_hash(&conn, "small", base).unwrap();
+        save_hash(&conn, "large", base ^ 0b101).unwrap();
+        // Two bits from `large`, four from `small`: chained into one group.
+        save_hash(&conn, "fav", base ^ 0b101 ^ 0b11000).unwrap();
+        save_hash(&conn, "other", !base).unwrap();
+        add_favorite(&conn, "fav").unwrap();
+
+        assert_eq!(load_hash(&conn, "small").unwrap(), Some(base));
+        assert_eq!(load_hash(&conn, "unhashed").unwrap(), None);
+
+        let groups = find_duplicates(&conn, 3).unwrap();
+        assert_eq!(groups.len(), 1);
+        let ids: Vec<&str> = groups[0].iter().map(|w| w.id.as_str()).collect();
+        assert_eq!(ids, vec!["fav", "large", "small"]);
+
+        assert_eq!(
+            find_duplicate_of(&conn, base ^ 1, 3, None).unwrap(),
+            Some("small".to_string())
+        );
+        assert_eq!(
+            find_duplicate_of(&conn, base, 1, Some("small")).unwrap(),
+            None
+        );
+        assert!(!skip_duplicates_enabled(&conn).unwrap());
+
+        // Unavailable and blocked wallpapers don't count.
+        conn.execute(
+            "UPDATE wallpapers SET is_available = 0 WHERE id = 'small'",
+            [],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO blocklist (wallpaper_id) VALUES ('fav')", [])
+            .unwrap();
+        assert_eq!(
+            find_duplicate_of(&conn, base ^ 1, 3, None).unwrap(),
+            Some("large".to_string())
+        );
+        assert!(find_duplicates(&conn, 3).unwrap().is_empty());
+    }
+}
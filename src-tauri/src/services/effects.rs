@@ -0,0 +1,208 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// One step of an effects chain. Amounts are fractions: `0` leaves the image
+/// alone, and signed amounts go down to `-1`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Effect {
+    /// Gaussian blur; `sigma` is in pixels of the output image.
+    Blur {
+        sigma: f32,
+    },
+    /// Scales every channel by `1 + amount`; negative amounts dim.
+    Brightness {
+        amount: f32,
+    },
+    Contrast {
+        amount: f32,
+    },
+    /// `-1` is fully grey, positive amounts boost color.
+    Saturation {
+        amount: f32,
+    },
+    Grayscale,
+    Sepia {
+        amount: f32,
+    },
+    /// Blends towards `color` (`#rrggbb`) by `amount`.
+    Tint {
+        color: String,
+        amount: f32,
+    },
+    /// Darkens towards the corners by up to `amount`.
+    Vignette {
+        amount: f32,
+    },
+}
+
+/// Runs `effects` over `img` in order.
+pub fn apply_effects(img: DynamicImage, effects: &[Effect]) -> DynamicImage {
+    effects.iter().fold(img, |img, effect| match effect {
+        Effect::Blur { sigma } if *sigma > 0.0 => img.blur(*sigma),
+        Effect::Blur { .. } => img,
+        _ => DynamicImage::ImageRgba8(apply_pixel_effect(img.to_rgba8(), effect)),
+    })
+}
+
+fn apply_pixel_effect(mut img: RgbaImage, effect: &Effect) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let tint = match effect {
+        Effect::Tint { color, .. } => parse_hex_color(color),
+        _ => None,
+    };
+
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let rgb = [f32::from(r), f32::from(g), f32::from(b)];
+        let luma = 0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2];
+
+        let out = match effect {
+            Effect::Blur { .. } => rgb,
+            Effect::Brightness { amount } => rgb.map(|c| c * (1.0 + amount.max(-1.0))),
+            Effect::Contrast { amount } => {
+                rgb.map(|c| (c - 128.0) * (1.0 + amount.max(-1.0)) + 128.0)
+            }
+            Effect::Saturation { amount } => {
+                rgb.map(|c| luma + (c - luma) * (1.0 + amount.max(-1.0)))
+            }
+            Effect::Grayscale => [luma; 3],
+            Effect::Sepia { amount } => {
+                let sepia = [
+                    0.393 * rgb[0] + 0.769 * rgb[1] + 0.189 * rgb[2],
+                    0.349 * rgb[0] + 0.686 * rgb[1] + 0.168 * rgb[2],
+                    0.272 * rgb[0] + 0.534 * rgb[1] + 0.131 * rgb[2],
+                ];
+                mix(rgb, sepia, *amount)
+            }
+            Effect::Tint { amount, .. } => match tint {
+                Some(color) => mix(rgb, color, *amount),
+                None => rgb,
+            },
+            Effect::Vignette { amount } => {
+                // Distance from the center, 0 there and 1 in the corners.
+                let dx = (x as f32 + 0.5) / width as f32 - 0.5;
+                let dy = (y as f32 + 0.5) / height as f32 - 0.5;
+                let distance = (dx * dx + dy * dy).sqrt() / 0.5_f32.sqrt();
+                let falloff = smoothstep(0.4, 1.0, distance);
+                rgb.map(|c| c * (1.0 - amount.clamp(0.0, 1.0) * falloff))
+            }
+        };
+
+        *pixel = Rgba([
+            to_channel(out[0]),
+            to_channel(out[1]),
+            to_channel(out[2]),
+            a,
+        ]);
+    }
+    img
+}
+
+fn mix(from: [f32; 3], to: [f32; 3], amount: f32) -> [f32; 3] {
+    let t = amount.clamp(0.0, 1.0);
+    [
+        from[0] + (to[0] - from[0]) * t,
+        from[1] + (to[1] - from[1]) * t,
+        from[2] + (to[2] - from[2]) * t,
+    ]
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn to_channel(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+/// Parses `#rrggbb` (the `#` is optional).
+pub fn parse_hex_color(value: &str) -> Option<[f32; 3]> {
+    let hex = value.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(f32::from);
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Stable fingerprint of `parts`, for naming cache files. FNV-1a, so it
+/// doesn't change between builds the way `std`'s hasher may.
+pub fn fingerprint(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(rgb: [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            4,
+            4,
+            Rgba([rgb[0], rgb[1], rgb[2], 255]),
+        ))
+    }
+
+    fn first_pixel(img: &DynamicImage) -> [u8; 4] {
+        img.to_rgba8().get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn parses_chains_from_json() {
+        let chain: Vec<Effect> = serde_json::from_str(
+            r##"[{"type":"blur","sigma":2},{"type":"tint","color":"#ff8800","amount":0.5},{"type":"grayscale"}]"##,
+        )
+        .unwrap();
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain[2], Effect::Grayscale);
+    }
+
+    #[test]
+    fn color_effects() {
+        let grey = apply_effects(solid([200, 100, 50]), &[Effect::Grayscale]);
+        let [r, g, b, a] = first_pixel(&grey);
+        assert!(r == g && g == b);
+        assert_eq!(a, 255);
+
+        let dim = apply_effects(
+            solid([200, 100, 50]),
+            &[Effect::Brightness { amount: -0.5 }],
+        );
+        assert_eq!(first_pixel(&dim), [100, 50, 25, 255]);
+
+        let tinted = apply_effects(
+            solid([0, 0, 0]),
+            &[Effect::Tint {
+                color: "#ff0000".into(),
+                amount: 1.0,
+            }],
+        );
+        assert_eq!(first_pixel(&tinted), [255, 0, 0, 255]);
+
+        let flat = apply_effects(solid([10, 200, 90]), &[Effect::Contrast { amount: -1.0 }]);
+        assert_eq!(first_pixel(&flat), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn vignette_darkens_corners_only() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(9, 9, Rgba([200, 200, 200, 255])));
+        let out = apply_effects(img, &[Effect::Vignette { amount: 1.0 }]).to_rgba8();
+        assert_eq!(out.get_pixel(4, 4).0[0], 200);
+        assert!(out.get_pixel(0, 0).0[0] < 50);
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        assert_eq!(fingerprint(&[]), "cbf29ce484222325");
+        assert_ne!(fingerprint(&["ab", "c"]), fingerprint(&["a", "bc"]));
+    }
+}
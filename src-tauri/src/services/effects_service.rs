@@ -0,0 +1,183 @@
+use crate::services::db_services::{set_setting, DbPool};
+use crate::services::effects::Effect;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use tauri::State;
+
+fn parse_chain(json: &str) -> Vec<Effect> {
+    serde_json::from_str(json).unwrap_or_default()
+}
+
+fn chain_json(effects: &[Effect]) -> String {
+    serde_json::to_string(effects).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// The chain applied to wallpapers without one of their own, kept in the
+/// `effects` setting.
+pub fn get_global_effects(conn: &Connection) -> SqlResult<Vec<Effect>> {
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = 'effects'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(json.as_deref().map(parse_chain).unwrap_or_default())
+}
+
+pub fn set_global_effects(conn: &Connection, effects: &[Effect]) -> SqlResult<()> {
+    set_setting(conn, "effects", &chain_json(effects), "wallpaper")
+}
+
+pub fn get_wallpaper_effects(
+    conn: &Connection,
+    wallpaper_id: &str,
+) -> SqlResult<Option<Vec<Effect>>> {
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT effects FROM wallpaper_effects WHERE wallpaper_id = ?1",
+            params![wallpaper_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(json.as_deref().map(parse_chain))
+}
+
+/// Gives a wallpaper its own chain, or removes it with `None`. An empty chain
+/// is kept: it means "no effects" even when the global chain has some.
+/// Returns whether the wallpaper exists; nothing is stored if it doesn't.
+pub fn set_wallpaper_effects(
+    conn: &Connection,
+    wallpaper_id: &str,
+    effects: Option<&[Effect]>,
+) -> SqlResult<bool> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM wallpapers WHERE id = ?1)",
+        params![wallpaper_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(false);
+    }
+    match effects {
+        Some(effects) => conn.execute(
+            r#"
+            INSERT INTO wallpaper_effects (wallpaper_id, effects) VALUES (?1, ?2)
+            ON CONFLICT(wallpaper_id) DO UPDATE SET effects = excluded.effects
+            "#,
+            params![wallpaper_id, chain_json(effects)],
+        )?,
+        None => conn.execute(
+            "DELETE FROM wallpaper_effects WHERE wallpaper_id = ?1",
+            params![wallpaper_id],
+        )?,
+    };
+    Ok(true)
+}
+
+/// The chain to render a wallpaper with: its own if it has one, else the
+/// chain of the rotation applying it, else the global one.
+pub fn resolve_effects(
+    conn: &Connection,
+    wallpaper_id: Option<&str>,
+    schedule: Option<&[Effect]>,
+) -> SqlResult<Vec<Effect>> {
+    if let Some(id) = wallpaper_id {
+        if let Some(own) = get_wallpaper_effects(conn, id)? {
+            return Ok(own);
+        }
+    }
+    match schedule {
+        Some(schedule) => Ok(schedule.to_vec()),
+        None => get_global_effects(conn),
+    }
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn get_global_effects_command(db: State<'_, DbPool>) -> Result<Vec<Effect>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    get_global_effects(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_global_effects_command(
+    db: State<'_, DbPool>,
+    effects: Vec<Effect>,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    set_global_effects(&conn, &effects).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_wallpaper_effects_command(
+    db: State<'_, DbPool>,
+    id: String,
+) -> Result<Option<Vec<Effect>>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    get_wallpaper_effects(&conn, &id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_wallpaper_effects_command(
+    db: State<'_, DbPool>,
+    id: String,
+    effects: Option<Vec<Effect>>,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    if set_wallpaper_effects(&conn, &id, effects.as_deref()).map_err(|e| e.to_string())? {
+        Ok(())
+    } else {
+        Err(format!("No wallpaper with id {}", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::migrations::migrate;
+
+    #[test]
+    fn wallpaper_chain_beats_schedule_beats_global() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        conn.execute(
+            "INSERT INTO wallpapers (id, mongo_id, title, url, thumbnail, width, height, tags)
+             VALUES ('w1', 'm1', 'W1', '', '', 1920, 1080, '')",
+            [],
+        )
+        .unwrap();
+        let global = vec![Effect::Grayscale];
+        let schedule = vec![Effect::Blur { sigma: 3.0 }];
+        let own = vec![Effect::Vignette { amount: 0.4 }];
+
+        assert!(resolve_effects(&conn, Some("w1"), None).unwrap().is_empty());
+        set_global_effects(&conn, &global).unwrap();
+        assert_eq!(resolve_effects(&conn, Some("w1"), None).unwrap(), global);
+        assert_eq!(
+            resolve_effects(&conn, Some("w1"), Some(&schedule)).unwrap(),
+            schedule
+        );
+
+        assert!(!set_wallpaper_effects(&conn, "missing", Some(&own)).unwrap());
+        let stored: u32 = conn
+            .query_row("SELECT COUNT(*) FROM wallpaper_effects", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(stored, 0);
+
+        assert!(set_wallpaper_effects(&conn, "w1", Some(&own)).unwrap());
+        assert_eq!(
+            resolve_effects(&conn, Some("w1"), Some(&schedule)).unwrap(),
+            own
+        );
+        set_wallpaper_effects(&conn, "w1", Some(&[])).unwrap();
+        assert!(resolve_effects(&conn, Some("w1"), None).unwrap().is_empty());
+
+        set_wallpaper_effects(&conn, "w1", None).unwrap();
+        assert_eq!(resolve_effects(&conn, Some("w1"), None).unwrap(), global);
+    }
+}
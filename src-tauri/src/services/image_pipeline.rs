@@ -0,0 +1,537 @@
+use crate::services::codecs::{decode_heavy, SourceFormat};
+use crate::services::db_services::Settings;
+use crate::services::effects::{apply_effects, fingerprint, Effect};
+use crate::services::overlay::{render_overlay, Overlay};
+use crate::services::variant_service::Resolution;
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    imageops::FilterType,
+    DynamicImage, ImageDecoder, ImageReader,
+};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+
+/// Quality used when the `imageQuality` setting is missing or invalid.
+pub const DEFAULT_QUALITY: u8 = 90;
+
+/// Encoding written by the pipeline.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            "png" => Some(OutputFormat::Png),
+            "webp" => Some(OutputFormat::Webp),
+            _ => None,
+        }
+    }
+
+    /// The format a file name's extension asks for.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(OutputFormat::parse)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Resampling filter used when scaling, fastest first.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleFilter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    #[default]
+    Lanczos,
+}
+
+impl ScaleFilter {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "nearest" => Some(ScaleFilter::Nearest),
+            "bilinear" => Some(ScaleFilter::Bilinear),
+            "bicubic" => Some(ScaleFilter::Bicubic),
+            "lanczos" => Some(ScaleFilter::Lanczos),
+            _ => None,
+        }
+    }
+
+    fn filter_type(self) -> FilterType {
+        match self {
+            ScaleFilter::Nearest => FilterType::Nearest,
+            ScaleFilter::Bilinear => FilterType::Triangle,
+            ScaleFilter::Bicubic => FilterType::CatmullRom,
+            ScaleFilter::Lanczos => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// How an image is fitted to the target resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// Scale so the image just covers the target, keeping all of it.
+    Cover,
+    /// Crop to the target's aspect ratio, then scale to the target.
+    Fill,
+}
+
+/// A region of an image as fractions of its width and height, so the same
+/// crop applies to every size of a picture.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CropRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Where a [`Fit::Fill`] crop is placed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Focus {
+    /// Around the most detailed part of the image.
+    #[default]
+    Auto,
+    /// As close to centered on this point (fractions of width and height) as
+    /// the image edges allow.
+    Point { x: f64, y: f64 },
+    /// Exactly this region, e.g. one chosen earlier.
+    Region(CropRect),
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    /// `None` keeps the decoded size.
+    pub target: Option<Resolution>,
+    pub fit: Fit,
+    pub focus: Focus,
+    pub filter: ScaleFilter,
+    pub format: OutputFormat,
+    /// 1–100. Used by JPEG and WebP; PNG is always lossless.
+    pub quality: u8,
+    /// Run after fitting, on the output-sized image.
+    pub effects: Vec<Effect>,
+    /// Drawn last, over the effects.
+    pub overlay: Option<Overlay>,
+}
+
+impl PipelineOptions {
+    /// Options from the `imageFormat`, `imageQuality` and `scaleFilter`
+    /// settings, falling back to the defaults for missing or bad values.
+    pub fn from_settings(settings: &Settings, target: Option<Resolution>, fit: Fit) -> Self {
+        let get = |key: &str| settings.get(key).map(String::as_str).unwrap_or_default();
+        PipelineOptions {
+            target,
+            fit,
+            focus: Focus::Auto,
+            filter: ScaleFilter::parse(get("scaleFilter")).unwrap_or_default(),
+            format: OutputFormat::parse(get("imageFormat")).unwrap_or_default(),
+            quality: get("imageQuality")
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|q| (1..=100).contains(q))
+                .unwrap_or(DEFAULT_QUALITY),
+            effects: Vec::new(),
+            overlay: None,
+        }
+    }
+
+    /// Hash of everything that affects the output, for naming cached renders.
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&[
+            &format!("{:?}", self.target),
+            &format!("{:?}", self.fit),
+            &format!("{:?}", self.focus),
+            &format!("{:?}", self.filter),
+            &format!("{:?}", self.format),
+            &self.quality.to_string(),
+            &serde_json::to_string(&self.effects).unwrap_or_default(),
+            &self
+                .overlay
+                .as_ref()
+                .map(Overlay::fingerprint)
+                .unwrap_or_default(),
+        ])
+    }
+}
+
+/// Output of [`process`].
+pub struct Processed {
+    pub bytes: Vec<u8>,
+    /// The region a [`Fit::Fill`] crop kept.
+    pub crop: Option<CropRect>,
+}
+
+/// Decodes `bytes`, fits the image to the target and re-encodes it. Only
+/// pixels are carried over, so EXIF and other metadata are dropped; the EXIF
+/// orientation is applied first so the result stays upright. Images are
+/// never scaled up.
+pub fn process(bytes: &[u8], options: &PipelineOptions) -> Result<Processed, String> {
+    let mut img = decode(bytes)?;
+    let mut crop = None;
+
+    if let Some(target) = options.target {
+        if options.fit == Fit::Fill {
+            let rect = choose_crop(&img, target, options.focus);
+            img = apply_crop(&img, rect);
+            crop = Some(rect);
+        }
+        img = scale_to_cover(img, target, options.filter);
+    }
+    if !options.effects.is_empty() {
+        img = apply_effects(img, &options.effects);
+    }
+    if let Some(overlay) = &options.overlay {
+        img = render_overlay(img, overlay);
+    }
+
+    Ok(Processed {
+        bytes: encode(&img, options.format, options.quality)?,
+        crop,
+    })
+}
+
+/// Decodes any supported format, applying its EXIF orientation. Animated
+/// images give their first frame.
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage, String> {
+    if let Some(format) = SourceFormat::detect(bytes).filter(|f| !f.is_builtin()) {
+        return decode_heavy(format, bytes);
+    }
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let mut decoder = reader
+        .into_decoder()
+        .map_err(|e| format!("Unsupported image: {e}"))?;
+    let orientation = decoder.orientation().map_err(|e| e.to_string())?;
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// Side of the grid the saliency search runs on. Detail finer than this
+/// doesn't move the crop, and it keeps the search cheap for 8K originals.
+const SALIENCY_GRID: u32 = 64;
+
+/// The largest region of `img` with the aspect ratio of `target`, placed
+/// according to `focus`. A `Focus::Region` is used as given.
+pub fn choose_crop(img: &DynamicImage, target: Resolution, focus: Focus) -> CropRect {
+    let image_aspect = f64::from(img.width()) / f64::from(img.height());
+    let target_aspect = f64::from(target.width) / f64::from(target.height);
+
+    // Fractions of the image the crop spans; one of them is always 1.
+    let (width, height) = if image_aspect > target_aspect {
+        (target_aspect / image_aspect, 1.0)
+    } else {
+        (1.0, image_aspect / target_aspect)
+    };
+    let place = |center: f64, span: f64| (center - span / 2.0).clamp(0.0, 1.0 - span);
+
+    match focus {
+        Focus::Region(rect) => rect,
+        Focus::Point { x, y } => CropRect {
+            x: place(x, width),
+            y: place(y, height),
+            width,
+            height,
+        },
+        Focus::Auto => {
+            let (x, y) = salient_center(img, width, height);
+            CropRect {
+                x: place(x, width),
+                y: place(y, height),
+                width,
+                height,
+            }
+        }
+    }
+}
+
+/// Center of the `width` x `height` (fractions) window with the most edge
+/// detail, a cheap stand-in for saliency. The window only ever slides along
+/// one axis. A mild pull towards the middle breaks ties on flat images.
+fn salient_center(img: &DynamicImage, width: f64, height: f64) -> (f64, f64) {
+    let small = img
+        .resize(SALIENCY_GRID, SALIENCY_GRID, FilterType::Triangle)
+        .to_luma8();
+    let (cols, rows) = small.dimensions();
+    let horizontal = width < 1.0;
+
+    // Edge energy summed across the axis the window doesn't move along.
+    let lanes = if horizontal { cols } else { rows };
+    let mut energy = vec![0.0f64; lanes as usize];
+    for y in 0..rows {
+        for x in 0..cols {
+            let here = f64::from(small.get_pixel(x, y)[0]);
+            let right = f64::from(small.get_pixel((x + 1).min(cols - 1), y)[0]);
+            let below = f64::from(small.get_pixel(x, (y + 1).min(rows - 1))[0]);
+            let lane = if horizontal { x } else { y };
+            energy[lane as usize] += (here - right).abs() + (here - below).abs();
+        }
+    }
+
+    let span = if horizontal { width } else { height };
+    let window = ((span * f64::from(lanes)).round() as usize).clamp(1, lanes as usize);
+    let mut best = (f64::MIN, 0.5);
+    for start in 0..=(lanes as usize - window) {
+        let center = (start as f64 + window as f64 / 2.0) / f64::from(lanes);
+        let detail: f64 = energy[start..start + window].iter().sum();
+        let score = detail * (1.0 - 0.1 * (center - 0.5).abs());
+        if score > best.0 {
+            best = (score, center);
+        }
+    }
+
+    if horizontal {
+        (best.1, 0.5)
+    } else {
+        (0.5, best.1)
+    }
+}
+
+/// Cuts `rect` out of `img`.
+pub fn apply_crop(img: &DynamicImage, rect: CropRect) -> DynamicImage {
+    let (width, height) = (f64::from(img.width()), f64::from(img.height()));
+    let x = ((rect.x * width).round() as u32).min(img.width() - 1);
+    let y = ((rect.y * height).round() as u32).min(img.height() - 1);
+    let crop_width = ((rect.width * width).round() as u32).clamp(1, img.width() - x);
+    let crop_height = ((rect.height * height).round() as u32).clamp(1, img.height() - y);
+    img.crop_imm(x, y, crop_width, crop_height)
+}
+
+/// Shrinks `img` so it just covers `target`, keeping its aspect ratio.
+/// Images already at or below the target are returned as is.
+pub fn scale_to_cover(img: DynamicImage, target: Resolution, filter: ScaleFilter) -> DynamicImage {
+    let scale = f64::max(
+        f64::from(target.width) / f64::from(img.width()),
+        f64::from(target.height) / f64::from(img.height()),
+    );
+    if scale >= 1.0 {
+        return img;
+    }
+
+    let width = ((f64::from(img.width()) * scale).round() as u32).max(1);
+    let height = ((f64::from(img.height()) * scale).round() as u32).max(1);
+    img.resize_exact(width, height, filter.filter_type())
+}
+
+pub fn encode(img: &DynamicImage, format: OutputFormat, quality: u8) -> Result<Vec<u8>, String> {
+    let quality = quality.clamp(1, 100);
+    let mut out = Vec::new();
+
+    match format {
+        OutputFormat::Jpeg => {
+            // JPEG has no alpha channel.
+            JpegEncoder::new_with_quality(&mut out, quality)
+                .encode_image(&DynamicImage::ImageRgb8(img.to_rgb8()))
+                .map_err(|e| e.to_string())?;
+        }
+        OutputFormat::Png => {
+            img.write_with_encoder(PngEncoder::new(&mut out))
+                .map_err(|e| e.to_string())?;
+        }
+        OutputFormat::Webp => {
+            let rgba = img.to_rgba8();
+            let encoded = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+                .encode(f32::from(quality));
+            out.extend_from_slice(&encoded);
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/pipeline")
+            .join(name)
+    }
+
+    /// Compares `actual` with the golden PNG `name`, allowing `tolerance` per
+    /// channel for lossy formats. Set `UPDATE_GOLDEN=1` to rewrite goldens
+    /// from the exact (zero-tolerance) comparisons.
+    fn assert_golden(actual: &[u8], name: &str, tolerance: u8) {
+        let actual = image::load_from_memory(actual).unwrap().to_rgba8();
+        let path = fixture(name);
+        if tolerance == 0 && std::env::var_os("UPDATE_GOLDEN").is_some() {
+            actual.save_with_format(&path, ImageFormat::Png).unwrap();
+        }
+
+        let expected = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(actual.dimensions(), expected.dimensions(), "{}", name);
+        let worst = actual
+            .as_raw()
+            .iter()
+            .zip(expected.as_raw())
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap_or(0);
+        assert!(worst <= tolerance, "{}: off by {}", name, worst);
+    }
+
+    fn options(target: &str, fit: Fit, format: OutputFormat) -> PipelineOptions {
+        PipelineOptions {
+            target: Resolution::parse(target),
+            fit,
+            focus: Focus::Auto,
+            filter: ScaleFilter::Lanczos,
+            format,
+            quality: 90,
+            effects: Vec::new(),
+            overlay: None,
+        }
+    }
+
+    fn gradient() -> Vec<u8> {
+        std::fs::read(fixture("gradient_64x48.png")).unwrap()
+    }
+
+    #[test]
+    fn fill_crops_then_scales() {
+        let out = process(&gradient(), &options("32x18", Fit::Fill, OutputFormat::Png))
+            .unwrap()
+            .bytes;
+        assert_eq!(image::guess_format(&out).unwrap(), ImageFormat::Png);
+        assert_golden(&out, "gradient_fill_32x18.png", 0);
+    }
+
+    #[test]
+    fn cover_keeps_aspect() {
+        let out = process(
+            &gradient(),
+            &options("16x16", Fit::Cover, OutputFormat::Png),
+        )
+        .unwrap()
+        .bytes;
+        assert_golden(&out, "gradient_cover_16x16.png", 0);
+    }
+
+    #[test]
+    fn lossy_formats_stay_close_to_golden() {
+        for format in [OutputFormat::Jpeg, OutputFormat::Webp] {
+            let out = process(&gradient(), &options("32x18", Fit::Fill, format))
+                .unwrap()
+                .bytes;
+            assert_eq!(
+                OutputFormat::parse(&format!("{:?}", image::guess_format(&out).unwrap())),
+                Some(format)
+            );
+            assert_golden(&out, "gradient_fill_32x18.png", 24);
+        }
+    }
+
+    #[test]
+    fn never_upscales() {
+        let out = process(
+            &gradient(),
+            &options("640x480", Fit::Cover, OutputFormat::Png),
+        )
+        .unwrap()
+        .bytes;
+        let img = image::load_from_memory(&out).unwrap();
+        assert_eq!((img.width(), img.height()), (64, 48));
+    }
+
+    #[test]
+    fn strips_exif_after_applying_orientation() {
+        // 8x4 JPEG tagged with EXIF orientation 6 (rotate 90° clockwise).
+        let input = std::fs::read(fixture("rotated_exif.jpg")).unwrap();
+        assert!(input.windows(6).any(|w| w == b"Exif\0\0"));
+
+        let options = PipelineOptions {
+            target: None,
+            ..options("1x1", Fit::Cover, OutputFormat::Jpeg)
+        };
+        let out = process(&input, &options).unwrap().bytes;
+        assert!(!out.windows(6).any(|w| w == b"Exif\0\0"));
+
+        let img = image::load_from_memory(&out).unwrap();
+        assert_eq!((img.width(), img.height()), (4, 8));
+    }
+
+    #[test]
+    fn reads_settings_with_fallbacks() {
+        let mut settings = Settings::new();
+        settings.insert("imageFormat".into(), "webp".into());
+        settings.insert("imageQuality".into(), "250".into());
+        let options = PipelineOptions::from_settings(&settings, None, Fit::Cover);
+        assert_eq!(options.format, OutputFormat::Webp);
+        assert_eq!(options.quality, DEFAULT_QUALITY);
+        assert_eq!(options.filter, ScaleFilter::Lanczos);
+    }
+
+    /// 40x10 image, flat grey except for a checkerboard in columns 28..36.
+    fn busy_right() -> DynamicImage {
+        DynamicImage::ImageLuma8(image::ImageBuffer::from_fn(40, 10, |x, y| {
+            if (28..36).contains(&x) && (x + y) % 2 == 0 {
+                image::Luma([255])
+            } else {
+                image::Luma([128])
+            }
+        }))
+    }
+
+    #[test]
+    fn auto_crop_follows_detail() {
+        let target = Resolution::parse("10x10").unwrap();
+        let rect = choose_crop(&busy_right(), target, Focus::Auto);
+        assert_eq!((rect.width, rect.height), (0.25, 1.0));
+        // The 10px window lands over columns 28..36.
+        let left = rect.x * 40.0;
+        assert!((26.0..=28.0).contains(&left), "window starts at {}", left);
+    }
+
+    #[test]
+    fn focal_points_clamp_to_edges() {
+        let target = Resolution::parse("10x10").unwrap();
+        let img = busy_right();
+
+        let rect = choose_crop(&img, target, Focus::Point { x: 0.5, y: 0.5 });
+        assert_eq!(rect.x, 0.375);
+        let rect = choose_crop(&img, target, Focus::Point { x: 0.02, y: 0.5 });
+        assert_eq!(rect.x, 0.0);
+        let rect = choose_crop(&img, target, Focus::Point { x: 1.0, y: 0.5 });
+        assert_eq!(rect.x, 0.75);
+    }
+
+    #[test]
+    fn stored_regions_are_reproduced() {
+        let mut png = Vec::new();
+        busy_right()
+            .write_with_encoder(PngEncoder::new(&mut png))
+            .unwrap();
+        let first = process(&png, &options("10x10", Fit::Fill, OutputFormat::Png)).unwrap();
+        let crop = first.crop.unwrap();
+
+        let again = PipelineOptions {
+            focus: Focus::Region(crop),
+            ..options("10x10", Fit::Fill, OutputFormat::Png)
+        };
+        let second = process(&png, &again).unwrap();
+        assert_eq!(second.crop, Some(crop));
+        assert_eq!(second.bytes, first.bytes);
+    }
+}
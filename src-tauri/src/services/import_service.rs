@@ -0,0 +1,433 @@
+use crate::services::codecs::SourceFormat;
+use crate::services::db_services::{
+    is_blocked, parse_tags, set_wallpaper_tags, DbPool, WallpaperSource,
+};
+use crate::services::duplicate_service::{
+    find_duplicate_of, save_hash, skip_duplicates_enabled, DEFAULT_MAX_DISTANCE,
+};
+use crate::services::image_pipeline::decode;
+use crate::services::metadata::read_metadata;
+use crate::services::metadata_service::save_metadata;
+use crate::services::palette_service::ensure_palette;
+use crate::services::phash::dhash;
+use crate::services::thumbnail_service::{ensure_thumbnails_of, remove_thumbnails, thumbnail_dir};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+use walkdir::WalkDir;
+
+/// A file an import left out, and why.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub imported: u32,
+    /// Files imported before, refreshed.
+    pub updated: u32,
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// Sent with `import-progress` after each file.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ImportProgress {
+    pub processed: u32,
+    pub total: u32,
+    pub imported: u32,
+    pub updated: u32,
+    pub skipped: u32,
+}
+
+/// What importing one file did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileOutcome {
+    Added(String),
+    Updated(String),
+    Skipped(String),
+}
+
+/// Whether `path` has the extension of a format this build can decode.
+pub fn is_supported_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(SourceFormat::from_extension)
+        .is_some_and(SourceFormat::is_enabled)
+}
+
+fn is_hidden(name: &std::ffi::OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+/// Images under `root`, sorted, and the other files found next to them.
+/// Hidden files and folders are left out entirely.
+pub fn scan_images(root: &Path, recursive: bool) -> (Vec<PathBuf>, Vec<SkippedFile>) {
+    let mut images = Vec::new();
+    let mut skipped = Vec::new();
+    let walker = WalkDir::new(root)
+        .max_depth(if recursive { usize::MAX } else { 1 })
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.file_name()));
+
+    for entry in walker {
+        match entry {
+            Ok(entry) if entry.file_type().is_file() => {
+                if is_supported_image(entry.path()) {
+                    images.push(entry.into_path());
+                } else {
+                    skipped.push(SkippedFile {
+                        path: entry.path().to_string_lossy().into_owned(),
+                        reason: "Unsupported file type".to_string(),
+                    });
+                }
+            }
+            Ok(_) => {}
+            Err(e) => skipped.push(SkippedFile {
+                path: e
+                    .path()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                reason: e.to_string(),
+            }),
+        }
+    }
+    (images, skipped)
+}
+
+/// Tags for a file: the names of the folders from `root` down to it,
+/// `root` included.
+pub fn folder_tags(root: &Path, file: &Path) -> String {
+    let mut names: Vec<String> = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .into_iter()
+        .collect();
+    if let Some(folders) = file.parent().and_then(|p| p.strip_prefix(root).ok()) {
+        names.extend(
+            folders
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+        );
+    }
+    names.retain(|name| !name.trim().is_empty());
+    names.join(", ")
+}
+
+/// `mountain_lake-01.jpg` becomes `mountain lake 01`.
+fn title_from(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().replace(['_', '-'], " "))
+        .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default()
+}
+
+pub fn get_wallpaper_id_by_path(conn: &Connection, path: &str) -> SqlResult<Option<String>> {
+    conn.query_row(
+        "SELECT id FROM wallpapers WHERE file_path = ?1",
+        params![path],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Adds the image at `path` to the library, or refreshes it if it was
+/// imported before. Tags come from the folders between `root` and the file;
+/// tags a refreshed wallpaper already has are kept, while its thumbnails and
+/// palette are made again. Thumbnails, palette and hash are made straight
+/// away when `thumbnails` is given, since there is no remote thumbnail to
+/// show meanwhile. The row is written in one transaction. Blocked files are
+/// skipped.
+pub fn import_file(
+    conn: &Connection,
+    root: &Path,
+    path: &Path,
+    thumbnails: Option<&Path>,
+    skip_duplicates: bool,
+) -> Result<FileOutcome, String> {
+    let file_path = path.to_string_lossy().into_owned();
+    if is_blocked(conn, None, Some(&file_path)).map_err(|e| e.to_string())? {
+        return Ok(FileOutcome::Skipped("Blocked".into()));
+    }
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => return Ok(FileOutcome::Skipped(format!("Unreadable: {e}"))),
+    };
+    let img = match decode(&bytes) {
+        Ok(img) => img,
+        Err(e) => return Ok(FileOutcome::Skipped(e)),
+    };
+    let hash = dhash(&img);
+    // Embedded keywords become tags next to the folder names; a comma in
+    // one would split it in two.
+    let metadata = read_metadata(&bytes);
+    let keywords: Vec<String> = metadata
+        .keywords
+        .iter()
+        .map(|k| k.replace(',', " "))
+        .collect();
+    let tags = parse_tags(&format!(
+        "{},{}",
+        folder_tags(root, path),
+        keywords.join(",")
+    ))
+    .join(", ");
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let existing = get_wallpaper_id_by_path(&tx, &file_path).map_err(|e| e.to_string())?;
+
+    let (id, outcome) = match existing {
+        Some(id) => {
+            // The file's contents may have changed, so its thumbnails and
+            // palette are stale.
+            if let Some(dir) = thumbnails {
+                remove_thumbnails(dir, &id);
+            }
+            let old_tags: String = tx
+                .query_row(
+                    "SELECT COALESCE(tags, '') FROM wallpapers WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            let tags = parse_tags(&format!("{old_tags},{tags}")).join(", ");
+            tx.execute(
+                "UPDATE wallpapers
+                 SET width = ?2, height = ?3, tags = ?4, phash = ?5, is_available = 1,
+                     dominant_color = NULL, accent_color = NULL
+                 WHERE id = ?1",
+                params![id, img.width(), img.height(), tags, hash as i64],
+            )
+            .map_err(|e| e.to_string())?;
+            tx.execute(
+                "DELETE FROM wallpaper_colors WHERE wallpaper_id = ?1",
+                params![id],
+            )
+            .map_err(|e| e.to_string())?;
+            set_wallpaper_tags(&tx, &id, &tags).map_err(|e| e.to_string())?;
+            (id.clone(), FileOutcome::Updated(id))
+        }
+        None => {
+            if skip_duplicates {
+                if let Some(original) = find_duplicate_of(&tx, hash, DEFAULT_MAX_DISTANCE, None)
+                    .map_err(|e| e.to_string())?
+                {
+                    return Ok(FileOutcome::Skipped(format!("Duplicate of {original}")));
+                }
+            }
+            let id = uuid::Uuid::new_v4().to_string();
+            tx.execute(
+                "INSERT INTO wallpapers (
+                     id, title, url, thumbnail, width, height, tags, is_ai_generated,
+                     is_available, source, file_path
+                 ) VALUES (?1, ?2, ?3, '', ?4, ?5, ?6, 0, 1, ?7, ?3)",
+                params![
+                    id,
+                    title_from(path),
+                    file_path,
+                    img.width(),
+                    img.height(),
+                    tags,
+                    WallpaperSource::Local.as_str()
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            set_wallpaper_tags(&tx, &id, &tags).map_err(|e| e.to_string())?;
+            save_hash(&tx, &id, hash).map_err(|e| e.to_string())?;
+            (id.clone(), FileOutcome::Added(id))
+        }
+    };
+    save_metadata(&tx, &id, &metadata).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    if let Some(dir) = thumbnails {
+        if ensure_thumbnails_of(dir, &id, &img).is_ok() {
+            let _ = ensure_palette(conn, dir, &id);
+        }
+    }
+    Ok(outcome)
+}
+
+/// Imports every supported image in `root`, and its subfolders when
+/// `recursive`. `on_progress` is called after each image.
+pub fn import_folder_with<F>(
+    conn: &Connection,
+    root: &Path,
+    recursive: bool,
+    thumbnails: Option<&Path>,
+    mut on_progress: F,
+) -> Result<ImportReport, String>
+where
+    F: FnMut(&ImportProgress),
+{
+    if !root.is_dir() {
+        return Err(format!("Not a folder: {}", root.display()));
+    }
+    let skip_duplicates = skip_duplicates_enabled(conn).map_err(|e| e.to_string())?;
+    let (images, skipped) = scan_images(root, recursive);
+    let mut report = ImportReport {
+        skipped,
+        ..Default::default()
+    };
+
+    for (index, path) in images.iter().enumerate() {
+        match import_file(conn, root, path, thumbnails, skip_duplicates)? {
+            FileOutcome::Added(_) => report.imported += 1,
+            FileOutcome::Updated(_) => report.updated += 1,
+            FileOutcome::Skipped(reason) => report.skipped.push(SkippedFile {
+                path: path.to_string_lossy().into_owned(),
+                reason,
+            }),
+        }
+        on_progress(&ImportProgress {
+            processed: index as u32 + 1,
+            total: images.len() as u32,
+            imported: report.imported,
+            updated: report.updated,
+            skipped: report.skipped.len() as u32,
+        });
+    }
+    Ok(report)
+}
+
+//
+// Tauri Commands
+//
+
+/// Imports a folder, emitting `import-progress` as it goes.
+#[tauri::command]
+pub async fn import_folder(
+    app: AppHandle,
+    path: String,
+    recursive: bool,
+) -> Result<ImportReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let root = fs::canonicalize(&path).map_err(|e| format!("{path}: {e}"))?;
+        let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+        let thumbnails = thumbnail_dir().ok();
+        import_folder_with(&conn, &root, recursive, thumbnails.as_deref(), |progress| {
+            let _ = app.emit("import-progress", *progress);
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::db_services::{get_wallpaper_by_url, set_setting};
+    use crate::services::migrations::migrate;
+    use image::{Rgb, RgbImage};
+
+    fn write_image(path: &Path, width: u32, height: u32, shade: u8) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        RgbImage::from_fn(width, height, |x, _| {
+            Rgb([shade, (x * 4) as u8, 255 - shade])
+        })
+        .save(path)
+        .unwrap();
+    }
+
+    #[test]
+    fn imports_recursively_with_folder_tags() {
+        let root = std::env::temp_dir()
+            .join(format!("wallpaper-import-{}", uuid::Uuid::new_v4()))
+            .join("Walls");
+        write_image(&root.join("sea_view-01.png"), 64, 32, 10);
+        write_image(&root.join("Nature/Mountains/peak.jpg"), 40, 60, 200);
+        write_image(&root.join(".hidden/secret.png"), 8, 8, 0);
+        fs::write(root.join("notes.txt"), "not an image").unwrap();
+        fs::write(root.join("broken.png"), "not a png either").unwrap();
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        let mut last = None;
+        let report = import_folder_with(&conn, &root, true, None, |p| last = Some(*p)).unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(last.unwrap().processed, 3);
+
+        let peak_path = root.join("Nature/Mountains/peak.jpg");
+        let peak = get_wallpaper_by_url(&conn, &peak_path.to_string_lossy())
+            .unwrap()
+            .unwrap();
+        assert_eq!(peak.source, WallpaperSource::Local);
+        assert_eq!(
+            peak.file_path.as_deref(),
+            Some(&*peak_path.to_string_lossy())
+        );
+        assert_eq!((peak.width, peak.height), (40, 60));
+        assert_eq!(peak.tags, "Walls, Nature, Mountains");
+        assert_eq!(peak.mongo_id, None);
+
+        let sea = get_wallpaper_by_url(&conn, &root.join("sea_view-01.png").to_string_lossy())
+            .unwrap()
+            .unwrap();
+        assert_eq!(sea.title, "sea view 01");
+
+        // Top level only, and nothing new the second time round.
+        let again = import_folder_with(&conn, &root, false, None, |_| {}).unwrap();
+        assert_eq!((again.imported, again.updated), (0, 1));
+
+        fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn edited_files_get_fresh_thumbnails_and_palette() {
+        let root = std::env::temp_dir().join(format!("wallpaper-import-{}", uuid::Uuid::new_v4()));
+        let thumbnails = root.join(".thumbnails");
+        let file = root.join("edited.png");
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        let dominant = |conn: &Connection| -> Option<String> {
+            conn.query_row("SELECT dominant_color FROM wallpapers", [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+
+        write_image(&file, 64, 32, 10);
+        import_file(&conn, &root, &file, Some(&thumbnails), false).unwrap();
+        let before = dominant(&conn);
+        assert!(before.is_some());
+
+        write_image(&file, 64, 32, 240);
+        let outcome = import_file(&conn, &root, &file, Some(&thumbnails), false).unwrap();
+        assert!(matches!(outcome, FileOutcome::Updated(_)));
+        let after = dominant(&conn);
+        assert!(after.is_some());
+        assert_ne!(after, before);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn skips_duplicates_when_asked() {
+        let root = std::env::temp_dir().join(format!("wallpaper-import-{}", uuid::Uuid::new_v4()));
+        write_image(&root.join("a.png"), 64, 32, 10);
+        write_image(&root.join("copy/a-small.png"), 32, 16, 10);
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        set_setting(&conn, "skipDuplicates", "true", "general").unwrap();
+        let report = import_folder_with(&conn, &root, true, None, |_| {}).unwrap();
+        assert_eq!(report.imported, 1);
+        assert!(report.skipped[0].reason.starts_with("Duplicate of"));
+
+        // Once the original's file is gone, a copy comes in.
+        fs::remove_file(root.join("a.png")).unwrap();
+        conn.execute(
+            "UPDATE wallpapers SET is_available = 0 WHERE file_path = ?1",
+            params![root.join("a.png").to_string_lossy()],
+        )
+        .unwrap();
+        let report = import_folder_with(&conn, &root, true, None, |_| {}).unwrap();
+        assert_eq!((report.imported, report.skipped.len()), (1, 0));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,712 @@
+use crate::services::db_services::{parse_tags, set_wallpaper_tags, DbPool};
+use crate::services::duplicate_service::skip_duplicates_enabled;
+use crate::services::import_service::{
+    folder_tags, import_file, is_supported_image, scan_images, FileOutcome,
+};
+use crate::services::thumbnail_service::thumbnail_dir;
+use notify_debouncer_full::notify::event::{EventKind, ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{
+    new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache,
+};
+use once_cell::sync::Lazy;
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// How long a file must stay quiet before its changes are applied, so a
+/// copy in progress is imported once, when it's done.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Full rescans catch whatever the watcher missed: changes while the app
+/// was closed, network drives, overflowing event queues.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+type LibraryWatcher = Debouncer<RecommendedWatcher, RecommendedCache>;
+
+static WATCHER: Lazy<Mutex<Option<LibraryWatcher>>> = Lazy::new(|| Mutex::new(None));
+
+/// A folder kept in sync with the library.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LibraryFolder {
+    pub path: String,
+    pub recursive: bool,
+}
+
+/// Counts of what changed, sent with `library-changed`.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct LibraryChange {
+    pub added: u32,
+    pub updated: u32,
+    pub removed: u32,
+    pub renamed: u32,
+}
+
+impl LibraryChange {
+    pub fn is_empty(&self) -> bool {
+        *self == LibraryChange::default()
+    }
+
+    fn add(&mut self, other: LibraryChange) {
+        self.added += other.added;
+        self.updated += other.updated;
+        self.removed += other.removed;
+        self.renamed += other.renamed;
+    }
+}
+
+/// One change on disk, as the library cares about it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FsChange {
+    /// A file or folder appeared or its contents changed.
+    Upsert(PathBuf),
+    Remove(PathBuf),
+    Rename(PathBuf, PathBuf),
+}
+
+pub fn list_library_folders(conn: &Connection) -> SqlResult<Vec<LibraryFolder>> {
+    let mut stmt = conn.prepare("SELECT path, recursive FROM library_folders ORDER BY path")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(LibraryFolder {
+            path: row.get(0)?,
+            recursive: row.get(1)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn add_library_folder(conn: &Connection, folder: &LibraryFolder) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO library_folders (path, recursive) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET recursive = excluded.recursive",
+        params![folder.path, folder.recursive],
+    )?;
+    Ok(())
+}
+
+/// Stops tracking a folder. Wallpapers already imported from it stay.
+pub fn remove_library_folder(conn: &Connection, path: &str) -> SqlResult<bool> {
+    Ok(conn.execute("DELETE FROM library_folders WHERE path = ?1", params![path])? > 0)
+}
+
+/// The library folder `path` belongs in, if any.
+fn folder_of<'a>(folders: &'a [LibraryFolder], path: &Path) -> Option<&'a LibraryFolder> {
+    folders.iter().find(|folder| {
+        let root = Path::new(&folder.path);
+        if folder.recursive {
+            path.starts_with(root) && path != root
+        } else {
+            path.parent() == Some(root)
+        }
+    })
+}
+
+/// Available local wallpapers at `path` or, when it is a folder, anywhere
+/// under it.
+fn wallpapers_at(conn: &Connection, path: &Path) -> SqlResult<Vec<(String, PathBuf)>> {
+    let path = path.to_string_lossy();
+    let prefix = format!("{path}{}", std::path::MAIN_SEPARATOR);
+    let pattern = format!(
+        "{}%",
+        prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, file_path FROM wallpapers
+        WHERE is_available = 1
+          AND (
+              file_path = ?1
+              -- LIKE ignores ASCII case; the prefix comparison doesn't.
+              OR (file_path LIKE ?3 ESCAPE '\' AND substr(file_path, 1, length(?2)) = ?2)
+          )
+        "#,
+    )?;
+    let rows = stmt.query_map(params![path, prefix, pattern], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            PathBuf::from(row.get::<_, String>(1)?),
+        ))
+    })?;
+    rows.collect()
+}
+
+/// A wallpaper whose file is gone is marked unavailable rather than
+/// deleted, like a remote tombstone, so its favorite, collections, rating
+/// and crops are still there if the file comes back.
+fn mark_unavailable(conn: &Connection, id: &str) -> SqlResult<()> {
+    conn.execute(
+        "UPDATE wallpapers SET is_available = 0 WHERE id = ?1",
+        params![id],
+    )?;
+    Ok(())
+}
+
+/// Swaps the folder-name tags of a wallpaper moved from `from` to `to`,
+/// keeping its other tags.
+fn retag_moved(
+    conn: &Connection,
+    folders: &[LibraryFolder],
+    id: &str,
+    from: &Path,
+    to: &Path,
+) -> SqlResult<()> {
+    let tags_of = |file: &Path| {
+        folder_of(folders, file)
+            .map(|folder| parse_tags(&folder_tags(Path::new(&folder.path), file)))
+            .unwrap_or_default()
+    };
+    let stale = tags_of(from);
+    let current: String = conn.query_row(
+        "SELECT COALESCE(tags, '') FROM wallpapers WHERE id = ?1",
+        params![id],
+        |row| row.get(0),
+    )?;
+    let kept = parse_tags(&current)
+        .into_iter()
+        .filter(|tag| !stale.iter().any(|old| old.eq_ignore_ascii_case(tag)));
+    let tags = parse_tags(
+        &tags_of(to)
+            .into_iter()
+            .chain(kept)
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+    .join(", ");
+    conn.execute(
+        "UPDATE wallpapers SET tags = ?2 WHERE id = ?1",
+        params![id, tags],
+    )?;
+    set_wallpaper_tags(conn, id, &tags)
+}
+
+fn upsert(
+    conn: &Connection,
+    folders: &[LibraryFolder],
+    path: &Path,
+    thumbnails: Option<&Path>,
+    skip_duplicates: bool,
+) -> Result<LibraryChange, String> {
+    let mut change = LibraryChange::default();
+    if path.is_dir() {
+        // A folder moved or copied in arrives as one event.
+        for folder in folders {
+            let root = Path::new(&folder.path);
+            if path.starts_with(root) && (folder.recursive || path == root) {
+                let (images, _) = scan_images(path, folder.recursive);
+                for image in images {
+                    change.add(upsert(conn, folders, &image, thumbnails, skip_duplicates)?);
+                }
+                break;
+            }
+        }
+        return Ok(change);
+    }
+
+    let Some(folder) = folder_of(folders, path) else {
+        return Ok(change);
+    };
+    if !is_supported_image(path) || !path.is_file() {
+        return Ok(change);
+    }
+
+    match import_file(
+        conn,
+        Path::new(&folder.path),
+        path,
+        thumbnails,
+        skip_duplicates,
+    )? {
+        FileOutcome::Added(_) => change.added += 1,
+        FileOutcome::Updated(_) => change.updated += 1,
+        FileOutcome::Skipped(_) => {}
+    }
+    Ok(change)
+}
+
+/// Applies changes reported by the watcher to the library. Files outside
+/// every library folder are ignored; renames keep the wallpaper's id, and
+/// with it its favorite, crop and effects.
+pub fn apply_fs_changes(
+    conn: &Connection,
+    folders: &[LibraryFolder],
+    changes: &[FsChange],
+    thumbnails: Option<&Path>,
+) -> Result<LibraryChange, String> {
+    let skip_duplicates = skip_duplicates_enabled(conn).map_err(|e| e.to_string())?;
+    let mut total = LibraryChange::default();
+
+    for change in changes {
+        match change {
+            FsChange::Upsert(path) => {
+                total.add(upsert(conn, folders, path, thumbnails, skip_duplicates)?);
+            }
+            FsChange::Remove(path) => {
+                for (id, _) in wallpapers_at(conn, path).map_err(|e| e.to_string())? {
+                    mark_unavailable(conn, &id).map_err(|e| e.to_string())?;
+                    total.removed += 1;
+                }
+            }
+            FsChange::Rename(from, to) => {
+                let moved = wallpapers_at(conn, from).map_err(|e| e.to_string())?;
+                if moved.is_empty() {
+                    total.add(upsert(conn, folders, to, thumbnails, skip_duplicates)?);
+                    continue;
+                }
+                for (id, old) in moved {
+                    let new = match old.strip_prefix(from) {
+                        Ok(rest) if !rest.as_os_str().is_empty() => to.join(rest),
+                        _ => to.clone(),
+                    };
+                    if folder_of(folders, &new).is_some() && is_supported_image(&new) {
+                        conn.execute(
+                            "UPDATE wallpapers SET file_path = ?2, url = ?2 WHERE id = ?1",
+                            params![id, new.to_string_lossy()],
+                        )
+                        .map_err(|e| e.to_string())?;
+                        retag_moved(conn, folders, &id, &old, &new).map_err(|e| e.to_string())?;
+                        total.renamed += 1;
+                    } else {
+                        mark_unavailable(conn, &id).map_err(|e| e.to_string())?;
+                        total.removed += 1;
+                    }
+                }
+            }
+        }
+    }
+    Ok(total)
+}
+
+fn is_available_at(conn: &Connection, file: &Path) -> SqlResult<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM wallpapers WHERE file_path = ?1 AND is_available = 1)",
+        params![file.to_string_lossy()],
+        |row| row.get(0),
+    )
+}
+
+/// Brings the library in line with `folders` on disk: imports images it
+/// doesn't have and marks ones whose file is gone unavailable. Images already in the
+/// library are left alone; the watcher handles edits.
+pub fn rescan_library(
+    conn: &Connection,
+    folders: &[LibraryFolder],
+    thumbnails: Option<&Path>,
+) -> Result<LibraryChange, String> {
+    let skip_duplicates = skip_duplicates_enabled(conn).map_err(|e| e.to_string())?;
+    let mut change = LibraryChange::default();
+
+    for folder in folders {
+        let root = Path::new(&folder.path);
+        if !root.is_dir() {
+            // An unmounted drive shouldn't empty the library.
+            continue;
+        }
+        for (id, file) in wallpapers_at(conn, root).map_err(|e| e.to_string())? {
+            if !file.exists() {
+                mark_unavailable(conn, &id).map_err(|e| e.to_string())?;
+                change.removed += 1;
+            }
+        }
+
+        let (images, _) = scan_images(root, folder.recursive);
+        for image in images {
+            if is_available_at(conn, &image).map_err(|e| e.to_string())? {
+                continue;
+            }
+            // New files, and ones back where an unavailable wallpaper was.
+            match import_file(conn, root, &image, thumbnails, skip_duplicates)? {
+                FileOutcome::Added(_) => change.added += 1,
+                FileOutcome::Updated(_) => change.updated += 1,
+                FileOutcome::Skipped(_) => {}
+            }
+        }
+    }
+    Ok(change)
+}
+
+/// What a batch of debounced watcher events means for the library.
+pub fn events_to_changes(events: &[DebouncedEvent]) -> Vec<FsChange> {
+    let mut changes = Vec::new();
+    for event in events {
+        let paths = &event.paths;
+        let Some(first) = paths.first().cloned() else {
+            continue;
+        };
+        match event.kind {
+            EventKind::Create(_) => changes.push(FsChange::Upsert(first)),
+            EventKind::Remove(_) => changes.push(FsChange::Remove(first)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                changes.push(FsChange::Rename(first, paths[1].clone()))
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                changes.push(FsChange::Remove(first))
+            }
+            EventKind::Modify(ModifyKind::Name(_)) => changes.push(FsChange::Upsert(first)),
+            EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any) => {
+                changes.push(FsChange::Upsert(first))
+            }
+            _ => {}
+        }
+    }
+    changes
+}
+
+fn notify_changed(app: &AppHandle, change: LibraryChange) {
+    if !change.is_empty() {
+        let _ = app.emit("library-changed", change);
+    }
+}
+
+fn handle_events(app: &AppHandle, result: DebounceEventResult) {
+    let events = match result {
+        Ok(events) => events,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("Library watcher error: {}", e);
+            }
+            return;
+        }
+    };
+    let changes = events_to_changes(&events);
+    if changes.is_empty() {
+        return;
+    }
+    let result = app
+        .state::<DbPool>()
+        .get()
+        .map_err(|e| e.to_string())
+        .and_then(|conn| {
+            let folders = list_library_folders(&conn).map_err(|e| e.to_string())?;
+            apply_fs_changes(&conn, &folders, &changes, thumbnail_dir().ok().as_deref())
+        });
+    match result {
+        Ok(change) => notify_changed(app, change),
+        Err(e) => eprintln!("Failed to apply library changes: {}", e),
+    }
+}
+
+fn watch(watcher: &mut LibraryWatcher, folder: &LibraryFolder) {
+    let mode = if folder.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    if let Err(e) = watcher.watch(Path::new(&folder.path), mode) {
+        eprintln!("Failed to watch {}: {}", folder.path, e);
+    }
+}
+
+fn rescan_with_events(app: &AppHandle) -> Result<LibraryChange, String> {
+    let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+    let folders = list_library_folders(&conn).map_err(|e| e.to_string())?;
+    let change = rescan_library(&conn, &folders, thumbnail_dir().ok().as_deref())?;
+    notify_changed(app, change);
+    Ok(change)
+}
+
+/// Starts watching every library folder, and rescans them all now and every
+/// [`RESCAN_INTERVAL`] after.
+pub fn start_library_watcher(app: AppHandle) {
+    let handler_app = app.clone();
+    match new_debouncer(DEBOUNCE_TIMEOUT, None, move |result| {
+        handle_events(&handler_app, result)
+    }) {
+        Ok(mut watcher) => {
+            let folders = app
+                .state::<DbPool>()
+                .get()
+                .map_err(|e| e.to_string())
+                .and_then(|conn| list_library_folders(&conn).map_err(|e| e.to_string()))
+                .unwrap_or_default();
+            for folder in &folders {
+                watch(&mut watcher, folder);
+            }
+            if let Ok(mut slot) = WATCHER.lock() {
+                *slot = Some(watcher);
+            }
+        }
+        Err(e) => eprintln!("Failed to start library watcher: {}", e),
+    }
+
+    std::thread::spawn(move || loop {
+        if let Err(e) = rescan_with_events(&app) {
+            eprintln!("Library rescan failed: {}", e);
+        }
+        std::thread::sleep(RESCAN_INTERVAL);
+    });
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn list_library_folders_command(
+    db: State<'_, DbPool>,
+) -> Result<Vec<LibraryFolder>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    list_library_folders(&conn).map_err(|e| e.to_string())
+}
+
+/// Registers a folder, starts watching it and imports what's in it.
+#[tauri::command]
+pub async fn add_library_folder_command(
+    app: AppHandle,
+    path: String,
+    recursive: bool,
+) -> Result<LibraryFolder, String> {
+    let root = fs::canonicalize(&path).map_err(|e| format!("{path}: {e}"))?;
+    if !root.is_dir() {
+        return Err(format!("Not a folder: {path}"));
+    }
+    let folder = LibraryFolder {
+        path: root.to_string_lossy().into_owned(),
+        recursive,
+    };
+    {
+        let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+        add_library_folder(&conn, &folder).map_err(|e| e.to_string())?;
+    }
+    if let Some(watcher) = WATCHER.lock().map_err(|e| e.to_string())?.as_mut() {
+        // Re-watching applies a changed `recursive`.
+        let _ = watcher.unwatch(&root);
+        watch(watcher, &folder);
+    }
+
+    let added = folder.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+        let change = rescan_library(&conn, &[added], thumbnail_dir().ok().as_deref())?;
+        notify_changed(&app, change);
+        Ok::<_, String>(())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    Ok(folder)
+}
+
+/// Stops watching a folder. Its wallpapers stay in the library.
+#[tauri::command]
+pub async fn remove_library_folder_command(
+    db: State<'_, DbPool>,
+    path: String,
+) -> Result<bool, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let removed = remove_library_folder(&conn, &path).map_err(|e| e.to_string())?;
+    if let Some(watcher) = WATCHER.lock().map_err(|e| e.to_string())?.as_mut() {
+        let _ = watcher.unwatch(Path::new(&path));
+    }
+    Ok(removed)
+}
+
+#[tauri::command]
+pub async fn rescan_library_command(app: AppHandle) -> Result<LibraryChange, String> {
+    tauri::async_runtime::spawn_blocking(move || rescan_with_events(&app))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::db_services::{add_favorite, get_favorite_wallpapers};
+    use crate::services::import_service::get_wallpaper_id_by_path;
+    use crate::services::migrations::migrate;
+    use image::{Rgb, RgbImage};
+
+    fn write_image(path: &Path, shade: u8) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        RgbImage::from_fn(32, 16, |x, _| Rgb([shade, (x * 8) as u8, 40]))
+            .save(path)
+            .unwrap();
+    }
+
+    fn paths(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT file_path FROM wallpapers WHERE is_available = 1 ORDER BY file_path")
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn changes_on_disk_follow_into_the_library() {
+        let root = std::env::temp_dir().join(format!("wallpaper-library-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let folders = vec![LibraryFolder {
+            path: root.to_string_lossy().into_owned(),
+            recursive: true,
+        }];
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        add_library_folder(&conn, &folders[0]).unwrap();
+        assert_eq!(list_library_folders(&conn).unwrap(), folders);
+
+        let a = root.join("a.png");
+        let outside = std::env::temp_dir().join("not-in-library.png");
+        write_image(&a, 10);
+        let change = apply_fs_changes(
+            &conn,
+            &folders,
+            &[FsChange::Upsert(a.clone()), FsChange::Upsert(outside)],
+            None,
+        )
+        .unwrap();
+        assert_eq!(change.added, 1);
+        let id = get_wallpaper_id_by_path(&conn, &a.to_string_lossy())
+            .unwrap()
+            .unwrap();
+
+        // A folder moved in at once, then renamed with the image inside.
+        write_image(&root.join("new/b.png"), 200);
+        let change =
+            apply_fs_changes(&conn, &folders, &[FsChange::Upsert(root.join("new"))], None).unwrap();
+        assert_eq!(change.added, 1);
+        fs::rename(root.join("new"), root.join("renamed")).unwrap();
+        fs::rename(&a, root.join("renamed/a.png")).unwrap();
+        let change = apply_fs_changes(
+            &conn,
+            &folders,
+            &[
+                FsChange::Rename(root.join("new"), root.join("renamed")),
+                FsChange::Rename(a.clone(), root.join("renamed/a.png")),
+            ],
+            None,
+        )
+        .unwrap();
+        assert_eq!(change.renamed, 2);
+        assert_eq!(
+            get_wallpaper_id_by_path(&conn, &root.join("renamed/a.png").to_string_lossy()).unwrap(),
+            Some(id)
+        );
+
+        // Deleted behind the watcher's back, and a file it never saw.
+        fs::remove_file(root.join("renamed/b.png")).unwrap();
+        write_image(&root.join("c.png"), 120);
+        let change = rescan_library(&conn, &folders, None).unwrap();
+        assert_eq!((change.added, change.removed), (1, 1));
+        assert_eq!(
+            paths(&conn),
+            vec![
+                root.join("c.png").to_string_lossy(),
+                root.join("renamed/a.png").to_string_lossy()
+            ]
+        );
+
+        fs::remove_dir_all(root.join("renamed")).unwrap();
+        let change = apply_fs_changes(
+            &conn,
+            &folders,
+            &[FsChange::Remove(root.join("renamed"))],
+            None,
+        )
+        .unwrap();
+        assert_eq!(change.removed, 1);
+        assert_eq!(
+            rescan_library(&conn, &folders, None).unwrap(),
+            LibraryChange::default()
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn tags(conn: &Connection, id: &str) -> String {
+        conn.query_row(
+            "SELECT tags FROM wallpapers WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn moved_files_are_retagged_and_missing_ones_kept() {
+        let base = std::env::temp_dir().join(format!("wallpaper-library-{}", uuid::Uuid::new_v4()));
+        let root = base.join("Library");
+        let folders = vec![LibraryFolder {
+            path: root.to_string_lossy().into_owned(),
+            recursive: true,
+        }];
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+
+        let a = root.join("Nature/a.png");
+        let sibling = root.join("Natures/b.png");
+        let lookalike = root.join("NaXure/c.png");
+        for (file, shade) in [(&a, 10), (&sibling, 100), (&lookalike, 200)] {
+            write_image(file, shade);
+            apply_fs_changes(&conn, &folders, &[FsChange::Upsert(file.clone())], None).unwrap();
+        }
+        let id = get_wallpaper_id_by_path(&conn, &a.to_string_lossy())
+            .unwrap()
+            .unwrap();
+        add_favorite(&conn, &id).unwrap();
+        conn.execute(
+            "UPDATE wallpapers SET tags = tags || ', sunset' WHERE id = ?1",
+            params![id],
+        )
+        .unwrap();
+
+        // Moved to another folder: its folder tags follow, the rest stay.
+        let moved = root.join("City/a.png");
+        fs::create_dir_all(moved.parent().unwrap()).unwrap();
+        fs::rename(&a, &moved).unwrap();
+        let change = apply_fs_changes(
+            &conn,
+            &folders,
+            &[FsChange::Rename(a.clone(), moved.clone())],
+            None,
+        )
+        .unwrap();
+        assert_eq!(change.renamed, 1);
+        assert_eq!(tags(&conn, &id), "Library, City, sunset");
+
+        // Only the folder itself goes, not its lookalike siblings.
+        let change = apply_fs_changes(
+            &conn,
+            &folders,
+            &[
+                FsChange::Remove(root.join("Nature")),
+                FsChange::Remove(root.join("Na_ure")),
+            ],
+            None,
+        )
+        .unwrap();
+        assert_eq!(change.removed, 0);
+
+        // Out of the library and back: same wallpaper, still a favorite.
+        let outside = base.join("a.png");
+        fs::rename(&moved, &outside).unwrap();
+        let change = apply_fs_changes(
+            &conn,
+            &folders,
+            &[FsChange::Rename(moved.clone(), outside.clone())],
+            None,
+        )
+        .unwrap();
+        assert_eq!(change.removed, 1);
+        assert!(!paths(&conn).contains(&moved.to_string_lossy().into_owned()));
+        assert!(get_favorite_wallpapers(&conn).unwrap().is_empty());
+
+        fs::rename(&outside, &moved).unwrap();
+        let change = rescan_library(&conn, &folders, None).unwrap();
+        assert_eq!((change.added, change.updated), (0, 1));
+        assert_eq!(
+            get_wallpaper_id_by_path(&conn, &moved.to_string_lossy()).unwrap(),
+            Some(id)
+        );
+        assert_eq!(paths(&conn).len(), 3);
+        assert_eq!(get_favorite_wallpapers(&conn).unwrap().len(), 1);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,521 @@
+use exif::experimental::Writer;
+use exif::{Context, DateTime, Exif, Field, In, Reader, Tag, Value};
+use img_parts::{Bytes, DynImage, ImageEXIF};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// What an image says about itself in its EXIF, XMP and IPTC blocks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ImageMetadata {
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    /// `YYYY-MM-DDTHH:MM:SS`, in the camera's local time.
+    pub captured_at: Option<String>,
+    /// Degrees, negative south and west.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Metres above sea level.
+    pub altitude: Option<f64>,
+    pub author: Option<String>,
+    pub copyright: Option<String>,
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+}
+
+impl ImageMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == ImageMetadata::default()
+    }
+
+    /// Fills whatever `self` lacks from `other`, and adds its keywords.
+    fn merge(&mut self, other: ImageMetadata) {
+        fn fill<T>(slot: &mut Option<T>, value: Option<T>) {
+            if slot.is_none() {
+                *slot = value;
+            }
+        }
+        fill(&mut self.camera_make, other.camera_make);
+        fill(&mut self.camera_model, other.camera_model);
+        fill(&mut self.lens_model, other.lens_model);
+        fill(&mut self.captured_at, other.captured_at);
+        fill(&mut self.latitude, other.latitude);
+        fill(&mut self.longitude, other.longitude);
+        fill(&mut self.altitude, other.altitude);
+        fill(&mut self.author, other.author);
+        fill(&mut self.copyright, other.copyright);
+        fill(&mut self.description, other.description);
+        for keyword in other.keywords {
+            if !self
+                .keywords
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(&keyword))
+            {
+                self.keywords.push(keyword);
+            }
+        }
+    }
+}
+
+/// Reads the metadata of an encoded image. EXIF wins over XMP, which wins
+/// over IPTC; keywords are collected from all of them.
+pub fn read_metadata(bytes: &[u8]) -> ImageMetadata {
+    let mut metadata = read_exif(bytes)
+        .map(|exif| from_exif(&exif))
+        .unwrap_or_default();
+    if let Some(xmp) = find_xmp(bytes) {
+        metadata.merge(from_xmp(xmp));
+    }
+    if let Some(iptc) = find_iptc(bytes) {
+        metadata.merge(from_iptc(iptc));
+    }
+    metadata
+}
+
+fn read_exif(bytes: &[u8]) -> Option<Exif> {
+    Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+}
+
+fn clean(text: &str) -> Option<String> {
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(parts) => clean(&String::from_utf8_lossy(parts.first()?)),
+        _ => None,
+    }
+}
+
+fn rationals(exif: &Exif, tag: Tag) -> Option<Vec<f64>> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(values) => Some(values.iter().map(|r| r.to_f64()).collect()),
+        _ => None,
+    }
+}
+
+/// Degrees from EXIF's degrees, minutes and seconds, signed by `reference`.
+fn coordinate(exif: &Exif, tag: Tag, reference: Tag, negative: &str) -> Option<f64> {
+    let dms = rationals(exif, tag)?;
+    let degrees =
+        dms.first()? + dms.get(1).unwrap_or(&0.0) / 60.0 + dms.get(2).unwrap_or(&0.0) / 3600.0;
+    if !degrees.is_finite() {
+        return None;
+    }
+    let sign = match ascii(exif, reference) {
+        Some(r) if r.eq_ignore_ascii_case(negative) => -1.0,
+        _ => 1.0,
+    };
+    Some(sign * degrees)
+}
+
+fn iso_datetime(text: &str) -> Option<String> {
+    let dt = DateTime::from_ascii(text.as_bytes()).ok()?;
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second
+    ))
+}
+
+fn from_exif(exif: &Exif) -> ImageMetadata {
+    let altitude = rationals(exif, Tag::GPSAltitude)
+        .and_then(|values| values.first().copied())
+        .filter(|a| a.is_finite())
+        .map(|altitude| {
+            let below = matches!(
+                exif.get_field(Tag::GPSAltitudeRef, In::PRIMARY).map(|f| &f.value),
+                Some(Value::Byte(b)) if b.first() == Some(&1)
+            );
+            if below {
+                -altitude
+            } else {
+                altitude
+            }
+        });
+
+    ImageMetadata {
+        camera_make: ascii(exif, Tag::Make),
+        camera_model: ascii(exif, Tag::Model),
+        lens_model: ascii(exif, Tag::LensModel),
+        captured_at: ascii(exif, Tag::DateTimeOriginal)
+            .or_else(|| ascii(exif, Tag::DateTime))
+            .and_then(|text| iso_datetime(&text)),
+        latitude: coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+        longitude: coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+        altitude,
+        author: ascii(exif, Tag::Artist),
+        copyright: ascii(exif, Tag::Copyright),
+        description: ascii(exif, Tag::ImageDescription),
+        keywords: Vec::new(),
+    }
+}
+
+// ---------------------- XMP ----------------------
+
+/// The XMP packet, wherever the container put it; it is plain XML in JPEG,
+/// PNG and WebP alike.
+fn find_xmp(bytes: &[u8]) -> Option<&str> {
+    let start = find(bytes, b"<x:xmpmeta")?;
+    let end = find(&bytes[start..], b"</x:xmpmeta>")? + start + b"</x:xmpmeta>".len();
+    std::str::from_utf8(&bytes[start..end]).ok()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The `<rdf:li>` values of property `name`.
+fn xmp_list(xmp: &str, name: &str) -> Vec<String> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let Some(start) = xmp.find(&open) else {
+        return Vec::new();
+    };
+    let body = &xmp[start + open.len()..];
+    let body = &body[..body.find(&close).unwrap_or(body.len())];
+
+    let mut values = Vec::new();
+    let mut rest = body;
+    while let Some(li) = rest.find("<rdf:li") {
+        rest = &rest[li..];
+        let Some(content) = rest.find('>') else {
+            break;
+        };
+        let Some(end) = rest.find("</rdf:li>") else {
+            break;
+        };
+        if content < end {
+            if let Some(value) = clean(&unescape(&rest[content + 1..end])) {
+                values.push(value);
+            }
+        }
+        rest = &rest[end + "</rdf:li>".len()..];
+    }
+    values
+}
+
+/// A simple property, written either as an attribute or as an element.
+fn xmp_value(xmp: &str, name: &str) -> Option<String> {
+    let attribute = format!("{name}=\"");
+    if let Some(start) = xmp.find(&attribute) {
+        let value = &xmp[start + attribute.len()..];
+        return clean(&unescape(&value[..value.find('"')?]));
+    }
+    let open = format!("<{name}>");
+    let start = xmp.find(&open)? + open.len();
+    let end = xmp[start..].find('<')? + start;
+    clean(&unescape(&xmp[start..end]))
+}
+
+/// `51,30.5N` or `51,30,30N` to signed degrees.
+fn xmp_coordinate(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (number, hemisphere) = text.split_at(text.len().checked_sub(1)?);
+    let parts: Vec<f64> = number
+        .split(',')
+        .map(|p| p.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    let degrees = parts.first()?
+        + parts.get(1).unwrap_or(&0.0) / 60.0
+        + parts.get(2).unwrap_or(&0.0) / 3600.0;
+    match hemisphere {
+        "N" | "E" => Some(degrees),
+        "S" | "W" => Some(-degrees),
+        _ => None,
+    }
+}
+
+/// `2023-05-04T12:34:56`, with any fraction or zone dropped; dates alone
+/// get midnight.
+fn xmp_datetime(text: &str) -> Option<String> {
+    let date = text.get(..10)?;
+    let time = text.get(11..19).unwrap_or("00:00:00");
+    iso_datetime(&format!("{} {}", date.replace('-', ":"), time))
+}
+
+fn from_xmp(xmp: &str) -> ImageMetadata {
+    ImageMetadata {
+        camera_make: xmp_value(xmp, "tiff:Make"),
+        camera_model: xmp_value(xmp, "tiff:Model"),
+        lens_model: xmp_value(xmp, "aux:Lens").or_else(|| xmp_value(xmp, "exifEX:LensModel")),
+        captured_at: xmp_value(xmp, "exif:DateTimeOriginal")
+            .or_else(|| xmp_value(xmp, "photoshop:DateCreated"))
+            .or_else(|| xmp_value(xmp, "xmp:CreateDate"))
+            .and_then(|text| xmp_datetime(&text)),
+        latitude: xmp_value(xmp, "exif:GPSLatitude").and_then(|t| xmp_coordinate(&t)),
+        longitude: xmp_value(xmp, "exif:GPSLongitude").and_then(|t| xmp_coordinate(&t)),
+        altitude: None,
+        author: xmp_list(xmp, "dc:creator").into_iter().next(),
+        copyright: xmp_list(xmp, "dc:rights").into_iter().next(),
+        description: xmp_list(xmp, "dc:description").into_iter().next(),
+        keywords: xmp_list(xmp, "dc:subject"),
+    }
+}
+
+// ---------------------- IPTC ----------------------
+
+const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+const IPTC_RESOURCE: u16 = 0x0404;
+
+/// The IPTC-IIM block of a JPEG, from the Photoshop resources in APP13.
+fn find_iptc(bytes: &[u8]) -> Option<&[u8]> {
+    let mut rest = &bytes[find(bytes, PHOTOSHOP_SIGNATURE)? + PHOTOSHOP_SIGNATURE.len()..];
+    while rest.len() >= 12 && rest.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([rest[4], rest[5]]);
+        // Pascal string name, padded to an even length.
+        let name_len = rest[6] as usize;
+        let name_size = (name_len + 1 + 1) & !1;
+        let size_at = 6 + name_size;
+        let size = u32::from_be_bytes(rest.get(size_at..size_at + 4)?.try_into().ok()?) as usize;
+        let data = rest.get(size_at + 4..size_at + 4 + size)?;
+        if id == IPTC_RESOURCE {
+            return Some(data);
+        }
+        rest = rest.get(size_at + 4 + ((size + 1) & !1)..)?;
+    }
+    None
+}
+
+fn from_iptc(iim: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    let mut rest = iim;
+    while rest.len() >= 5 && rest[0] == 0x1c {
+        let (record, dataset) = (rest[1], rest[2]);
+        let size = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        if size & 0x8000 != 0 {
+            // Extended sizes are only used for binary previews.
+            break;
+        }
+        let Some(data) = rest.get(5..5 + size) else {
+            break;
+        };
+        let text = clean(&String::from_utf8_lossy(data));
+        match (record, dataset, text) {
+            (2, 25, Some(keyword)) => metadata.keywords.push(keyword),
+            (2, 80, text) if metadata.author.is_none() => metadata.author = text,
+            (2, 116, text) => metadata.copyright = text,
+            (2, 120, text) => metadata.description = text,
+            (2, 55, Some(date)) if date.len() == 8 => {
+                metadata.captured_at = iso_datetime(&format!(
+                    "{}:{}:{} 00:00:00",
+                    &date[..4],
+                    &date[4..6],
+                    &date[6..]
+                ))
+            }
+            _ => {}
+        }
+        rest = &rest[5 + size..];
+    }
+    metadata
+}
+
+// ---------------------- Writing ----------------------
+
+/// EXIF of `source` fit to go with a re-encoded copy: the primary IFD only,
+/// without the orientation (the pixels are upright already), sizes or the
+/// maker note, and without GPS when `strip_location`.
+pub fn exif_for_copy(source: &[u8], strip_location: bool) -> Option<Vec<u8>> {
+    let exif = read_exif(source)?;
+    let kept: Vec<&Field> = exif
+        .fields()
+        .filter(|f| f.ifd_num == In::PRIMARY)
+        .filter(|f| !(strip_location && f.tag.context() == Context::Gps))
+        .filter(|f| {
+            ![
+                Tag::Orientation,
+                Tag::ImageWidth,
+                Tag::ImageLength,
+                Tag::PixelXDimension,
+                Tag::PixelYDimension,
+                Tag::MakerNote,
+            ]
+            .contains(&f.tag)
+        })
+        .collect();
+    if kept.is_empty() {
+        return None;
+    }
+
+    let mut writer = Writer::new();
+    for field in kept {
+        writer.push_field(field);
+    }
+    let mut out = Cursor::new(Vec::new());
+    writer.write(&mut out, exif.little_endian()).ok()?;
+    Some(out.into_inner())
+}
+
+/// Puts `exif` into an encoded JPEG, PNG or WebP. Other formats, and
+/// images that can't be parsed, come back unchanged.
+pub fn with_exif(encoded: Vec<u8>, exif: Vec<u8>) -> Vec<u8> {
+    match DynImage::from_bytes(Bytes::from(encoded.clone())) {
+        Ok(Some(mut image)) => {
+            image.set_exif(Some(Bytes::from(exif)));
+            image.encoder().bytes().to_vec()
+        }
+        _ => encoded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::Rational;
+    use image::{DynamicImage, RgbImage};
+    use img_parts::jpeg::{markers, Jpeg, JpegSegment};
+
+    fn ascii_field(tag: Tag, text: &str) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        }
+    }
+
+    fn dms(degrees: u32, minutes: u32, seconds: u32) -> Value {
+        Value::Rational(vec![
+            Rational::from((degrees, 1)),
+            Rational::from((minutes, 1)),
+            Rational::from((seconds, 1)),
+        ])
+    }
+
+    fn exif_block() -> Vec<u8> {
+        let fields = vec![
+            ascii_field(Tag::Make, "Canon"),
+            ascii_field(Tag::Model, "EOS R5"),
+            ascii_field(Tag::DateTimeOriginal, "2023:05:04 12:34:56"),
+            ascii_field(Tag::Artist, "Jo Doe"),
+            ascii_field(Tag::GPSLatitudeRef, "S"),
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: dms(33, 51, 36),
+            },
+            ascii_field(Tag::GPSLongitudeRef, "E"),
+            Field {
+                tag: Tag::GPSLongitude,
+                ifd_num: In::PRIMARY,
+                value: dms(151, 12, 0),
+            },
+            Field {
+                tag: Tag::Orientation,
+                ifd_num: In::PRIMARY,
+                value: Value::Short(vec![1]),
+            },
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut out = Cursor::new(Vec::new());
+        writer.write(&mut out, false).unwrap();
+        out.into_inner()
+    }
+
+    fn xmp_segment() -> JpegSegment {
+        let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description
+            photoshop:DateCreated="2020-01-02" tiff:Make="Nikon">
+            <dc:subject><rdf:Bag><rdf:li>Beach</rdf:li><rdf:li>Sunset &amp; Sea</rdf:li></rdf:Bag></dc:subject>
+            <dc:rights><rdf:Alt><rdf:li xml:lang="x-default">© Jo Doe</rdf:li></rdf:Alt></dc:rights>
+            </rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        let mut contents = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+        contents.extend_from_slice(xmp.as_bytes());
+        JpegSegment::new_with_contents(markers::APP1, Bytes::from(contents))
+    }
+
+    fn iptc_segment() -> JpegSegment {
+        let mut iim = Vec::new();
+        for (dataset, text) in [(25u8, "beach"), (25, "Holiday"), (116, "IPTC rights")] {
+            iim.extend_from_slice(&[0x1c, 2, dataset]);
+            iim.extend_from_slice(&(text.len() as u16).to_be_bytes());
+            iim.extend_from_slice(text.as_bytes());
+        }
+        let mut contents = PHOTOSHOP_SIGNATURE.to_vec();
+        contents.extend_from_slice(b"8BIM");
+        contents.extend_from_slice(&IPTC_RESOURCE.to_be_bytes());
+        contents.extend_from_slice(&[0, 0]);
+        contents.extend_from_slice(&(iim.len() as u32).to_be_bytes());
+        contents.extend_from_slice(&iim);
+        if iim.len() % 2 == 1 {
+            contents.push(0);
+        }
+        JpegSegment::new_with_contents(markers::APP13, Bytes::from(contents))
+    }
+
+    fn photo() -> Vec<u8> {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+        let jpeg = crate::services::image_pipeline::encode(
+            &img,
+            crate::services::image_pipeline::OutputFormat::Jpeg,
+            90,
+        )
+        .unwrap();
+        let mut jpeg = Jpeg::from_bytes(Bytes::from(jpeg)).unwrap();
+        jpeg.set_exif(Some(Bytes::from(exif_block())));
+        jpeg.segments_mut().insert(3, xmp_segment());
+        jpeg.segments_mut().insert(4, iptc_segment());
+        jpeg.encoder().bytes().to_vec()
+    }
+
+    #[test]
+    fn reads_exif_xmp_and_iptc_together() {
+        let metadata = read_metadata(&photo());
+        assert_eq!(metadata.camera_make.as_deref(), Some("Canon"));
+        assert_eq!(metadata.camera_model.as_deref(), Some("EOS R5"));
+        assert_eq!(metadata.captured_at.as_deref(), Some("2023-05-04T12:34:56"));
+        assert!((metadata.latitude.unwrap() + 33.86).abs() < 1e-6);
+        assert!((metadata.longitude.unwrap() - 151.2).abs() < 1e-6);
+        assert_eq!(metadata.author.as_deref(), Some("Jo Doe"));
+        assert_eq!(metadata.copyright.as_deref(), Some("© Jo Doe"));
+        assert_eq!(metadata.keywords, vec!["Beach", "Sunset & Sea", "Holiday"]);
+
+        assert!(read_metadata(b"not an image").is_empty());
+    }
+
+    #[test]
+    fn copies_exif_without_location_when_asked() {
+        let source = photo();
+        let stripped = exif_for_copy(&source, true).unwrap();
+        let kept = exif_for_copy(&source, false).unwrap();
+
+        let plain = crate::services::image_pipeline::encode(
+            &DynamicImage::ImageRgb8(RgbImage::new(4, 4)),
+            crate::services::image_pipeline::OutputFormat::Png,
+            90,
+        )
+        .unwrap();
+        let stripped = read_metadata(&with_exif(plain.clone(), stripped));
+        assert_eq!(stripped.camera_model.as_deref(), Some("EOS R5"));
+        assert_eq!(stripped.latitude, None);
+
+        let kept = read_exif(&with_exif(plain, kept)).unwrap();
+        assert!(kept.get_field(Tag::GPSLatitude, In::PRIMARY).is_some());
+        assert!(kept.get_field(Tag::Orientation, In::PRIMARY).is_none());
+    }
+
+    #[test]
+    fn xmp_coordinates_and_dates() {
+        assert_eq!(xmp_coordinate("51,30N"), Some(51.5));
+        assert_eq!(xmp_coordinate("0,30,0W"), Some(-0.5));
+        assert_eq!(xmp_coordinate("51,30X"), None);
+        assert_eq!(
+            xmp_datetime("2021-07-08T09:10:11.5+02:00").as_deref(),
+            Some("2021-07-08T09:10:11")
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::services::db_services::DbPool;
+use crate::services::metadata::ImageMetadata;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use tauri::State;
+
+/// Setting that drops GPS tags from files written by `download_wallpaper`.
+const STRIP_LOCATION_SETTING: &str = "stripLocation";
+
+pub fn save_metadata(
+    conn: &Connection,
+    wallpaper_id: &str,
+    metadata: &ImageMetadata,
+) -> SqlResult<()> {
+    if metadata.is_empty() {
+        conn.execute(
+            "DELETE FROM wallpaper_metadata WHERE wallpaper_id = ?1",
+            params![wallpaper_id],
+        )?;
+        return Ok(());
+    }
+    let keywords = serde_json::to_string(&metadata.keywords).unwrap_or_else(|_| "[]".into());
+    conn.execute(
+        "INSERT INTO wallpaper_metadata (
+            wallpaper_id, camera_make, camera_model, lens_model, captured_at,
+            latitude, longitude, altitude, author, copyright, description, keywords
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+         ON CONFLICT(wallpaper_id) DO UPDATE SET
+            camera_make = excluded.camera_make,
+            camera_model = excluded.camera_model,
+            lens_model = excluded.lens_model,
+            captured_at = excluded.captured_at,
+            latitude = excluded.latitude,
+            longitude = excluded.longitude,
+            altitude = excluded.altitude,
+            author = excluded.author,
+            copyright = excluded.copyright,
+            description = excluded.description,
+            keywords = excluded.keywords",
+        params![
+            wallpaper_id,
+            metadata.camera_make,
+            metadata.camera_model,
+            metadata.lens_model,
+            metadata.captured_at,
+            metadata.latitude,
+            metadata.longitude,
+            metadata.altitude,
+            metadata.author,
+            metadata.copyright,
+            metadata.description,
+            keywords
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn load_metadata(conn: &Connection, wallpaper_id: &str) -> SqlResult<Option<ImageMetadata>> {
+    conn.query_row(
+        "SELECT camera_make, camera_model, lens_model, captured_at, latitude, longitude,
+                altitude, author, copyright, description, keywords
+         FROM wallpaper_metadata WHERE wallpaper_id = ?1",
+        params![wallpaper_id],
+        |row| {
+            let keywords: String = row.get(10)?;
+            Ok(ImageMetadata {
+                camera_make: row.get(0)?,
+                camera_model: row.get(1)?,
+                lens_model: row.get(2)?,
+                captured_at: row.get(3)?,
+                latitude: row.get(4)?,
+                longitude: row.get(5)?,
+                altitude: row.get(6)?,
+                author: row.get(7)?,
+                copyright: row.get(8)?,
+                description: row.get(9)?,
+                keywords: serde_json::from_str(&keywords).unwrap_or_default(),
+            })
+        },
+    )
+    .optional()
+}
+
+/// Whether downloads should leave out where the photo was taken. On unless
+/// turned off.
+pub fn strip_location_enabled(conn: &Connection) -> SqlResult<bool> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![STRIP_LOCATION_SETTING],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value.is_none_or(|v| v.trim() != "false"))
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn get_wallpaper_metadata(
+    db: State<'_, DbPool>,
+    id: String,
+) -> Result<Option<ImageMetadata>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    load_metadata(&conn, &id).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::db_services::set_setting;
+    use crate::services::migrations::migrate;
+
+    #[test]
+    fn metadata_round_trips_and_goes_with_its_wallpaper() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        conn.execute(
+            "INSERT INTO wallpapers (id, mongo_id, title, url, thumbnail, width, height, tags)
+             VALUES ('a', 'a', 'A', '/a.jpg', '', 10, 10, '')",
+            [],
+        )
+        .unwrap();
+
+        let metadata = ImageMetadata {
+            camera_model: Some("X100V".into()),
+            captured_at: Some("2022-03-04T05:06:07".into()),
+            latitude: Some(-33.86),
+            longitude: Some(151.2),
+            keywords: vec!["harbour".into(), "night".into()],
+            ..Default::default()
+        };
+        save_metadata(&conn, "a", &metadata).unwrap();
+        assert_eq!(load_metadata(&conn, "a").unwrap(), Some(metadata));
+
+        save_metadata(&conn, "a", &ImageMetadata::default()).unwrap();
+        assert_eq!(load_metadata(&conn, "a").unwrap(), None);
+
+        save_metadata(
+            &conn,
+            "a",
+            &ImageMetadata {
+                author: Some("Jo".into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        conn.execute("DELETE FROM wallpapers WHERE id = 'a'", [])
+            .unwrap();
+        assert_eq!(load_metadata(&conn, "a").unwrap(), None);
+
+        assert!(strip_location_enabled(&conn).unwrap());
+        set_setting(&conn, STRIP_LOCATION_SETTING, "false", "general").unwrap();
+        assert!(!strip_location_enabled(&conn).unwrap());
+    }
+}
@@ -0,0 +1,856 @@
+use rusqlite::{params, Connection, Result as SqlResult, Transaction};
+use std::path::Path;
+
+/// One schema step. `MIGRATIONS[i]` upgrades a database from
+/// `user_version = i` to `user_version = i + 1`.
+pub struct Migration {
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> SqlResult<()>,
+}
+
+/// Ordered schema history. Only ever append to this list; never edit a step
+/// that has shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "baseline schema",
+        up: baseline_schema,
+    },
+    Migration {
+        description: "sync change feed: availability, updated_at and sync_state",
+        up: sync_change_feed,
+    },
+    Migration {
+        description: "normalized tags and wallpaper_tags",
+        up: normalized_tags,
+    },
+    Migration {
+        description: "full-text search over titles and tags",
+        up: full_text_search,
+    },
+    Migration {
+        description: "server popularity for sorting",
+        up: popularity,
+    },
+    Migration {
+        description: "screen-sized image variants",
+        up: image_variants,
+    },
+    Migration {
+        description: "focal points and stored crops",
+        up: focal_points,
+    },
+    Migration {
+        description: "per-wallpaper effects chains",
+        up: wallpaper_effects,
+    },
+    Migration {
+        description: "color palettes",
+        up: color_palettes,
+    },
+    Migration {
+        description: "perceptual hashes",
+        up: perceptual_hashes,
+    },
+    Migration {
+        description: "local folder imports",
+        up: local_imports,
+    },
+    Migration {
+        description: "watched library folders",
+        up: library_folders,
+    },
+    Migration {
+        description: "image metadata",
+        up: image_metadata,
+    },
+    Migration {
+        description: "collections",
+        up: collections,
+    },
+    Migration {
+        description: "smart collections",
+        up: smart_collections,
+    },
+    Migration {
+        description: "ratings, hidden flag and blocklist",
+        up: ratings_and_blocklist,
+    },
+    Migration {
+        description: "full-text search keyed on wallpaper id",
+        up: fts_by_wallpaper_id,
+    },
+    Migration {
+        description: "stored shuffle keys",
+        up: shuffle_keys,
+    },
+];
+
+/// Schema version this build of the app writes.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+pub fn current_version(conn: &Connection) -> SqlResult<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Brings the database up to [`latest_version`]. If there is anything to
+/// migrate and `backup_path` is given, a copy of the existing database is
+/// written there first. Refuses to touch a database written by a newer app.
+pub fn migrate(conn: &mut Connection, backup_path: Option<&Path>) -> Result<(), String> {
+    let current = current_version(conn).map_err(|e| e.to_string())?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(format!(
+            "Database schema version {} is newer than this app supports ({}). Please update WallpaperRemix.",
+            current, latest
+        ));
+    }
+    if current == latest {
+        return Ok(());
+    }
+
+    if let Some(path) = backup_path {
+        if has_tables(conn).map_err(|e| e.to_string())? {
+            backup(conn, path)?;
+        }
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index as u32 + 1;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        (migration.up)(&tx)
+            .and_then(|_| tx.pragma_update(None, "user_version", version))
+            .and_then(|_| tx.commit())
+            .map_err(|e| {
+                format!(
+                    "Migration {} ({}) failed: {}",
+                    version, migration.description, e
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+fn has_tables(conn: &Connection) -> SqlResult<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table'",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Writes a consistent snapshot of the database, including anything still in
+/// the WAL, to `path`.
+fn backup(conn: &Connection, path: &Path) -> Result<(), String> {
+    if path.exists() {
+        std::fs::remove_file(path).map_err(|e| format!("Failed to replace backup: {}", e))?;
+    }
+    conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+        .map_err(|e| format!("Failed to back up database before migrating: {}", e))?;
+    Ok(())
+}
+
+/// Databases created before versioning may already have some of a step's
+/// columns, so column additions have to be idempotent.
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> SqlResult<()> {
+    let exists: bool = conn.query_row(
+        &format!(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?1",
+            table
+        ),
+        params![column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))?;
+    }
+    Ok(())
+}
+
+// ---------------------- Steps ----------------------
+
+/// The original unversioned schema. `IF NOT EXISTS` lets this adopt
+/// databases created before migrations existed.
+fn baseline_schema(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS wallpapers (
+            id TEXT PRIMARY KEY,
+            mongo_id TEXT UNIQUE,
+            title TEXT,
+            url TEXT,
+            thumbnail TEXT,
+            width INTEGER,
+            height INTEGER,
+            tags TEXT,
+            is_ai_generated BOOLEAN DEFAULT 0,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS favorites (
+            id TEXT PRIMARY KEY,
+            wallpaper_id TEXT,
+            added_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (wallpaper_id) REFERENCES wallpapers(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT,
+            category TEXT
+        );
+        ",
+    )
+}
+
+fn sync_change_feed(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(
+        tx,
+        "wallpapers",
+        "is_available",
+        "BOOLEAN NOT NULL DEFAULT 1",
+    )?;
+    add_column_if_missing(tx, "wallpapers", "updated_at", "TEXT")?;
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS sync_state (
+            key TEXT PRIMARY KEY,
+            value TEXT
+        );
+        ",
+    )
+}
+
+/// Moves the comma-separated `wallpapers.tags` strings into a join table so
+/// tags can be matched exactly and counted. The string column stays as the
+/// display copy.
+fn normalized_tags(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE
+        );
+
+        CREATE TABLE IF NOT EXISTS wallpaper_tags (
+            wallpaper_id TEXT NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (wallpaper_id, tag_id),
+            FOREIGN KEY (wallpaper_id) REFERENCES wallpapers(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_wallpaper_tags_tag ON wallpaper_tags(tag_id);
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_delete_tags
+        AFTER DELETE ON wallpapers
+        BEGIN
+            DELETE FROM wallpaper_tags WHERE wallpaper_id = old.id;
+        END;
+        ",
+    )?;
+
+    let rows: Vec<(String, String)> = {
+        let mut stmt = tx.prepare("SELECT id, tags FROM wallpapers WHERE tags IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<SqlResult<_>>()?
+    };
+    // Spelled out rather than shared with sync so this step never changes.
+    // `tags.name` is case-insensitive, so the first spelling seen wins and
+    // repeats within a wallpaper link to the same tag once.
+    let mut insert_tag = tx.prepare("INSERT OR IGNORE INTO tags (name) VALUES (?1)")?;
+    let mut link_tag = tx.prepare(
+        "INSERT OR IGNORE INTO wallpaper_tags (wallpaper_id, tag_id)
+         SELECT ?1, id FROM tags WHERE name = ?2",
+    )?;
+    for (id, tags) in rows {
+        for name in tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            insert_tag.execute(params![name])?;
+            link_tag.execute(params![id, name])?;
+        }
+    }
+    Ok(())
+}
+
+/// FTS5 index over titles and tags. Rows share the `wallpapers` rowid, and
+/// triggers keep the index in step with every insert, update and delete.
+fn full_text_search(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE VIRTUAL TABLE IF NOT EXISTS wallpapers_fts USING fts5(
+            title,
+            tags,
+            tokenize = 'unicode61 remove_diacritics 2',
+            prefix = '2 3'
+        );
+
+        INSERT INTO wallpapers_fts (rowid, title, tags)
+        SELECT rowid, COALESCE(title, ''), COALESCE(tags, '') FROM wallpapers;
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_fts_insert
+        AFTER INSERT ON wallpapers
+        BEGIN
+            INSERT INTO wallpapers_fts (rowid, title, tags)
+            VALUES (new.rowid, COALESCE(new.title, ''), COALESCE(new.tags, ''));
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_fts_update
+        AFTER UPDATE OF title, tags ON wallpapers
+        BEGIN
+            UPDATE wallpapers_fts
+            SET title = COALESCE(new.title, ''), tags = COALESCE(new.tags, '')
+            WHERE rowid = new.rowid;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_fts_delete
+        AFTER DELETE ON wallpapers
+        BEGIN
+            DELETE FROM wallpapers_fts WHERE rowid = old.rowid;
+        END;
+        ",
+    )
+}
+
+fn popularity(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(tx, "wallpapers", "popularity", "INTEGER NOT NULL DEFAULT 0")?;
+    tx.execute_batch(
+        "
+        CREATE INDEX IF NOT EXISTS idx_wallpapers_popularity ON wallpapers(popularity);
+        CREATE INDEX IF NOT EXISTS idx_wallpapers_created_at ON wallpapers(created_at);
+        ",
+    )
+}
+
+/// Renditions from the API as a JSON array; they're only ever read together
+/// with their wallpaper.
+fn image_variants(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(tx, "wallpapers", "variants", "TEXT NOT NULL DEFAULT '[]'")
+}
+
+/// Focal points are fractions of the image size. `focal_source` is `user` or
+/// `api`; sync never overwrites a user's choice. Crops are stored per aspect
+/// ratio, also as fractions, so every variant of a picture crops the same.
+fn focal_points(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(tx, "wallpapers", "focal_x", "REAL")?;
+    add_column_if_missing(tx, "wallpapers", "focal_y", "REAL")?;
+    add_column_if_missing(tx, "wallpapers", "focal_source", "TEXT")?;
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS wallpaper_crops (
+            wallpaper_id TEXT NOT NULL,
+            aspect TEXT NOT NULL,
+            x REAL NOT NULL,
+            y REAL NOT NULL,
+            width REAL NOT NULL,
+            height REAL NOT NULL,
+            method TEXT NOT NULL,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (wallpaper_id, aspect),
+            FOREIGN KEY (wallpaper_id) REFERENCES wallpapers(id) ON DELETE CASCADE
+        );
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_delete_crops
+        AFTER DELETE ON wallpapers
+        BEGIN
+            DELETE FROM wallpaper_crops WHERE wallpaper_id = old.id;
+        END;
+        ",
+    )
+}
+
+/// Effects chains as JSON, overriding the global chain in settings.
+fn wallpaper_effects(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS wallpaper_effects (
+            wallpaper_id TEXT PRIMARY KEY,
+            effects TEXT NOT NULL
+        );
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_delete_effects
+        AFTER DELETE ON wallpapers
+        BEGIN
+            DELETE FROM wallpaper_effects WHERE wallpaper_id = old.id;
+        END;
+        ",
+    )
+}
+
+/// Dominant and accent colors on the wallpaper row, and every palette color
+/// as channels so listings can filter by color distance.
+fn color_palettes(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(tx, "wallpapers", "dominant_color", "TEXT")?;
+    add_column_if_missing(tx, "wallpapers", "accent_color", "TEXT")?;
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS wallpaper_colors (
+            wallpaper_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            r INTEGER NOT NULL,
+            g INTEGER NOT NULL,
+            b INTEGER NOT NULL,
+            population REAL NOT NULL,
+            PRIMARY KEY (wallpaper_id, position),
+            FOREIGN KEY (wallpaper_id) REFERENCES wallpapers(id) ON DELETE CASCADE
+        );
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_delete_colors
+        AFTER DELETE ON wallpapers
+        BEGIN
+            DELETE FROM wallpaper_colors WHERE wallpaper_id = old.id;
+        END;
+        ",
+    )
+}
+
+fn perceptual_hashes(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(tx, "wallpapers", "phash", "INTEGER")
+}
+
+/// `source` is `remote` for wallpapers from the API and `local` for
+/// imported files, which keep their path in `file_path` as well as `url`.
+fn local_imports(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(tx, "wallpapers", "source", "TEXT NOT NULL DEFAULT 'remote'")?;
+    add_column_if_missing(tx, "wallpapers", "file_path", "TEXT")?;
+    tx.execute_batch(
+        "
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_wallpapers_file_path
+        ON wallpapers(file_path) WHERE file_path IS NOT NULL;
+        ",
+    )
+}
+
+fn library_folders(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS library_folders (
+            path TEXT PRIMARY KEY,
+            recursive BOOLEAN NOT NULL DEFAULT 1,
+            added_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+        ",
+    )
+}
+
+/// What imported photos say about themselves. `captured_at` is the
+/// camera's local time; `keywords` is a JSON array.
+fn image_metadata(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS wallpaper_metadata (
+            wallpaper_id TEXT PRIMARY KEY,
+            camera_make TEXT,
+            camera_model TEXT,
+            lens_model TEXT,
+            captured_at TEXT,
+            latitude REAL,
+            longitude REAL,
+            altitude REAL,
+            author TEXT,
+            copyright TEXT,
+            description TEXT,
+            keywords TEXT NOT NULL DEFAULT '[]',
+            FOREIGN KEY (wallpaper_id) REFERENCES wallpapers(id) ON DELETE CASCADE
+        );
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_delete_metadata
+        AFTER DELETE ON wallpapers
+        BEGIN
+            DELETE FROM wallpaper_metadata WHERE wallpaper_id = old.id;
+        END;
+        ",
+    )
+}
+
+/// User collections, ordered by `position`, as are their items. The
+/// built-in `favorites` collection mirrors the favorites table both ways so
+/// existing favorites keep working and show up as a collection.
+fn collections(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS collections (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            cover_wallpaper_id TEXT,
+            is_builtin BOOLEAN NOT NULL DEFAULT 0,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (cover_wallpaper_id) REFERENCES wallpapers(id) ON DELETE SET NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS collection_items (
+            collection_id TEXT NOT NULL,
+            wallpaper_id TEXT NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            added_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (collection_id, wallpaper_id),
+            FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE,
+            FOREIGN KEY (wallpaper_id) REFERENCES wallpapers(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_collection_items_wallpaper
+        ON collection_items(wallpaper_id);
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_delete_collection_items
+        AFTER DELETE ON wallpapers
+        BEGIN
+            DELETE FROM collection_items WHERE wallpaper_id = old.id;
+            UPDATE collections SET cover_wallpaper_id = NULL
+            WHERE cover_wallpaper_id = old.id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS collections_delete_items
+        AFTER DELETE ON collections
+        BEGIN
+            DELETE FROM collection_items WHERE collection_id = old.id;
+        END;
+
+        INSERT OR IGNORE INTO collections (id, name, position, is_builtin)
+        VALUES ('favorites', 'Favorites', 0, 1);
+
+        INSERT OR IGNORE INTO collection_items (collection_id, wallpaper_id, position, added_at)
+        SELECT 'favorites', f.wallpaper_id,
+               ROW_NUMBER() OVER (ORDER BY MIN(f.added_at), f.wallpaper_id) - 1,
+               MIN(f.added_at)
+        FROM favorites f
+        JOIN wallpapers w ON w.id = f.wallpaper_id
+        GROUP BY f.wallpaper_id;
+
+        CREATE TRIGGER IF NOT EXISTS favorites_insert_collection_item
+        AFTER INSERT ON favorites
+        WHEN new.wallpaper_id IS NOT NULL
+        BEGIN
+            INSERT OR IGNORE INTO collection_items (collection_id, wallpaper_id, position)
+            VALUES (
+                'favorites',
+                new.wallpaper_id,
+                COALESCE(
+                    (SELECT MAX(position) + 1 FROM collection_items
+                     WHERE collection_id = 'favorites'),
+                    0
+                )
+            );
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS favorites_delete_collection_item
+        AFTER DELETE ON favorites
+        WHEN NOT EXISTS (SELECT 1 FROM favorites WHERE wallpaper_id = old.wallpaper_id)
+        BEGIN
+            DELETE FROM collection_items
+            WHERE collection_id = 'favorites' AND wallpaper_id = old.wallpaper_id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS collection_items_insert_favorite
+        AFTER INSERT ON collection_items
+        WHEN new.collection_id = 'favorites'
+            AND NOT EXISTS (SELECT 1 FROM favorites WHERE wallpaper_id = new.wallpaper_id)
+        BEGIN
+            INSERT INTO favorites (id, wallpaper_id)
+            VALUES (lower(hex(randomblob(16))), new.wallpaper_id);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS collection_items_delete_favorite
+        AFTER DELETE ON collection_items
+        WHEN old.collection_id = 'favorites'
+        BEGIN
+            DELETE FROM favorites WHERE wallpaper_id = old.wallpaper_id;
+        END;
+        ",
+    )
+}
+
+/// Saved searches: a `WallpaperFilter` and `WallpaperSort` as JSON, run
+/// against the library whenever they are read.
+fn smart_collections(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS smart_collections (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            filter TEXT NOT NULL DEFAULT '{}',
+            sort TEXT NOT NULL DEFAULT '{\"by\":\"newest\"}',
+            position INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+        ",
+    )
+}
+
+/// Blocklist entries carry every key the wallpaper could come back under,
+/// so it stays blocked after its row is deleted and synced or rescanned in
+/// again.
+fn ratings_and_blocklist(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(
+        tx,
+        "wallpapers",
+        "rating",
+        "INTEGER CHECK (rating BETWEEN 1 AND 5)",
+    )?;
+    add_column_if_missing(tx, "wallpapers", "is_hidden", "BOOLEAN NOT NULL DEFAULT 0")?;
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS blocklist (
+            id INTEGER PRIMARY KEY,
+            wallpaper_id TEXT UNIQUE,
+            mongo_id TEXT UNIQUE,
+            file_path TEXT UNIQUE,
+            title TEXT NOT NULL DEFAULT '',
+            blocked_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+        ",
+    )
+}
+
+/// Rebuilds the search index around `wallpapers.id`. The implicit rowid it
+/// was keyed on may be renumbered by `VACUUM`, which would point matches at
+/// the wrong wallpaper. `id` comes last so the `bm25` weights still line up
+/// with `title` and `tags`.
+fn fts_by_wallpaper_id(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "
+        DROP TRIGGER IF EXISTS wallpapers_fts_insert;
+        DROP TRIGGER IF EXISTS wallpapers_fts_update;
+        DROP TRIGGER IF EXISTS wallpapers_fts_delete;
+        DROP TABLE IF EXISTS wallpapers_fts;
+
+        CREATE VIRTUAL TABLE wallpapers_fts USING fts5(
+            title,
+            tags,
+            id UNINDEXED,
+            tokenize = 'unicode61 remove_diacritics 2',
+            prefix = '2 3'
+        );
+
+        INSERT INTO wallpapers_fts (title, tags, id)
+        SELECT COALESCE(title, ''), COALESCE(tags, ''), id FROM wallpapers;
+
+        CREATE TRIGGER wallpapers_fts_insert
+        AFTER INSERT ON wallpapers
+        BEGIN
+            INSERT INTO wallpapers_fts (title, tags, id)
+            VALUES (COALESCE(new.title, ''), COALESCE(new.tags, ''), new.id);
+        END;
+
+        CREATE TRIGGER wallpapers_fts_update
+        AFTER UPDATE OF id, title, tags ON wallpapers
+        BEGIN
+            UPDATE wallpapers_fts
+            SET title = COALESCE(new.title, ''), tags = COALESCE(new.tags, ''), id = new.id
+            WHERE id = old.id;
+        END;
+
+        CREATE TRIGGER wallpapers_fts_delete
+        AFTER DELETE ON wallpapers
+        BEGIN
+            DELETE FROM wallpapers_fts WHERE id = old.id;
+        END;
+        ",
+    )
+}
+
+/// Random sorts shuffle a key stored with each wallpaper rather than its
+/// rowid, which `VACUUM` may renumber. Keys start out as the rowid, so
+/// existing shuffles keep their order, and are never changed afterwards.
+fn shuffle_keys(tx: &Transaction) -> SqlResult<()> {
+    add_column_if_missing(tx, "wallpapers", "shuffle_key", "INTEGER")?;
+    tx.execute_batch(
+        "
+        UPDATE wallpapers SET shuffle_key = rowid % 2147483647 WHERE shuffle_key IS NULL;
+
+        CREATE TRIGGER IF NOT EXISTS wallpapers_shuffle_key
+        AFTER INSERT ON wallpapers
+        WHEN new.shuffle_key IS NULL
+        BEGIN
+            UPDATE wallpapers SET shuffle_key = new.rowid % 2147483647 WHERE id = new.id;
+        END;
+        ",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column_names(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        let columns = column_names(&conn, "wallpapers");
+        assert!(columns.contains(&"is_available".to_string()));
+        assert!(columns.contains(&"updated_at".to_string()));
+    }
+
+    #[test]
+    fn each_step_applies_in_order() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            let tx = conn.transaction().unwrap();
+            (migration.up)(&tx).unwrap();
+            tx.pragma_update(None, "user_version", index as u32 + 1)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        // Re-running from the top is a no-op.
+        migrate(&mut conn, None).unwrap();
+    }
+
+    #[test]
+    fn unversioned_legacy_database_keeps_its_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let tx = conn.transaction().unwrap();
+        baseline_schema(&tx).unwrap();
+        tx.commit().unwrap();
+        conn.execute(
+            "INSERT INTO wallpapers (id, mongo_id, title) VALUES ('a', 'm1', 'Old')",
+            [],
+        )
+        .unwrap();
+
+        migrate(&mut conn, None).unwrap();
+
+        let (title, available): (String, bool) = conn
+            .query_row(
+                "SELECT title, is_available FROM wallpapers WHERE id = 'a'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(title, "Old");
+        assert!(available);
+    }
+
+    #[test]
+    fn legacy_tag_strings_are_split() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        for (index, migration) in MIGRATIONS.iter().take(2).enumerate() {
+            let tx = conn.transaction().unwrap();
+            (migration.up)(&tx).unwrap();
+            tx.pragma_update(None, "user_version", index as u32 + 1)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        conn.execute_batch(
+            "
+            INSERT INTO wallpapers (id, mongo_id, tags) VALUES ('a', 'm1', 'car, Night,,night ');
+            INSERT INTO wallpapers (id, mongo_id, tags) VALUES ('b', 'm2', 'cartoon');
+            ",
+        )
+        .unwrap();
+
+        migrate(&mut conn, None).unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT t.name FROM wallpaper_tags wt JOIN tags t ON t.id = wt.tag_id
+                 WHERE wt.wallpaper_id = 'a' ORDER BY t.name",
+            )
+            .unwrap();
+        let names: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(names, vec!["car", "Night"]);
+
+        let tag_count: u32 = conn
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tag_count, 3);
+    }
+
+    #[test]
+    fn favorites_become_a_collection() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let before = MIGRATIONS
+            .iter()
+            .position(|m| m.description == "collections")
+            .unwrap();
+        for (index, migration) in MIGRATIONS.iter().take(before).enumerate() {
+            let tx = conn.transaction().unwrap();
+            (migration.up)(&tx).unwrap();
+            tx.pragma_update(None, "user_version", index as u32 + 1)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        conn.execute_batch(
+            "
+            INSERT INTO wallpapers (id, mongo_id, title, url) VALUES ('a', 'm1', 'A', 'a');
+            INSERT INTO wallpapers (id, mongo_id, title, url) VALUES ('b', 'm2', 'B', 'b');
+            INSERT INTO favorites (id, wallpaper_id, added_at)
+            VALUES ('f1', 'b', '2024-01-01'), ('f2', 'a', '2024-02-01'), ('f3', 'b', '2024-03-01');
+            ",
+        )
+        .unwrap();
+
+        migrate(&mut conn, None).unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT wallpaper_id FROM collection_items
+                 WHERE collection_id = 'favorites' ORDER BY position",
+            )
+            .unwrap();
+        let ids: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(ids, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn newer_database_is_rejected() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        let err = migrate(&mut conn, None).unwrap_err();
+        assert!(err.contains("newer than this app supports"));
+    }
+
+    #[test]
+    fn backup_is_written_before_migrating() {
+        let dir = std::env::temp_dir().join(format!("wallpaper-migrate-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let backup_path = dir.join("backup.sqlite");
+
+        let mut conn = Connection::open(dir.join("db.sqlite")).unwrap();
+        let tx = conn.transaction().unwrap();
+        baseline_schema(&tx).unwrap();
+        tx.commit().unwrap();
+
+        migrate(&mut conn, Some(&backup_path)).unwrap();
+
+        let backup = Connection::open(&backup_path).unwrap();
+        assert_eq!(current_version(&backup).unwrap(), 0);
+        assert!(!column_names(&backup, "wallpapers").contains(&"is_available".to_string()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
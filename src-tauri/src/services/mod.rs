@@ -1,3 +1,27 @@
+pub mod codecs;
+pub mod collection_service;
+pub mod color_scheme;
+pub mod color_scheme_service;
+pub mod crop_service;
 pub mod db_services;
+pub mod duplicate_service;
+pub mod effects;
+pub mod effects_service;
+pub mod image_pipeline;
+pub mod import_service;
+pub mod library_service;
+pub mod metadata;
+pub mod metadata_service;
+pub mod migrations;
+pub mod overlay;
+pub mod overlay_service;
+pub mod palette;
+pub mod palette_service;
+pub mod phash;
+pub mod query_service;
+pub mod search_service;
+pub mod smart_collection_service;
 pub mod sync_service;
+pub mod thumbnail_service;
+pub mod variant_service;
 pub mod wallpaper_service;
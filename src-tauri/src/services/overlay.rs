@@ -0,0 +1,598 @@
+use crate::services::effects::{fingerprint, parse_hex_color};
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime};
+use image::{DynamicImage, RgbaImage};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+static REGULAR: Lazy<FontRef<'static>> = Lazy::new(|| {
+    FontRef::try_from_slice(include_bytes!("../../assets/fonts/DejaVuSans.ttf"))
+        .expect("bundled font is valid")
+});
+static BOLD: Lazy<FontRef<'static>> = Lazy::new(|| {
+    FontRef::try_from_slice(include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf"))
+        .expect("bundled font is valid")
+});
+
+/// Quotes used when the user hasn't set their own, one per line.
+pub const DEFAULT_QUOTES: &str = include_str!("../../assets/quotes.txt");
+
+/// Gap between the overlay and the image edges, as a fraction of the shorter side.
+const MARGIN: f32 = 0.04;
+/// Longest a line of text gets before wrapping, as a fraction of the image width.
+const MAX_LINE_WIDTH: f32 = 0.45;
+const SHADOW_ALPHA: f32 = 0.55;
+
+/// What an overlay item shows.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Widget {
+    /// Today's date; `format` is a strftime pattern, `%A, %B %-d` by default.
+    Date {
+        #[serde(default)]
+        format: Option<String>,
+    },
+    /// The time of the render, `%H:%M` by default. Only stays current with
+    /// the overlay refresh on.
+    Clock {
+        #[serde(default)]
+        format: Option<String>,
+    },
+    /// The quote of the day from the overlay's quote list.
+    Quote,
+    Text {
+        text: String,
+    },
+    /// This month, weeks starting on Monday, with today highlighted.
+    Calendar,
+}
+
+/// Where on the image an item goes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    #[default]
+    BottomRight,
+}
+
+impl Anchor {
+    const ALL: [Anchor; 9] = [
+        Anchor::TopLeft,
+        Anchor::Top,
+        Anchor::TopRight,
+        Anchor::Left,
+        Anchor::Center,
+        Anchor::Right,
+        Anchor::BottomLeft,
+        Anchor::Bottom,
+        Anchor::BottomRight,
+    ];
+
+    /// Horizontal and vertical placement, 0 at the start and 1 at the end.
+    fn placement(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+fn default_color() -> String {
+    "#ffffff".to_string()
+}
+
+fn default_size() -> f32 {
+    0.04
+}
+
+fn default_shadow() -> bool {
+    true
+}
+
+/// One widget and how it is drawn. Items sharing a position stack downwards
+/// in the order given.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OverlayItem {
+    #[serde(flatten)]
+    pub widget: Widget,
+    #[serde(default)]
+    pub position: Anchor,
+    /// `#rrggbb`; white if missing or invalid.
+    #[serde(default = "default_color")]
+    pub color: String,
+    /// Text height as a fraction of the image height.
+    #[serde(default = "default_size")]
+    pub size: f32,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default = "default_shadow")]
+    pub shadow: bool,
+}
+
+/// Items to draw, with the moment and quote they show.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    pub items: Vec<OverlayItem>,
+    pub now: NaiveDateTime,
+    pub quote: Option<String>,
+}
+
+enum Content {
+    Lines(Vec<String>),
+    Calendar(NaiveDate),
+}
+
+impl Overlay {
+    fn content(&self, widget: &Widget) -> Content {
+        match widget {
+            Widget::Date { format } => {
+                Content::Lines(vec![format_time(self.now, format.as_deref(), "%A, %B %-d")])
+            }
+            Widget::Clock { format } => {
+                Content::Lines(vec![format_time(self.now, format.as_deref(), "%H:%M")])
+            }
+            Widget::Quote => Content::Lines(self.quote.iter().cloned().collect()),
+            Widget::Text { text } => Content::Lines(text.lines().map(String::from).collect()),
+            Widget::Calendar => Content::Calendar(self.now.date()),
+        }
+    }
+
+    /// Hash of what the overlay draws rather than when it was made, so a
+    /// date overlay only invalidates cached renders once a day.
+    pub fn fingerprint(&self) -> String {
+        let mut parts = vec![serde_json::to_string(&self.items).unwrap_or_default()];
+        for item in &self.items {
+            parts.push(match self.content(&item.widget) {
+                Content::Lines(lines) => lines.join("\n"),
+                Content::Calendar(date) => date.to_string(),
+            });
+        }
+        let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+        fingerprint(&parts)
+    }
+}
+
+/// Formats `now` with `format`, falling back to `default` when the pattern
+/// is invalid.
+fn format_time(now: NaiveDateTime, format: Option<&str>, default: &str) -> String {
+    let mut out = String::new();
+    if write!(out, "{}", now.format(format.unwrap_or(default))).is_ok() {
+        return out;
+    }
+    now.format(default).to_string()
+}
+
+/// Picks the quote for `date` from `quotes`, the same one all day.
+pub fn quote_of_the_day(quotes: &[&str], date: NaiveDate) -> Option<String> {
+    let quotes: Vec<&str> = quotes
+        .iter()
+        .map(|q| q.trim())
+        .filter(|q| !q.is_empty())
+        .collect();
+    if quotes.is_empty() {
+        return None;
+    }
+    let day = date.num_days_from_ce().unsigned_abs() as usize;
+    Some(quotes[day % quotes.len()].to_string())
+}
+
+/// A piece of text placed relative to its block.
+struct Run {
+    text: String,
+    x: f32,
+    baseline: f32,
+    bold: bool,
+}
+
+/// An item laid out at a size, ready to be placed.
+struct Block<'a> {
+    item: &'a OverlayItem,
+    px: f32,
+    runs: Vec<Run>,
+    /// Calendar cells drawn behind the text: x, y, width, height.
+    highlights: Vec<[f32; 4]>,
+    width: f32,
+    height: f32,
+}
+
+/// Draws `overlay` onto `img`.
+pub fn render_overlay(img: DynamicImage, overlay: &Overlay) -> DynamicImage {
+    let mut canvas = img.to_rgba8();
+    let (width, height) = (canvas.width() as f32, canvas.height() as f32);
+    let margin = width.min(height) * MARGIN;
+
+    for anchor in Anchor::ALL {
+        let (fx, fy) = anchor.placement();
+        let blocks: Vec<Block> = overlay
+            .items
+            .iter()
+            .filter(|item| item.position == anchor)
+            .map(|item| layout(item, overlay.content(&item.widget), width, height, fx))
+            .collect();
+        if blocks.is_empty() {
+            continue;
+        }
+
+        let gap = |block: &Block| block.px * 0.5;
+        let stack_height: f32 =
+            blocks.iter().map(|b| b.height + gap(b)).sum::<f32>() - gap(blocks.last().unwrap());
+        let mut y = margin + (height - 2.0 * margin - stack_height) * fy;
+        for block in &blocks {
+            let x = margin + (width - 2.0 * margin - block.width) * fx;
+            draw_block(&mut canvas, block, x, y);
+            y += block.height + gap(block);
+        }
+    }
+
+    DynamicImage::ImageRgba8(canvas)
+}
+
+fn font(bold: bool) -> &'static FontRef<'static> {
+    if bold {
+        &BOLD
+    } else {
+        &REGULAR
+    }
+}
+
+fn layout<'a>(
+    item: &'a OverlayItem,
+    content: Content,
+    width: f32,
+    height: f32,
+    align: f32,
+) -> Block<'a> {
+    let px = (item.size.clamp(0.005, 0.5) * height).max(6.0);
+    let scaled = font(item.bold).as_scaled(PxScale::from(px));
+    let line_height = scaled.height() + scaled.line_gap();
+    let ascent = scaled.ascent();
+
+    let mut block = Block {
+        item,
+        px,
+        runs: Vec::new(),
+        highlights: Vec::new(),
+        width: 0.0,
+        height: 0.0,
+    };
+
+    match content {
+        Content::Lines(lines) => {
+            let lines: Vec<String> = lines
+                .iter()
+                .flat_map(|line| wrap(line, item.bold, px, width * MAX_LINE_WIDTH))
+                .collect();
+            let widths: Vec<f32> = lines.iter().map(|l| text_width(l, item.bold, px)).collect();
+            block.width = widths.iter().cloned().fold(0.0, f32::max);
+            block.height = line_height * lines.len() as f32;
+            for (index, (text, line_width)) in lines.into_iter().zip(widths).enumerate() {
+                block.runs.push(Run {
+                    text,
+                    x: (block.width - line_width) * align,
+                    baseline: ascent + line_height * index as f32,
+                    bold: item.bold,
+                });
+            }
+        }
+        Content::Calendar(today) => {
+            let cell = text_width("00", true, px) * 1.6;
+            block.width = cell * 7.0;
+            let centered =
+                |block: &mut Block, text: String, row: f32, left: f32, span: f32, bold| {
+                    let x = left + (span - text_width(&text, bold, px)) / 2.0;
+                    block.runs.push(Run {
+                        text,
+                        x,
+                        baseline: ascent + line_height * row,
+                        bold,
+                    });
+                };
+
+            let first = today.with_day(1).unwrap_or(today);
+            centered(
+                &mut block,
+                first.format("%B %Y").to_string(),
+                0.0,
+                0.0,
+                cell * 7.0,
+                true,
+            );
+            for (column, name) in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"]
+                .iter()
+                .enumerate()
+            {
+                centered(
+                    &mut block,
+                    name.to_string(),
+                    1.0,
+                    cell * column as f32,
+                    cell,
+                    item.bold,
+                );
+            }
+
+            let offset = first.weekday().num_days_from_monday();
+            let days = first
+                .checked_add_months(Months::new(1))
+                .and_then(|next| next.pred_opt())
+                .map_or(28, |last| last.day());
+            let mut row = 2.0;
+            for day in 1..=days {
+                let slot = offset + day - 1;
+                if slot % 7 == 0 && day > 1 {
+                    row += 1.0;
+                }
+                let left = cell * (slot % 7) as f32;
+                if day == today.day() {
+                    block
+                        .highlights
+                        .push([left, line_height * row, cell, line_height]);
+                }
+                centered(
+                    &mut block,
+                    day.to_string(),
+                    row,
+                    left,
+                    cell,
+                    day == today.day(),
+                );
+            }
+            block.height = line_height * (row + 1.0);
+        }
+    }
+    block
+}
+
+/// Splits `text` at spaces so no line is wider than `max_width`, unless a
+/// single word is.
+fn wrap(text: &str, bold: bool, px: f32, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if !line.is_empty() && text_width(&candidate, bold, px) > max_width {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        } else {
+            line = candidate;
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn text_width(text: &str, bold: bool, px: f32) -> f32 {
+    let scaled = font(bold).as_scaled(PxScale::from(px));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+fn draw_block(canvas: &mut RgbaImage, block: &Block, x: f32, y: f32) {
+    let color = parse_hex_color(&block.item.color).unwrap_or([255.0; 3]);
+
+    for [left, top, width, height] in &block.highlights {
+        fill_rect(canvas, x + left, y + top, *width, *height, color, 0.3);
+    }
+    if block.item.shadow {
+        let offset = (block.px / 16.0).max(1.0);
+        for run in &block.runs {
+            draw_text(
+                canvas,
+                run,
+                block.px,
+                x + offset,
+                y + offset,
+                [0.0; 3],
+                SHADOW_ALPHA,
+            );
+        }
+    }
+    for run in &block.runs {
+        draw_text(canvas, run, block.px, x, y, color, 1.0);
+    }
+}
+
+fn draw_text(
+    canvas: &mut RgbaImage,
+    run: &Run,
+    px: f32,
+    x: f32,
+    y: f32,
+    color: [f32; 3],
+    alpha: f32,
+) {
+    let font = font(run.bold);
+    let scaled = font.as_scaled(PxScale::from(px));
+    let mut caret = x + run.x;
+    let mut previous = None;
+    for c in run.text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(px, point(caret, y + run.baseline));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                blend(
+                    canvas,
+                    bounds.min.x as i64 + i64::from(gx),
+                    bounds.min.y as i64 + i64::from(gy),
+                    color,
+                    coverage * alpha,
+                );
+            });
+        }
+    }
+}
+
+fn fill_rect(
+    canvas: &mut RgbaImage,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    color: [f32; 3],
+    alpha: f32,
+) {
+    for py in y.round() as i64..(y + height).round() as i64 {
+        for px in x.round() as i64..(x + width).round() as i64 {
+            blend(canvas, px, py, color, alpha);
+        }
+    }
+}
+
+fn blend(canvas: &mut RgbaImage, x: i64, y: i64, color: [f32; 3], alpha: f32) {
+    if x < 0 || y < 0 || x >= i64::from(canvas.width()) || y >= i64::from(canvas.height()) {
+        return;
+    }
+    let alpha = alpha.clamp(0.0, 1.0);
+    let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+    for (channel, target) in pixel.0.iter_mut().zip(color) {
+        let value = f32::from(*channel);
+        *channel = (value + (target - value) * alpha).round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn item(json: &str) -> OverlayItem {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn lit(canvas: &RgbaImage, x: std::ops::Range<u32>, y: std::ops::Range<u32>) -> usize {
+        y.flat_map(|y| x.clone().map(move |x| (x, y)))
+            .filter(|&(x, y)| canvas.get_pixel(x, y).0[0] > 128)
+            .count()
+    }
+
+    #[test]
+    fn items_parse_with_defaults() {
+        let clock = item(r#"{"type":"clock"}"#);
+        assert_eq!(clock.widget, Widget::Clock { format: None });
+        assert_eq!(clock.position, Anchor::BottomRight);
+        assert_eq!(clock.color, "#ffffff");
+        assert!(clock.shadow && !clock.bold);
+
+        let text = item(r##"{"type":"text","text":"Hi","position":"top-left","color":"#ff0000"}"##);
+        assert_eq!(text.position, Anchor::TopLeft);
+    }
+
+    #[test]
+    fn draws_in_the_anchored_corner() {
+        let overlay = Overlay {
+            items: vec![item(
+                r#"{"type":"text","text":"Hello","position":"top-left","size":0.2,"shadow":false}"#,
+            )],
+            now: at("2026-10-19", "08:30"),
+            quote: None,
+        };
+        let black = DynamicImage::ImageRgba8(RgbaImage::from_pixel(200, 100, Rgba([0, 0, 0, 255])));
+        let out = render_overlay(black, &overlay).to_rgba8();
+        assert!(lit(&out, 0..100, 0..50) > 50);
+        assert_eq!(lit(&out, 100..200, 50..100), 0);
+    }
+
+    #[test]
+    fn calendar_and_stacked_items_fit() {
+        let overlay = Overlay {
+            items: vec![
+                item(r#"{"type":"clock","position":"bottom-left"}"#),
+                item(r#"{"type":"calendar","position":"bottom-left"}"#),
+            ],
+            now: at("2026-02-28", "23:59"),
+            quote: None,
+        };
+        let grey =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(640, 480, Rgba([60, 60, 60, 255])));
+        let out = render_overlay(grey, &overlay).to_rgba8();
+        assert!(lit(&out, 0..320, 0..480) > 100);
+        assert_eq!(lit(&out, 320..640, 0..480), 0);
+    }
+
+    #[test]
+    fn fingerprint_follows_what_is_drawn() {
+        let overlay = |items: &str, now| Overlay {
+            items: serde_json::from_str(items).unwrap(),
+            now,
+            quote: None,
+        };
+        let date = r#"[{"type":"date"}]"#;
+        let clock = r#"[{"type":"clock"}]"#;
+        let morning = at("2026-10-19", "08:30");
+        let evening = at("2026-10-19", "20:00");
+        let tomorrow = at("2026-10-20", "08:30");
+
+        assert_eq!(
+            overlay(date, morning).fingerprint(),
+            overlay(date, evening).fingerprint()
+        );
+        assert_ne!(
+            overlay(date, morning).fingerprint(),
+            overlay(date, tomorrow).fingerprint()
+        );
+        assert_ne!(
+            overlay(clock, morning).fingerprint(),
+            overlay(clock, evening).fingerprint()
+        );
+    }
+
+    #[test]
+    fn quote_and_format_fallbacks() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let quotes = ["first", " ", "second"];
+        assert_eq!(
+            quote_of_the_day(&quotes, day),
+            quote_of_the_day(&quotes, day)
+        );
+        assert_ne!(
+            quote_of_the_day(&quotes, day),
+            quote_of_the_day(&quotes, day.succ_opt().unwrap())
+        );
+        assert_eq!(quote_of_the_day(&[], day), None);
+
+        let now = at("2026-10-19", "08:05");
+        assert_eq!(format_time(now, Some("%H:%M"), "%H:%M"), "08:05");
+        assert_eq!(format_time(now, Some("%Q"), "%H:%M"), "08:05");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::services::db_services::{set_setting, DbPool};
+use crate::services::overlay::{quote_of_the_day, Overlay, OverlayItem, DEFAULT_QUOTES};
+use chrono::NaiveDateTime;
+use rusqlite::{Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::State;
+
+/// Shortest re-render interval, so a misconfigured refresh can't keep the
+/// pipeline busy.
+const MIN_REFRESH_SEC: u64 = 10;
+
+/// The overlay drawn on applied wallpapers, kept in the `overlay` setting.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct OverlaySettings {
+    pub enabled: bool,
+    pub items: Vec<OverlayItem>,
+    /// The quote widget's list; empty uses the bundled one.
+    pub quotes: Vec<String>,
+    /// Seconds between re-renders of the current wallpaper, e.g. to keep a
+    /// clock current. `0` renders once per apply.
+    pub refresh_interval_sec: u64,
+}
+
+pub fn get_overlay_settings(conn: &Connection) -> SqlResult<OverlaySettings> {
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = 'overlay'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(json
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+pub fn set_overlay_settings(conn: &Connection, settings: &OverlaySettings) -> SqlResult<()> {
+    let json = serde_json::to_string(settings).unwrap_or_else(|_| "{}".to_string());
+    set_setting(conn, "overlay", &json, "wallpaper")
+}
+
+/// The overlay to draw at `now`, or `None` when it is off or empty.
+pub fn current_overlay(conn: &Connection, now: NaiveDateTime) -> SqlResult<Option<Overlay>> {
+    let settings = get_overlay_settings(conn)?;
+    if !settings.enabled || settings.items.is_empty() {
+        return Ok(None);
+    }
+
+    let quotes: Vec<&str> = if settings.quotes.is_empty() {
+        DEFAULT_QUOTES.lines().collect()
+    } else {
+        settings.quotes.iter().map(String::as_str).collect()
+    };
+    Ok(Some(Overlay {
+        quote: quote_of_the_day(&quotes, now.date()),
+        items: settings.items,
+        now,
+    }))
+}
+
+/// How often the current wallpaper should be re-rendered, if at all.
+pub fn refresh_interval(conn: &Connection) -> SqlResult<Option<Duration>> {
+    let settings = get_overlay_settings(conn)?;
+    if !settings.enabled || settings.items.is_empty() || settings.refresh_interval_sec == 0 {
+        return Ok(None);
+    }
+    Ok(Some(Duration::from_secs(
+        settings.refresh_interval_sec.max(MIN_REFRESH_SEC),
+    )))
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn get_overlay_settings_command(
+    db: State<'_, DbPool>,
+) -> Result<OverlaySettings, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    get_overlay_settings(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_overlay_settings_command(
+    db: State<'_, DbPool>,
+    settings: OverlaySettings,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    set_overlay_settings(&conn, &settings).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::migrations::migrate;
+
+    #[test]
+    fn overlay_only_when_enabled_with_items() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        let now = NaiveDateTime::parse_from_str("2026-10-19 08:30", "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(current_overlay(&conn, now).unwrap(), None);
+
+        let mut settings = OverlaySettings {
+            enabled: true,
+            items: serde_json::from_str(r#"[{"type":"quote"}]"#).unwrap(),
+            quotes: Vec::new(),
+            refresh_interval_sec: 1,
+        };
+        set_overlay_settings(&conn, &settings).unwrap();
+        let overlay = current_overlay(&conn, now).unwrap().unwrap();
+        assert!(overlay.quote.is_some());
+        assert_eq!(
+            refresh_interval(&conn).unwrap(),
+            Some(Duration::from_secs(MIN_REFRESH_SEC))
+        );
+
+        settings.quotes = vec!["Only one".to_string()];
+        settings.enabled = false;
+        set_overlay_settings(&conn, &settings).unwrap();
+        assert_eq!(get_overlay_settings(&conn).unwrap(), settings);
+        assert_eq!(current_overlay(&conn, now).unwrap(), None);
+        assert_eq!(refresh_interval(&conn).unwrap(), None);
+    }
+}
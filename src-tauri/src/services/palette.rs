@@ -0,0 +1,182 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+/// Side of the copy colors are sampled from.
+const SAMPLE_SIZE: u32 = 64;
+
+/// Colors kept per wallpaper.
+pub const PALETTE_SIZE: usize = 6;
+
+/// One palette color and the share of the image it stands for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Swatch {
+    /// `#rrggbb`.
+    pub color: String,
+    /// Fraction of the sampled pixels, 0–1.
+    pub population: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Palette {
+    /// The most common color.
+    pub dominant: String,
+    /// The most vivid color that still covers part of the image; the
+    /// dominant one for images without any.
+    pub accent: String,
+    /// Most common first.
+    pub colors: Vec<Swatch>,
+}
+
+pub fn to_hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+/// Median-cut palette of up to `size` colors, from a downscaled copy.
+/// Transparent pixels are ignored; `None` if nothing is left.
+pub fn extract_palette(img: &DynamicImage, size: usize) -> Option<Palette> {
+    let sample = if img.width().max(img.height()) > SAMPLE_SIZE {
+        img.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE)
+    } else {
+        img.clone()
+    };
+    let pixels: Vec<[u8; 3]> = sample
+        .to_rgba8()
+        .pixels()
+        .filter(|p| p.0[3] >= 128)
+        .map(|p| [p.0[0], p.0[1], p.0[2]])
+        .collect();
+    if pixels.is_empty() || size == 0 {
+        return None;
+    }
+    let total = pixels.len() as f32;
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < size {
+        // Split the box spanning the widest range of any channel.
+        let Some((index, channel, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, pixels)| pixels.len() > 1)
+            .map(|(index, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (index, channel, range)
+            })
+            .filter(|&(_, _, range)| range > 0)
+            .max_by_key(|&(_, _, range)| range)
+        else {
+            break;
+        };
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|p| p[channel]);
+        let upper = pixels.split_off(pixels.len() / 2);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    // Medians can split a run of one color, leaving boxes with the same average.
+    let mut swatches: Vec<([u8; 3], f32)> = Vec::new();
+    for pixels in &boxes {
+        let rgb = average(pixels);
+        let population = pixels.len() as f32 / total;
+        match swatches.iter_mut().find(|(existing, _)| *existing == rgb) {
+            Some((_, share)) => *share += population,
+            None => swatches.push((rgb, population)),
+        }
+    }
+    swatches.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let dominant = swatches[0].0;
+    let accent = swatches
+        .iter()
+        .map(|&(rgb, population)| (rgb, vividness(rgb) * population.sqrt()))
+        .filter(|&(_, score)| score > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(dominant, |(rgb, _)| rgb);
+
+    Some(Palette {
+        dominant: to_hex(dominant),
+        accent: to_hex(accent),
+        colors: swatches
+            .into_iter()
+            .map(|(rgb, population)| Swatch {
+                color: to_hex(rgb),
+                population,
+            })
+            .collect(),
+    })
+}
+
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = pixels.iter().fold((u8::MAX, u8::MIN), |(min, max), p| {
+                (min.min(p[channel]), max.max(p[channel]))
+            });
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+fn average(pixels: &[[u8; 3]]) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    for p in pixels {
+        for (s, &c) in sum.iter_mut().zip(p) {
+            *s += u64::from(c);
+        }
+    }
+    let n = pixels.len().max(1) as u64;
+    sum.map(|s| ((s + n / 2) / n) as u8)
+}
+
+/// How well a color works as an accent: saturated, and neither near black
+/// nor near white.
+pub fn vividness(rgb: [u8; 3]) -> f32 {
+    let [r, g, b] = rgb.map(|c| f32::from(c) / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let saturation = if max == min {
+        0.0
+    } else {
+        (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+    };
+    saturation * (1.0 - (lightness - 0.5).abs() * 2.0).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn dominant_is_the_largest_area_and_accent_the_most_vivid() {
+        // Three quarters grey, one quarter strong orange.
+        let img = RgbaImage::from_fn(40, 40, |x, _| {
+            if x < 30 {
+                Rgba([120, 120, 120, 255])
+            } else {
+                Rgba([240, 120, 20, 255])
+            }
+        });
+        let palette = extract_palette(&DynamicImage::ImageRgba8(img), PALETTE_SIZE).unwrap();
+        assert_eq!(palette.dominant, "#787878");
+        assert_eq!(palette.accent, "#f07814");
+        assert_eq!(palette.colors.len(), 2);
+        assert!((palette.colors[0].population - 0.75).abs() < 0.01);
+    }
+
+    #[test]
+    fn flat_and_transparent_images() {
+        let flat = RgbaImage::from_pixel(8, 8, Rgba([10, 20, 30, 255]));
+        let palette = extract_palette(&DynamicImage::ImageRgba8(flat), PALETTE_SIZE).unwrap();
+        assert_eq!(palette.colors.len(), 1);
+        assert_eq!(palette.accent, palette.dominant);
+
+        let clear = RgbaImage::from_pixel(8, 8, Rgba([10, 20, 30, 0]));
+        assert_eq!(
+            extract_palette(&DynamicImage::ImageRgba8(clear), PALETTE_SIZE),
+            None
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use crate::services::db_services::{set_setting, DbPool};
+use crate::services::effects::parse_hex_color;
+use crate::services::image_pipeline::decode;
+use crate::services::palette::{extract_palette, to_hex, Palette, Swatch, PALETTE_SIZE};
+use crate::services::thumbnail_service::{stored_thumbnail, thumbnail_dir};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Setting holding the accent color of the wallpaper applied last.
+const ACCENT_SETTING: &str = "accentColor";
+
+/// Thumbnail size palettes are extracted from; any larger is wasted work.
+const PALETTE_SOURCE_SIZE: u32 = 256;
+
+pub fn save_palette(conn: &Connection, wallpaper_id: &str, palette: &Palette) -> SqlResult<()> {
+    conn.execute(
+        "UPDATE wallpapers SET dominant_color = ?2, accent_color = ?3 WHERE id = ?1",
+        params![wallpaper_id, palette.dominant, palette.accent],
+    )?;
+    conn.execute(
+        "DELETE FROM wallpaper_colors WHERE wallpaper_id = ?1",
+        params![wallpaper_id],
+    )?;
+    let mut insert = conn.prepare(
+        "INSERT INTO wallpaper_colors (wallpaper_id, position, r, g, b, population)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    for (position, swatch) in palette.colors.iter().enumerate() {
+        let Some([r, g, b]) = parse_hex_color(&swatch.color) else {
+            continue;
+        };
+        insert.execute(params![
+            wallpaper_id,
+            position as i64,
+            r as i64,
+            g as i64,
+            b as i64,
+            swatch.population
+        ])?;
+    }
+    Ok(())
+}
+
+pub fn load_palette(conn: &Connection, wallpaper_id: &str) -> SqlResult<Option<Palette>> {
+    let colors: Option<(Option<String>, Option<String>)> = conn
+        .query_row(
+            "SELECT dominant_color, accent_color FROM wallpapers WHERE id = ?1",
+            params![wallpaper_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((Some(dominant), Some(accent))) = colors else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT r, g, b, population FROM wallpaper_colors
+         WHERE wallpaper_id = ?1 ORDER BY position",
+    )?;
+    let colors = stmt
+        .query_map(params![wallpaper_id], |row| {
+            let channel = |i| row.get::<_, i64>(i).map(|c| c.clamp(0, 255) as u8);
+            Ok(Swatch {
+                color: to_hex([channel(0)?, channel(1)?, channel(2)?]),
+                population: row.get(3)?,
+            })
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    Ok(Some(Palette {
+        dominant,
+        accent,
+        colors,
+    }))
+}
+
+/// The wallpaper's palette, extracted from its local thumbnail the first
+/// time. `None` while there is no thumbnail to extract it from.
+pub fn ensure_palette(
+    conn: &Connection,
+    thumbnails: &Path,
+    wallpaper_id: &str,
+) -> Result<Option<Palette>, String> {
+    if let Some(palette) = load_palette(conn, wallpaper_id).map_err(|e| e.to_string())? {
+        return Ok(Some(palette));
+    }
+    let Some(path) = stored_thumbnail(thumbnails, wallpaper_id, PALETTE_SOURCE_SIZE) else {
+        return Ok(None);
+    };
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let Some(palette) = extract_palette(&decode(&bytes)?, PALETTE_SIZE) else {
+        return Ok(None);
+    };
+    save_palette(conn, wallpaper_id, &palette).map_err(|e| e.to_string())?;
+    Ok(Some(palette))
+}
+
+/// Extracts palettes for every wallpaper that has a thumbnail but no
+/// palette yet. Returns how many were added.
+pub fn backfill_palettes(conn: &Connection, thumbnails: &Path) -> Result<usize, String> {
+    let ids: Vec<String> = {
+        let mut stmt = conn
+            .prepare("SELECT id FROM wallpapers WHERE dominant_color IS NULL")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut added = 0;
+    for id in ids {
+        if stored_thumbnail(thumbnails, &id, PALETTE_SOURCE_SIZE).is_none() {
+            continue;
+        }
+        if let Ok(Some(_)) = ensure_palette(conn, thumbnails, &id) {
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+/// Backfills palettes for images cached before palettes existed, once per
+/// launch.
+pub fn start_palette_backfill(app: AppHandle) {
+    std::thread::spawn(move || {
+        let result = thumbnail_dir().and_then(|dir| {
+            let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+            backfill_palettes(&conn, &dir)
+        });
+        if let Err(e) = result {
+            eprintln!("Palette backfill failed: {}", e);
+        }
+    });
+}
+
+/// Makes the applied wallpaper's accent the app's accent color and tells
+/// the frontend with an `accent-color-changed` event.
+pub fn follow_applied_wallpaper(app: &AppHandle, conn: &Connection, wallpaper_id: &str) {
+    let Ok(dir) = thumbnail_dir() else {
+        return;
+    };
+    if let Ok(Some(palette)) = ensure_palette(conn, &dir, wallpaper_id) {
+        if set_setting(conn, ACCENT_SETTING, &palette.accent, "appearance").is_ok() {
+            let _ = app.emit("accent-color-changed", &palette.accent);
+        }
+    }
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn get_palette(db: State<'_, DbPool>, id: String) -> Result<Option<Palette>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let dir = thumbnail_dir()?;
+    ensure_palette(&conn, &dir, &id)
+}
+
+#[tauri::command]
+pub async fn get_accent_color(db: State<'_, DbPool>) -> Result<Option<String>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        params![ACCENT_SETTING],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::migrations::migrate;
+    use crate::services::query_service::{query_wallpapers, WallpaperFilter, WallpaperSort};
+
+    fn seeded() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO wallpapers (id, mongo_id, title, url, thumbnail, width, height, tags)
+            VALUES
+                ('sunset', 'm1', 'Sunset', 'a', '', 1920, 1080, ''),
+                ('forest', 'm2', 'Forest', 'b', '', 1920, 1080, ''),
+                ('unknown', 'm3', 'Unknown', 'c', '', 1920, 1080, '');
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn palette(colors: &[(&str, f32)]) -> Palette {
+        Palette {
+            dominant: colors[0].0.to_string(),
+            accent: colors[colors.len() - 1].0.to_string(),
+            colors: colors
+                .iter()
+                .map(|&(color, population)| Swatch {
+                    color: color.to_string(),
+                    population,
+                })
+                .collect(),
+        }
+    }
+
+    fn matching(conn: &Connection, color: &str) -> Vec<String> {
+        let filter = WallpaperFilter {
+            color: Some(color.to_string()),
+            ..Default::default()
+        };
+        query_wallpapers(conn, &filter, WallpaperSort::default(), 10, None)
+            .unwrap()
+            .wallpapers
+            .into_iter()
+            .map(|w| w.id)
+            .collect()
+    }
+
+    #[test]
+    fn palettes_round_trip_and_filter_listings() {
+        let conn = seeded();
+        let sunset = palette(&[("#1a1030", 0.6), ("#f07814", 0.4)]);
+        save_palette(&conn, "sunset", &sunset).unwrap();
+        save_palette(
+            &conn,
+            "forest",
+            &palette(&[("#1f4d2b", 0.97), ("#f07814", 0.03)]),
+        )
+        .unwrap();
+
+        assert_eq!(load_palette(&conn, "sunset").unwrap(), Some(sunset));
+        assert_eq!(load_palette(&conn, "unknown").unwrap(), None);
+
+        // Near the orange, but forest has too little of it to count.
+        assert_eq!(matching(&conn, "#e8801c"), vec!["sunset"]);
+        assert_eq!(matching(&conn, "#2a6a3a"), vec!["forest"]);
+        assert!(matching(&conn, "#ffffff").is_empty());
+        assert!(matching(&conn, "orange").is_empty());
+
+        conn.execute("DELETE FROM wallpapers WHERE id = 'sunset'", [])
+            .unwrap();
+        let left: i64 = conn
+            .query_row("SELECT COUNT(*) FROM wallpaper_colors", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(left, 2);
+    }
+}
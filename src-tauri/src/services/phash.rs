@@ -0,0 +1,52 @@
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// Difference hash: 64 bits, each set where a cell of a 9×8 greyscale
+/// shrink is brighter than its right-hand neighbour. Survives rescaling,
+/// recompression and small color shifts, so re-encoded copies of one image
+/// land within a few bits of each other.
+pub fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y).0[0] > small.get_pixel(x + 1, y).0[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Number of bits two hashes differ in.
+pub fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn waves(width: u32, height: u32, phase: f32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let u = x as f32 / width as f32 * 6.0 + phase;
+            let v = y as f32 / height as f32 * 4.0;
+            let level = ((u.sin() + v.cos()) * 60.0 + 128.0) as u8;
+            Rgb([level, level / 2, 255 - level])
+        }))
+    }
+
+    #[test]
+    fn rescaled_copies_match_and_other_images_do_not() {
+        let original = dhash(&waves(640, 360, 0.0));
+        let smaller = dhash(&waves(320, 180, 0.0));
+        let brighter = dhash(&waves(640, 360, 0.0).brighten(20));
+        let other = dhash(&waves(640, 360, 2.5));
+
+        assert!(hamming(original, smaller) <= 4);
+        assert!(hamming(original, brighter) <= 4);
+        assert!(hamming(original, other) > 16);
+    }
+}
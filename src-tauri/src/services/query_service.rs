@@ -0,0 +1,818 @@
+use crate::services::db_services::{
+    parse_tags, tag_filter_clause, wallpaper_from_row, DbPool, TagMatch, Wallpaper, NOT_BLOCKED,
+    WALLPAPER_COLUMNS,
+};
+use crate::services::effects::parse_hex_color;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rusqlite::{params_from_iter, types::Value, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+/// How far (relative) a wallpaper's width/height may drift from a bucket's
+/// ratio and still count as that bucket, so that 1366x768 is 16:9 and
+/// 3440x1440 is 21:9.
+const ASPECT_TOLERANCE: f64 = 0.03;
+
+/// Largest RGB distance at which a palette color matches a color filter.
+const COLOR_DISTANCE: f64 = 60.0;
+
+/// Share of the image a palette color needs to match a color filter, so a
+/// speck of red doesn't make a wallpaper "red".
+const MIN_COLOR_SHARE: f64 = 0.1;
+
+/// Most rows a listing or search page returns, whatever `limit` asks for.
+pub(crate) const MAX_PAGE_SIZE: u32 = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AspectRatio {
+    #[serde(rename = "4:3")]
+    Standard,
+    #[serde(rename = "16:10")]
+    Widescreen16x10,
+    #[serde(rename = "16:9")]
+    Widescreen,
+    #[serde(rename = "21:9")]
+    Ultrawide,
+    #[serde(rename = "32:9")]
+    SuperUltrawide,
+    #[serde(rename = "1:1")]
+    Square,
+    #[serde(rename = "9:16")]
+    Portrait,
+}
+
+impl AspectRatio {
+    fn ratio(self) -> f64 {
+        match self {
+            AspectRatio::Standard => 4.0 / 3.0,
+            AspectRatio::Widescreen16x10 => 16.0 / 10.0,
+            AspectRatio::Widescreen => 16.0 / 9.0,
+            AspectRatio::Ultrawide => 21.0 / 9.0,
+            AspectRatio::SuperUltrawide => 32.0 / 9.0,
+            AspectRatio::Square => 1.0,
+            AspectRatio::Portrait => 9.0 / 16.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+/// Structured filter shared by listing and search commands. Every field is
+/// optional; unset fields don't narrow the result.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WallpaperFilter {
+    pub min_width: Option<u32>,
+    pub min_height: Option<u32>,
+    /// Matches any of the listed buckets.
+    pub aspect_ratios: Vec<AspectRatio>,
+    pub orientation: Option<Orientation>,
+    pub is_ai_generated: Option<bool>,
+    pub favorites_only: bool,
+    /// At least this many stars; unrated wallpapers never match.
+    pub min_rating: Option<u8>,
+    /// Hidden wallpapers are left out unless set. Blocked ones always are.
+    pub include_hidden: bool,
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    /// Inclusive bounds on when the wallpaper was added, as ISO-8601 dates or
+    /// date-times.
+    pub added_after: Option<String>,
+    pub added_before: Option<String>,
+    /// Only wallpapers added in the last this many days, counted from when
+    /// the filter runs rather than when it was saved.
+    pub added_within_days: Option<u32>,
+    /// `#rrggbb`; matches wallpapers with a palette color close to it.
+    /// Wallpapers without a palette yet never match.
+    pub color: Option<String>,
+}
+
+impl WallpaperFilter {
+    /// SQL conditions over `w` (wallpapers) with their bound values, in
+    /// placeholder order. Always hides unavailable and blocked rows.
+    pub(crate) fn to_sql(&self) -> (Vec<String>, Vec<Value>) {
+        let mut conditions = vec!["w.is_available = 1".to_string(), NOT_BLOCKED.to_string()];
+        let mut values = Vec::new();
+
+        if !self.include_hidden {
+            conditions.push("w.is_hidden = 0".to_string());
+        }
+
+        if let Some(min_width) = self.min_width {
+            conditions.push("w.width >= ?".to_string());
+            values.push(Value::from(min_width));
+        }
+        if let Some(min_height) = self.min_height {
+            conditions.push("w.height >= ?".to_string());
+            values.push(Value::from(min_height));
+        }
+        if !self.aspect_ratios.is_empty() {
+            let buckets =
+                vec!["ABS(CAST(w.width AS REAL) / w.height - ?) <= ?"; self.aspect_ratios.len()];
+            conditions.push(format!("(w.height > 0 AND ({}))", buckets.join(" OR ")));
+            for aspect in &self.aspect_ratios {
+                values.push(Value::from(aspect.ratio()));
+                values.push(Value::from(aspect.ratio() * ASPECT_TOLERANCE));
+            }
+        }
+        match self.orientation {
+            Some(Orientation::Landscape) => conditions.push("w.width > w.height".to_string()),
+            Some(Orientation::Portrait) => conditions.push("w.width < w.height".to_string()),
+            Some(Orientation::Square) => conditions.push("w.width = w.height".to_string()),
+            None => {}
+        }
+        if let Some(ai) = self.is_ai_generated {
+            conditions.push("w.is_ai_generated = ?".to_string());
+            values.push(Value::from(ai));
+        }
+        if self.favorites_only {
+            conditions
+                .push("EXISTS (SELECT 1 FROM favorites f WHERE f.wallpaper_id = w.id)".to_string());
+        }
+        if let Some(stars) = self.min_rating {
+            conditions.push("w.rating >= ?".to_string());
+            values.push(Value::from(stars));
+        }
+
+        let tag_names = parse_tags(&self.tags.join(","));
+        if !tag_names.is_empty() {
+            let (clause, tag_values) = tag_filter_clause(&tag_names, self.tag_match);
+            conditions.push(clause);
+            values.extend(tag_values);
+        }
+
+        if let Some(after) = &self.added_after {
+            conditions.push("datetime(w.created_at) >= datetime(?)".to_string());
+            values.push(Value::from(after.clone()));
+        }
+        if let Some(before) = &self.added_before {
+            conditions.push("datetime(w.created_at) <= datetime(?)".to_string());
+            values.push(Value::from(before.clone()));
+        }
+        if let Some(days) = self.added_within_days {
+            conditions.push("datetime(w.created_at) >= datetime('now', ?)".to_string());
+            values.push(Value::from(format!("-{days} days")));
+        }
+
+        if let Some(color) = &self.color {
+            match parse_hex_color(color) {
+                Some(rgb) => {
+                    conditions.push(
+                        "EXISTS (SELECT 1 FROM wallpaper_colors c WHERE c.wallpaper_id = w.id \
+                         AND c.population >= ? \
+                         AND (c.r - ?) * (c.r - ?) + (c.g - ?) * (c.g - ?) + (c.b - ?) * (c.b - ?) <= ?)"
+                            .to_string(),
+                    );
+                    values.push(Value::from(MIN_COLOR_SHARE));
+                    for channel in rgb {
+                        values.push(Value::from(f64::from(channel)));
+                        values.push(Value::from(f64::from(channel)));
+                    }
+                    values.push(Value::from(COLOR_DISTANCE * COLOR_DISTANCE));
+                }
+                // An unreadable color matches nothing rather than everything.
+                None => conditions.push("0".to_string()),
+            }
+        }
+
+        (conditions, values)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(tag = "by", rename_all = "lowercase")]
+pub enum WallpaperSort {
+    #[default]
+    Newest,
+    Oldest,
+    Title,
+    /// Largest pixel count first.
+    Resolution,
+    /// Most popular on the server first.
+    Popularity,
+    /// Shuffled, but the same `seed` always gives the same order so paging
+    /// through a random listing doesn't repeat or skip wallpapers.
+    Random {
+        seed: u32,
+    },
+}
+
+impl WallpaperSort {
+    /// Sort key expressions, most significant first, and whether they ascend.
+    /// Every order ends in `w.id` so it is total, and all keys share one
+    /// direction so a single row-value comparison resumes after any row.
+    fn keys(self) -> (Vec<String>, bool) {
+        let keys = |k: &[&str]| k.iter().map(|s| s.to_string()).collect();
+        match self {
+            WallpaperSort::Newest => (keys(&["w.created_at", "w.id"]), false),
+            WallpaperSort::Oldest => (keys(&["w.created_at", "w.id"]), true),
+            WallpaperSort::Title => (
+                keys(&["COALESCE(w.title, '') COLLATE NOCASE", "w.id"]),
+                true,
+            ),
+            WallpaperSort::Resolution => (
+                keys(&["COALESCE(w.width, 0) * COALESCE(w.height, 0)", "w.id"]),
+                false,
+            ),
+            WallpaperSort::Popularity => (keys(&["w.popularity", "w.created_at", "w.id"]), false),
+            WallpaperSort::Random { seed } => {
+                // Derived integers, not user input, so they are safe to inline.
+                let (multiplier, increment) = shuffle_params(seed);
+                let hash = format!(
+                    "(COALESCE(w.shuffle_key, 0) * {} + {}) % 2147483647",
+                    multiplier, increment
+                );
+                (vec![hash, "w.id".to_string()], true)
+            }
+        }
+    }
+
+    /// Identifies the order a cursor was issued for.
+    fn signature(self) -> String {
+        match self {
+            WallpaperSort::Newest => "newest".to_string(),
+            WallpaperSort::Oldest => "oldest".to_string(),
+            WallpaperSort::Title => "title".to_string(),
+            WallpaperSort::Resolution => "resolution".to_string(),
+            WallpaperSort::Popularity => "popularity".to_string(),
+            WallpaperSort::Random { seed } => format!("random:{}", seed),
+        }
+    }
+}
+
+/// Derives the multiplier and increment of the shuffle
+/// `shuffle_key * m + c (mod 2^31 - 1)` from `seed`, so different seeds give
+/// unrelated orders rather than rotations of one order. Both stay below 2^31,
+/// which keeps the product inside SQLite's 64-bit integers.
+fn shuffle_params(seed: u32) -> (i64, i64) {
+    // splitmix64
+    let mut z = (seed as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+
+    let modulus = 2_147_483_647u64;
+    let multiplier = (z % (modulus - 1)) + 1;
+    let increment = (z >> 32) % modulus;
+    (multiplier as i64, increment as i64)
+}
+
+/// One page of a listing.
+#[derive(Serialize)]
+pub struct WallpaperPage {
+    pub wallpapers: Vec<Wallpaper>,
+    /// Pass back to get the next page; `None` on the last page. Opaque to
+    /// the frontend.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum CursorValue {
+    Null,
+    Int(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl From<Value> for CursorValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Integer(i) => CursorValue::Int(i),
+            Value::Real(r) => CursorValue::Real(r),
+            Value::Text(t) => CursorValue::Text(t),
+            Value::Null | Value::Blob(_) => CursorValue::Null,
+        }
+    }
+}
+
+impl From<CursorValue> for Value {
+    fn from(value: CursorValue) -> Self {
+        match value {
+            CursorValue::Null => Value::Null,
+            CursorValue::Int(i) => Value::Integer(i),
+            CursorValue::Real(r) => Value::Real(r),
+            CursorValue::Text(t) => Value::Text(t),
+        }
+    }
+}
+
+/// Sort keys of the last row on a page, tagged with the order they belong to.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    order: String,
+    keys: Vec<CursorValue>,
+}
+
+pub(crate) fn encode_cursor(order: String, keys: Vec<Value>) -> String {
+    let cursor = Cursor {
+        order,
+        keys: keys.into_iter().map(CursorValue::from).collect(),
+    };
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&cursor).unwrap_or_default())
+}
+
+pub(crate) fn decode_cursor(cursor: &str, order: &str) -> Result<Vec<Value>, String> {
+    let invalid = || "Invalid cursor".to_string();
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let cursor: Cursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+    if cursor.order != order {
+        return Err("Cursor belongs to a different sort order".to_string());
+    }
+    Ok(cursor.keys.into_iter().map(Value::from).collect())
+}
+
+/// Filtered, sorted listing with keyset pagination: each page resumes
+/// strictly after the sort keys of the previous page's last row, so rows
+/// inserted by a concurrent sync never shift later pages into duplicates or
+/// gaps.
+pub fn query_wallpapers(
+    conn: &Connection,
+    filter: &WallpaperFilter,
+    sort: WallpaperSort,
+    limit: u32,
+    cursor: Option<&str>,
+) -> Result<WallpaperPage, String> {
+    let limit = limit.min(MAX_PAGE_SIZE);
+    let (keys, ascending) = sort.keys();
+    let (mut conditions, mut values) = filter.to_sql();
+
+    if let Some(cursor) = cursor {
+        let after = decode_cursor(cursor, &sort.signature())?;
+        if after.len() != keys.len() {
+            return Err("Invalid cursor".to_string());
+        }
+        conditions.push(format!(
+            "({}) {} ({})",
+            keys.join(", "),
+            if ascending { ">" } else { "<" },
+            vec!["?"; keys.len()].join(", ")
+        ));
+        values.extend(after);
+    }
+
+    // Fetch one extra row to learn whether there is another page.
+    values.push(Value::from(limit + 1));
+
+    let direction = if ascending { "ASC" } else { "DESC" };
+    let key_columns: Vec<String> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| format!("{} AS sort_key_{}", key, i))
+        .collect();
+    let order_by: Vec<String> = keys
+        .iter()
+        .map(|key| format!("{} {}", key, direction))
+        .collect();
+
+    let sql = format!(
+        r#"
+        SELECT {}, {}
+        FROM wallpapers w
+        WHERE {}
+        ORDER BY {}
+        LIMIT ?
+        "#,
+        WALLPAPER_COLUMNS,
+        key_columns.join(", "),
+        conditions.join(" AND "),
+        order_by.join(", ")
+    );
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let mut rows: Vec<(Wallpaper, Vec<Value>)> = stmt
+        .query_map(params_from_iter(values), |row| {
+            let sort_keys = (0..keys.len())
+                .map(|i| row.get::<_, Value>(format!("sort_key_{}", i).as_str()))
+                .collect::<SqlResult<Vec<Value>>>()?;
+            Ok((wallpaper_from_row(row)?, sort_keys))
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .collect();
+
+    let next_cursor = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        rows.last()
+            .map(|(_, sort_keys)| encode_cursor(sort.signature(), sort_keys.clone()))
+    } else {
+        None
+    };
+
+    Ok(WallpaperPage {
+        wallpapers: rows.into_iter().map(|(wp, _)| wp).collect(),
+        next_cursor,
+    })
+}
+
+/// How many wallpapers match `filter`.
+pub fn count_wallpapers(conn: &Connection, filter: &WallpaperFilter) -> SqlResult<u32> {
+    let (conditions, values) = filter.to_sql();
+    let sql = format!(
+        r#"
+        SELECT COUNT(*)
+        FROM wallpapers w
+        WHERE {}
+        "#,
+        conditions.join(" AND ")
+    );
+    conn.query_row(&sql, params_from_iter(values), |row| row.get(0))
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn query_wallpapers_command(
+    db: State<'_, DbPool>,
+    filter: Option<WallpaperFilter>,
+    sort: Option<WallpaperSort>,
+    limit: u32,
+    cursor: Option<String>,
+) -> Result<WallpaperPage, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    query_wallpapers(
+        &conn,
+        &filter.unwrap_or_default(),
+        sort.unwrap_or_default(),
+        limit,
+        cursor.as_deref(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::migrations::migrate;
+
+    fn seeded() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        for i in 0..7 {
+            insert(&conn, &format!("w{}", i), "2024-01-01 00:00:00", i % 3);
+        }
+        conn
+    }
+
+    fn insert(conn: &Connection, id: &str, created_at: &str, popularity: i64) {
+        conn.execute(
+            "INSERT INTO wallpapers
+                (id, mongo_id, title, url, thumbnail, width, height, tags, created_at, popularity)
+             VALUES (?1, ?1, ?1, '', '', 1920, 1080, '', ?2, ?3)",
+            rusqlite::params![id, created_at, popularity],
+        )
+        .unwrap();
+    }
+
+    fn walk(conn: &Connection, sort: WallpaperSort, limit: u32) -> Vec<String> {
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = query_wallpapers(
+                conn,
+                &WallpaperFilter::default(),
+                sort,
+                limit,
+                cursor.as_deref(),
+            )
+            .unwrap();
+            seen.extend(page.wallpapers.into_iter().map(|w| w.id));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return seen,
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_limits_are_capped_and_keep_the_cursor() {
+        let conn = seeded();
+        for i in 7..=MAX_PAGE_SIZE {
+            insert(&conn, &format!("w{}", i), "2024-01-01 00:00:00", 0);
+        }
+        let page = query_wallpapers(
+            &conn,
+            &WallpaperFilter::default(),
+            WallpaperSort::Newest,
+            u32::MAX,
+            None,
+        )
+        .unwrap();
+        assert_eq!(page.wallpapers.len(), MAX_PAGE_SIZE as usize);
+        assert!(page.next_cursor.is_some());
+        assert_eq!(
+            walk(&conn, WallpaperSort::Newest, u32::MAX).len(),
+            MAX_PAGE_SIZE as usize + 1
+        );
+    }
+
+    #[test]
+    fn pages_cover_every_row_once_for_each_sort() {
+        let conn = seeded();
+        for sort in [
+            WallpaperSort::Newest,
+            WallpaperSort::Oldest,
+            WallpaperSort::Title,
+            WallpaperSort::Resolution,
+            WallpaperSort::Popularity,
+            WallpaperSort::Random { seed: 42 },
+        ] {
+            let all = walk(&conn, sort, 100);
+            assert_eq!(all.len(), 7);
+            assert_eq!(walk(&conn, sort, 2), all, "{:?}", sort);
+        }
+    }
+
+    /// One wallpaper per case the filters and sorts tell apart.
+    fn library() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        let rows = [
+            (
+                "hd",
+                "Bravo",
+                1920,
+                1080,
+                false,
+                "nature, lake",
+                "2024-01-01",
+                5,
+            ),
+            ("laptop", "alpha", 1366, 768, true, "city", "2024-02-01", 1),
+            // 16:9 give or take 3%, on either side of the edge.
+            (
+                "edge_in",
+                "Charlie",
+                1831,
+                1000,
+                false,
+                "nature",
+                "2024-03-01",
+                3,
+            ),
+            ("edge_out", "delta", 1832, 1000, false, "", "2024-04-01", 0),
+            (
+                "ultra",
+                "Echo",
+                3440,
+                1440,
+                false,
+                "nature",
+                "2024-05-01",
+                9,
+            ),
+            (
+                "phone",
+                "foxtrot",
+                1080,
+                1920,
+                true,
+                "nature, city",
+                "2024-06-01",
+                2,
+            ),
+            ("square", "Golf", 1000, 1000, false, "", "2024-07-01", 4),
+        ];
+        for (id, title, width, height, ai, tags, created_at, popularity) in rows {
+            conn.execute(
+                "INSERT INTO wallpapers (
+                     id, mongo_id, title, url, thumbnail, width, height, tags,
+                     is_ai_generated, created_at, popularity
+                 ) VALUES (?1, ?1, ?2, '', '', ?3, ?4, ?5, ?6, ?7 || ' 00:00:00', ?8)",
+                rusqlite::params![id, title, width, height, tags, ai, created_at, popularity],
+            )
+            .unwrap();
+            crate::services::db_services::set_wallpaper_tags(&conn, id, tags).unwrap();
+        }
+        conn
+    }
+
+    fn matching(conn: &Connection, filter: WallpaperFilter) -> Vec<String> {
+        let mut ids: Vec<String> = query_wallpapers(conn, &filter, WallpaperSort::Title, 100, None)
+            .unwrap()
+            .wallpapers
+            .into_iter()
+            .map(|w| w.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    fn sorted(conn: &Connection, sort: WallpaperSort) -> Vec<String> {
+        query_wallpapers(conn, &WallpaperFilter::default(), sort, 100, None)
+            .unwrap()
+            .wallpapers
+            .into_iter()
+            .map(|w| w.id)
+            .collect()
+    }
+
+    #[test]
+    fn filters_narrow_by_shape_size_date_ai_and_tags() {
+        let conn = library();
+        let aspect = |buckets: Vec<AspectRatio>| WallpaperFilter {
+            aspect_ratios: buckets,
+            ..Default::default()
+        };
+        assert_eq!(
+            matching(&conn, aspect(vec![AspectRatio::Widescreen])),
+            ["edge_in", "hd", "laptop"]
+        );
+        assert_eq!(
+            matching(
+                &conn,
+                aspect(vec![AspectRatio::Widescreen, AspectRatio::Ultrawide])
+            ),
+            ["edge_in", "hd", "laptop", "ultra"]
+        );
+        assert_eq!(
+            matching(&conn, aspect(vec![AspectRatio::Portrait])),
+            ["phone"]
+        );
+
+        let orientation = |o| WallpaperFilter {
+            orientation: Some(o),
+            ..Default::default()
+        };
+        assert_eq!(
+            matching(&conn, orientation(Orientation::Landscape)),
+            ["edge_in", "edge_out", "hd", "laptop", "ultra"]
+        );
+        assert_eq!(
+            matching(&conn, orientation(Orientation::Portrait)),
+            ["phone"]
+        );
+        assert_eq!(
+            matching(&conn, orientation(Orientation::Square)),
+            ["square"]
+        );
+
+        let large = WallpaperFilter {
+            min_width: Some(1900),
+            min_height: Some(1000),
+            ..Default::default()
+        };
+        assert_eq!(matching(&conn, large), ["hd", "ultra"]);
+
+        let recent = WallpaperFilter {
+            added_after: Some("2024-05-01".into()),
+            ..Default::default()
+        };
+        assert_eq!(matching(&conn, recent), ["phone", "square", "ultra"]);
+        let window = WallpaperFilter {
+            added_after: Some("2024-02-01".into()),
+            added_before: Some("2024-03-01T00:00:00".into()),
+            ..Default::default()
+        };
+        assert_eq!(matching(&conn, window), ["edge_in", "laptop"]);
+
+        let ai = WallpaperFilter {
+            is_ai_generated: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(matching(&conn, ai), ["laptop", "phone"]);
+
+        let tagged = |tag_match| WallpaperFilter {
+            tags: vec!["Nature".into(), "city".into()],
+            tag_match,
+            ..Default::default()
+        };
+        assert_eq!(matching(&conn, tagged(TagMatch::All)), ["phone"]);
+        assert_eq!(
+            matching(&conn, tagged(TagMatch::Any)),
+            ["edge_in", "hd", "laptop", "phone", "ultra"]
+        );
+    }
+
+    #[test]
+    fn each_sort_orders_as_documented() {
+        let conn = library();
+        assert_eq!(
+            sorted(&conn, WallpaperSort::Newest),
+            ["square", "phone", "ultra", "edge_out", "edge_in", "laptop", "hd"]
+        );
+        assert_eq!(
+            sorted(&conn, WallpaperSort::Oldest),
+            ["hd", "laptop", "edge_in", "edge_out", "ultra", "phone", "square"]
+        );
+        // Case doesn't matter.
+        assert_eq!(
+            sorted(&conn, WallpaperSort::Title),
+            ["laptop", "hd", "edge_in", "edge_out", "ultra", "phone", "square"]
+        );
+        // hd and phone have as many pixels; the id breaks the tie.
+        assert_eq!(
+            sorted(&conn, WallpaperSort::Resolution),
+            ["ultra", "phone", "hd", "edge_out", "edge_in", "laptop", "square"]
+        );
+        assert_eq!(
+            sorted(&conn, WallpaperSort::Popularity),
+            ["ultra", "hd", "square", "edge_in", "phone", "laptop", "edge_out"]
+        );
+
+        let shuffled = sorted(&conn, WallpaperSort::Random { seed: 7 });
+        assert_eq!(sorted(&conn, WallpaperSort::Random { seed: 7 }), shuffled);
+        let mut all = shuffled.clone();
+        all.sort();
+        assert_eq!(
+            all,
+            ["edge_in", "edge_out", "hd", "laptop", "phone", "square", "ultra"]
+        );
+        assert!((0..5).any(|seed| sorted(&conn, WallpaperSort::Random { seed }) != shuffled));
+
+        // What a VACUUM may do to a table without an INTEGER PRIMARY KEY.
+        conn.execute_batch(
+            "UPDATE wallpapers SET rowid = rowid + 100;
+             UPDATE wallpapers SET rowid = 1000 - rowid;",
+        )
+        .unwrap();
+        assert_eq!(sorted(&conn, WallpaperSort::Random { seed: 7 }), shuffled);
+    }
+
+    #[test]
+    fn repeated_favorites_rows_do_not_repeat_wallpapers() {
+        let conn = seeded();
+        for _ in 0..3 {
+            conn.execute(
+                "INSERT INTO favorites (id, wallpaper_id) VALUES (lower(hex(randomblob(8))), 'w1')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let all = walk(&conn, WallpaperSort::Title, 2);
+        assert_eq!(all.len(), 7);
+        let favorites = WallpaperFilter {
+            favorites_only: true,
+            ..Default::default()
+        };
+        let page = query_wallpapers(&conn, &favorites, WallpaperSort::Title, 1, None).unwrap();
+        assert_eq!(page.wallpapers.len(), 1);
+        assert!(page.wallpapers[0].is_favorite);
+        assert!(page.next_cursor.is_none());
+        assert_eq!(count_wallpapers(&conn, &favorites).unwrap(), 1);
+    }
+
+    #[test]
+    fn inserts_ahead_of_the_cursor_do_not_shift_pages() {
+        let conn = seeded();
+        let first = query_wallpapers(
+            &conn,
+            &WallpaperFilter::default(),
+            WallpaperSort::Newest,
+            3,
+            None,
+        )
+        .unwrap();
+
+        insert(&conn, "fresh", "2025-01-01 00:00:00", 0);
+
+        let second = query_wallpapers(
+            &conn,
+            &WallpaperFilter::default(),
+            WallpaperSort::Newest,
+            3,
+            first.next_cursor.as_deref(),
+        )
+        .unwrap();
+        let ids: Vec<String> = second.wallpapers.into_iter().map(|w| w.id).collect();
+        assert_eq!(ids, vec!["w3", "w2", "w1"]);
+    }
+
+    #[test]
+    fn cursor_is_bound_to_its_sort() {
+        let conn = seeded();
+        let page = query_wallpapers(
+            &conn,
+            &WallpaperFilter::default(),
+            WallpaperSort::Newest,
+            2,
+            None,
+        )
+        .unwrap();
+        let cursor = page.next_cursor.unwrap();
+        assert!(query_wallpapers(
+            &conn,
+            &WallpaperFilter::default(),
+            WallpaperSort::Title,
+            2,
+            Some(&cursor),
+        )
+        .is_err());
+        assert!(query_wallpapers(
+            &conn,
+            &WallpaperFilter::default(),
+            WallpaperSort::Newest,
+            2,
+            Some("garbage"),
+        )
+        .is_err());
+    }
+}
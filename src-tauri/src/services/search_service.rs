@@ -0,0 +1,358 @@
+use crate::services::db_services::{wallpaper_from_row, DbPool, Wallpaper, WALLPAPER_COLUMNS};
+use crate::services::effects::fingerprint;
+use crate::services::query_service::{
+    decode_cursor, encode_cursor, WallpaperFilter, WallpaperPage, MAX_PAGE_SIZE,
+};
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension};
+use tauri::State;
+
+/// Cursor tag for search pages of `match_query` under `filters`, so a
+/// cursor can't resume a different search.
+fn search_order(match_query: &str, filters: &WallpaperFilter) -> String {
+    let filters = serde_json::to_string(filters).unwrap_or_default();
+    format!("search:{}", fingerprint(&[match_query, &filters]))
+}
+
+/// Relevance of a row, lower is better. Title hits weigh more than tag hits.
+const RANK: &str = "bm25(wallpapers_fts, 10.0, 4.0)";
+
+/// Turns user input into an FTS5 `MATCH` expression. Double-quoted text is a
+/// phrase, a trailing `*` makes a word a prefix match, and every other word
+/// must match a whole token. All terms must match. Returns `None` when the
+/// input has nothing searchable in it.
+pub fn build_match_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+
+    for (index, segment) in input.split('"').enumerate() {
+        // Odd segments sit between a pair of quotes (or after an unclosed one).
+        if index % 2 == 1 {
+            if is_searchable(segment) {
+                terms.push(quote(segment.trim()));
+            }
+            continue;
+        }
+
+        for word in segment.split_whitespace() {
+            let stem = word.trim_end_matches('*');
+            if !is_searchable(stem) {
+                continue;
+            }
+            if stem.len() < word.len() {
+                terms.push(format!("{}*", quote(stem)));
+            } else {
+                terms.push(quote(stem));
+            }
+        }
+    }
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+fn is_searchable(text: &str) -> bool {
+    text.chars().any(char::is_alphanumeric)
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// Ranked full-text search over titles and tags. Title hits outrank tag hits.
+/// `cursor` is the `next_cursor` of the previous page. Pages resume after the
+/// rank and id of the previous page's last row. Scores shift as the library's
+/// term statistics change, so that row's rank is recomputed rather than
+/// trusted from the cursor; the stored one is only used once it is gone.
+pub fn search_wallpapers(
+    conn: &Connection,
+    query: &str,
+    filters: &WallpaperFilter,
+    limit: u32,
+    cursor: Option<&str>,
+) -> Result<WallpaperPage, String> {
+    let limit = limit.min(MAX_PAGE_SIZE);
+    let Some(match_query) = build_match_query(query) else {
+        return Ok(WallpaperPage {
+            wallpapers: Vec::new(),
+            next_cursor: None,
+        });
+    };
+
+    let order = search_order(&match_query, filters);
+    let after = match cursor {
+        Some(c) => match decode_cursor(c, &order)
+            .map_err(|_| "Invalid cursor".to_string())?
+            .as_slice()
+        {
+            [rank @ Value::Real(_), Value::Text(id)] => Some((rank.clone(), id.clone())),
+            _ => return Err("Invalid cursor".to_string()),
+        },
+        None => None,
+    };
+
+    let (filter_conditions, filter_values) = filters.to_sql();
+    let mut conditions = vec!["wallpapers_fts MATCH ?".to_string()];
+    conditions.extend(filter_conditions);
+    let mut values = vec![Value::from(match_query.clone())];
+    values.extend(filter_values);
+
+    let mut seek = String::new();
+    if let Some((stored_rank, id)) = after {
+        let rank = current_rank(conn, &match_query, &id)
+            .map_err(|e| e.to_string())?
+            .map_or(stored_rank, Value::from);
+        seek = "WHERE (search_rank, id) > (?, ?)".to_string();
+        values.push(rank);
+        values.push(Value::from(id));
+    }
+
+    // Fetch one extra row to learn whether there is another page.
+    values.push(Value::from(limit + 1));
+
+    let sql = format!(
+        r#"
+        SELECT * FROM (
+            SELECT {WALLPAPER_COLUMNS}, {RANK} AS search_rank
+            FROM wallpapers_fts
+            JOIN wallpapers w ON w.id = wallpapers_fts.id
+            WHERE {}
+        )
+        {seek}
+        ORDER BY search_rank, id
+        LIMIT ?
+        "#,
+        conditions.join(" AND ")
+    );
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let mut rows: Vec<(Wallpaper, f64)> = stmt
+        .query_map(params_from_iter(values), |row| {
+            Ok((wallpaper_from_row(row)?, row.get("search_rank")?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let next_cursor = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        rows.last().map(|(wp, rank)| {
+            encode_cursor(
+                order.clone(),
+                vec![Value::from(*rank), Value::from(wp.id.clone())],
+            )
+        })
+    } else {
+        None
+    };
+
+    Ok(WallpaperPage {
+        wallpapers: rows.into_iter().map(|(wp, _)| wp).collect(),
+        next_cursor,
+    })
+}
+
+/// Rank of wallpaper `id` for `match_query` as things stand now, if it still
+/// matches.
+fn current_rank(conn: &Connection, match_query: &str, id: &str) -> rusqlite::Result<Option<f64>> {
+    conn.query_row(
+        &format!("SELECT {RANK} FROM wallpapers_fts WHERE wallpapers_fts MATCH ?1 AND id = ?2"),
+        params![match_query, id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn search_wallpapers_command(
+    db: State<'_, DbPool>,
+    query: String,
+    filters: Option<WallpaperFilter>,
+    limit: u32,
+    cursor: Option<String>,
+) -> Result<WallpaperPage, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    search_wallpapers(
+        &conn,
+        &query,
+        &filters.unwrap_or_default(),
+        limit,
+        cursor.as_deref(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::migrations::migrate;
+
+    fn seeded() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO wallpapers
+                (id, mongo_id, title, url, thumbnail, width, height, tags, is_ai_generated)
+            VALUES
+                ('mountain', 'm1', 'Mountain View', '', '', 1920, 1080, 'mountain, snow, nature', 0),
+                ('city', 'm2', 'City Lights', '', '', 1920, 1080, 'city,night,lights', 0),
+                ('toon', 'm3', 'Sunday Morning', '', '', 1920, 1080, 'cartoon, sci-fi', 1),
+                ('cafe', 'm4', 'Café Terrace', '', '', 1920, 1080, 'street, night', 0),
+                ('snowfall', 'm5', 'Snowfall', '', '', 1920, 1080, 'winter', 0);
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn ids(conn: &Connection, query: &str) -> Vec<String> {
+        search_wallpapers(conn, query, &WallpaperFilter::default(), 50, None)
+            .unwrap()
+            .wallpapers
+            .into_iter()
+            .map(|w| w.id)
+            .collect()
+    }
+
+    #[test]
+    fn builds_quoted_terms() {
+        assert_eq!(
+            build_match_query(r#"car* "city lights" sky"#).as_deref(),
+            Some(r#""car"* "city lights" "sky""#)
+        );
+        assert_eq!(
+            build_match_query(r#"say "hi"#).as_deref(),
+            Some(r#""say" "hi""#)
+        );
+        assert_eq!(build_match_query(" - * \"\" "), None);
+    }
+
+    #[test]
+    fn tag_strings_tokenize_on_commas_and_spaces() {
+        let conn = seeded();
+        assert_eq!(ids(&conn, "snow"), vec!["mountain"]);
+        assert_eq!(ids(&conn, "lights"), vec!["city"]);
+        let mut night = ids(&conn, "night");
+        night.sort();
+        assert_eq!(night, vec!["cafe", "city"]);
+        assert_eq!(ids(&conn, "sci-fi"), vec!["toon"]);
+    }
+
+    #[test]
+    fn whole_words_unless_prefixed() {
+        let conn = seeded();
+        assert!(ids(&conn, "car").is_empty());
+        assert_eq!(ids(&conn, "car*"), vec!["toon"]);
+    }
+
+    #[test]
+    fn phrases_and_diacritics() {
+        let conn = seeded();
+        assert_eq!(ids(&conn, "\"city lights\""), vec!["city"]);
+        assert!(ids(&conn, "\"lights city\"").is_empty());
+        assert_eq!(ids(&conn, "cafe"), vec!["cafe"]);
+    }
+
+    #[test]
+    fn matches_follow_wallpapers_whose_rowids_change() {
+        let conn = seeded();
+        // What a VACUUM may do to a table without an INTEGER PRIMARY KEY.
+        conn.execute_batch(
+            "UPDATE wallpapers SET rowid = rowid + 100;
+             UPDATE wallpapers SET rowid = 1000 - rowid;",
+        )
+        .unwrap();
+        assert_eq!(ids(&conn, "snow*"), vec!["snowfall", "mountain"]);
+        assert_eq!(ids(&conn, "cartoon"), vec!["toon"]);
+
+        conn.execute("DELETE FROM wallpapers WHERE id = 'toon'", [])
+            .unwrap();
+        conn.execute(
+            "UPDATE wallpapers SET title = 'Cartoon City' WHERE id = 'city'",
+            [],
+        )
+        .unwrap();
+        assert_eq!(ids(&conn, "cartoon"), vec!["city"]);
+    }
+
+    #[test]
+    fn title_hits_rank_first() {
+        let conn = seeded();
+        assert_eq!(ids(&conn, "snow*"), vec!["snowfall", "mountain"]);
+    }
+
+    #[test]
+    fn filters_and_cursor() {
+        let conn = seeded();
+        let filters = WallpaperFilter {
+            is_ai_generated: Some(false),
+            ..Default::default()
+        };
+        let first = search_wallpapers(&conn, "night", &filters, 1, None).unwrap();
+        assert_eq!(first.wallpapers.len(), 1);
+        let cursor = first.next_cursor.unwrap();
+
+        let second = search_wallpapers(&conn, "night", &filters, 1, Some(&cursor)).unwrap();
+        assert_eq!(second.wallpapers.len(), 1);
+        assert_ne!(first.wallpapers[0].id, second.wallpapers[0].id);
+        assert!(second.next_cursor.is_none());
+
+        // Cursors only resume the search they came from.
+        for (query, filters) in [
+            ("city", filters.clone()),
+            ("night", WallpaperFilter::default()),
+        ] {
+            assert_eq!(
+                search_wallpapers(&conn, query, &filters, 1, Some(&cursor)).err(),
+                Some("Invalid cursor".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_limits_do_not_overflow() {
+        let conn = seeded();
+        let page =
+            search_wallpapers(&conn, "night", &WallpaperFilter::default(), u32::MAX, None).unwrap();
+        assert_eq!(page.wallpapers.len(), 2);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn rows_synced_in_between_pages_do_not_shift_results() {
+        let conn = seeded();
+        let first =
+            search_wallpapers(&conn, "night", &WallpaperFilter::default(), 1, None).unwrap();
+        let cursor = first.next_cursor.unwrap();
+
+        // Outranks both existing hits and changes the term statistics.
+        conn.execute(
+            "INSERT INTO wallpapers
+                 (id, mongo_id, title, url, thumbnail, width, height, tags, is_ai_generated)
+             VALUES ('owl', 'm6', 'Night Owl', '', '', 1920, 1080, 'night', 0)",
+            [],
+        )
+        .unwrap();
+
+        let second = search_wallpapers(
+            &conn,
+            "night",
+            &WallpaperFilter::default(),
+            1,
+            Some(&cursor),
+        )
+        .unwrap();
+        let mut seen = vec![
+            first.wallpapers[0].id.clone(),
+            second.wallpapers[0].id.clone(),
+        ];
+        seen.sort();
+        assert_eq!(seen, vec!["cafe", "city"]);
+        assert!(second.next_cursor.is_none());
+    }
+}
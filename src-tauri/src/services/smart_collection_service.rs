@@ -0,0 +1,342 @@
+use crate::services::collection_service::{export_dir, export_wallpapers, ExportReport};
+use crate::services::db_services::{DbPool, Wallpaper};
+use crate::services::effects::Effect;
+use crate::services::query_service::{
+    count_wallpapers, query_wallpapers, WallpaperFilter, WallpaperPage, WallpaperSort,
+};
+use crate::services::wallpaper_service::start_wallpaper_rotation;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult, Row};
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Page size used when a whole smart collection is needed at once.
+const PAGE_SIZE: u32 = 500;
+
+/// A saved search, run against the library whenever it is read.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SmartCollection {
+    pub id: String,
+    pub name: String,
+    pub filter: WallpaperFilter,
+    pub sort: WallpaperSort,
+    /// Wallpapers matching right now.
+    pub count: u32,
+}
+
+fn smart_collection_from_row(row: &Row) -> SqlResult<SmartCollection> {
+    Ok(SmartCollection {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        filter: serde_json::from_str(&row.get::<_, String>("filter")?).unwrap_or_default(),
+        sort: serde_json::from_str(&row.get::<_, String>("sort")?).unwrap_or_default(),
+        count: 0,
+    })
+}
+
+fn with_count(conn: &Connection, mut collection: SmartCollection) -> SqlResult<SmartCollection> {
+    collection.count = count_wallpapers(conn, &collection.filter)?;
+    Ok(collection)
+}
+
+pub fn list_smart_collections(conn: &Connection) -> SqlResult<Vec<SmartCollection>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, filter, sort FROM smart_collections ORDER BY position, created_at",
+    )?;
+    let rows = stmt
+        .query_map([], smart_collection_from_row)?
+        .collect::<SqlResult<Vec<_>>>()?;
+    rows.into_iter().map(|c| with_count(conn, c)).collect()
+}
+
+pub fn get_smart_collection(conn: &Connection, id: &str) -> SqlResult<Option<SmartCollection>> {
+    let collection = conn
+        .query_row(
+            "SELECT id, name, filter, sort FROM smart_collections WHERE id = ?1",
+            params![id],
+            smart_collection_from_row,
+        )
+        .optional()?;
+    collection.map(|c| with_count(conn, c)).transpose()
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "{}".into())
+}
+
+pub fn create_smart_collection(
+    conn: &Connection,
+    name: &str,
+    filter: &WallpaperFilter,
+    sort: WallpaperSort,
+) -> SqlResult<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO smart_collections (id, name, filter, sort, position)
+         VALUES (?1, ?2, ?3, ?4, (SELECT COALESCE(MAX(position) + 1, 0) FROM smart_collections))",
+        params![id, name.trim(), to_json(filter), to_json(&sort)],
+    )?;
+    Ok(id)
+}
+
+/// Replaces the name and query of a smart collection. Returns whether it
+/// exists.
+pub fn update_smart_collection(
+    conn: &Connection,
+    id: &str,
+    name: &str,
+    filter: &WallpaperFilter,
+    sort: WallpaperSort,
+) -> SqlResult<bool> {
+    let changed = conn.execute(
+        "UPDATE smart_collections
+         SET name = ?2, filter = ?3, sort = ?4, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?1",
+        params![id, name.trim(), to_json(filter), to_json(&sort)],
+    )?;
+    Ok(changed > 0)
+}
+
+pub fn delete_smart_collection(conn: &Connection, id: &str) -> SqlResult<bool> {
+    let deleted = conn.execute("DELETE FROM smart_collections WHERE id = ?1", params![id])?;
+    Ok(deleted > 0)
+}
+
+/// Every wallpaper a smart collection matches, in its order.
+pub fn matching_wallpapers(
+    conn: &Connection,
+    collection: &SmartCollection,
+) -> Result<Vec<Wallpaper>, String> {
+    let mut wallpapers = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = query_wallpapers(
+            conn,
+            &collection.filter,
+            collection.sort,
+            PAGE_SIZE,
+            cursor.as_deref(),
+        )?;
+        wallpapers.extend(page.wallpapers);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return Ok(wallpapers),
+        }
+    }
+}
+
+fn find(conn: &Connection, id: &str) -> Result<SmartCollection, String> {
+    get_smart_collection(conn, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No smart collection {id}"))
+}
+
+//
+// Tauri Commands
+//
+
+#[tauri::command]
+pub async fn list_smart_collections_command(
+    db: State<'_, DbPool>,
+) -> Result<Vec<SmartCollection>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    list_smart_collections(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_smart_collection_command(
+    db: State<'_, DbPool>,
+    name: String,
+    filter: WallpaperFilter,
+    sort: Option<WallpaperSort>,
+) -> Result<SmartCollection, String> {
+    if name.trim().is_empty() {
+        return Err("Collection name is empty".into());
+    }
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let id = create_smart_collection(&conn, &name, &filter, sort.unwrap_or_default())
+        .map_err(|e| e.to_string())?;
+    find(&conn, &id)
+}
+
+#[tauri::command]
+pub async fn update_smart_collection_command(
+    db: State<'_, DbPool>,
+    id: String,
+    name: String,
+    filter: WallpaperFilter,
+    sort: Option<WallpaperSort>,
+) -> Result<SmartCollection, String> {
+    if name.trim().is_empty() {
+        return Err("Collection name is empty".into());
+    }
+    let conn = db.get().map_err(|e| e.to_string())?;
+    update_smart_collection(&conn, &id, &name, &filter, sort.unwrap_or_default())
+        .map_err(|e| e.to_string())?;
+    find(&conn, &id)
+}
+
+#[tauri::command]
+pub async fn delete_smart_collection_command(
+    db: State<'_, DbPool>,
+    id: String,
+) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match delete_smart_collection(&conn, &id).map_err(|e| e.to_string())? {
+        true => Ok(()),
+        false => Err(format!("No smart collection {id}")),
+    }
+}
+
+/// One page of what a smart collection matches, paged like
+/// `query_wallpapers_command`.
+#[tauri::command]
+pub async fn query_smart_collection_command(
+    db: State<'_, DbPool>,
+    id: String,
+    limit: u32,
+    cursor: Option<String>,
+) -> Result<WallpaperPage, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let collection = find(&conn, &id)?;
+    query_wallpapers(
+        &conn,
+        &collection.filter,
+        collection.sort,
+        limit,
+        cursor.as_deref(),
+    )
+}
+
+/// Rotates through what a smart collection matches when the rotation starts.
+#[tauri::command]
+pub async fn start_smart_collection_rotation(
+    app: AppHandle,
+    id: String,
+    interval_sec: u64,
+    effects: Option<Vec<Effect>>,
+) -> Result<(), String> {
+    let urls: Vec<String> = {
+        let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+        let collection = find(&conn, &id)?;
+        matching_wallpapers(&conn, &collection)?
+            .into_iter()
+            .map(|w| w.url)
+            .collect()
+    };
+    if urls.is_empty() {
+        return Err("The smart collection matches no wallpapers".into());
+    }
+    start_wallpaper_rotation(app, urls, interval_sec, effects)
+}
+
+/// Exports what a smart collection matches to `destination`, or to a folder
+/// named after it in Downloads, emitting `export-progress` as it goes.
+#[tauri::command]
+pub async fn export_smart_collection(
+    app: AppHandle,
+    id: String,
+    destination: Option<String>,
+) -> Result<ExportReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let (collection, wallpapers) = {
+            let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+            let collection = find(&conn, &id)?;
+            let wallpapers = matching_wallpapers(&conn, &collection)?;
+            (collection, wallpapers)
+        };
+        let dir = match destination {
+            Some(path) => PathBuf::from(path),
+            None => export_dir(&collection.name)?,
+        };
+        export_wallpapers(&app, &wallpapers, &dir, |progress| {
+            let _ = app.emit("export-progress", progress);
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::db_services::{set_wallpaper_tags, TagMatch};
+    use crate::services::migrations::migrate;
+    use crate::services::query_service::Orientation;
+
+    fn insert(conn: &Connection, id: &str, size: (u32, u32), ai: bool, tags: &str, age_days: u32) {
+        conn.execute(
+            "INSERT INTO wallpapers
+                 (id, mongo_id, title, url, thumbnail, width, height, tags, is_ai_generated, created_at)
+             VALUES (?1, ?1, ?1, ?1, '', ?2, ?3, ?4, ?5, datetime('now', ?6))",
+            params![id, size.0, size.1, tags, ai, format!("-{age_days} days")],
+        )
+        .unwrap();
+        set_wallpaper_tags(conn, id, tags).unwrap();
+    }
+
+    #[test]
+    fn saved_searches_match_the_library_as_it_is() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        insert(&conn, "match", (3840, 2160), false, "nature, lake", 3);
+        insert(&conn, "old", (3840, 2160), false, "nature", 60);
+        insert(&conn, "ai", (3840, 2160), true, "nature", 1);
+        insert(&conn, "small", (1920, 1080), false, "nature", 1);
+        insert(&conn, "portrait", (2160, 3840), false, "nature", 1);
+        insert(&conn, "city", (3840, 2160), false, "city", 1);
+
+        let filter = WallpaperFilter {
+            min_width: Some(3840),
+            min_height: Some(2160),
+            orientation: Some(Orientation::Landscape),
+            is_ai_generated: Some(false),
+            tags: vec!["Nature".into()],
+            tag_match: TagMatch::All,
+            added_within_days: Some(30),
+            ..Default::default()
+        };
+        let id =
+            create_smart_collection(&conn, "4K nature", &filter, WallpaperSort::Newest).unwrap();
+
+        let collection = get_smart_collection(&conn, &id).unwrap().unwrap();
+        assert_eq!(collection.filter, filter);
+        assert_eq!(collection.count, 1);
+        let ids: Vec<String> = matching_wallpapers(&conn, &collection)
+            .unwrap()
+            .into_iter()
+            .map(|w| w.id)
+            .collect();
+        assert_eq!(ids, ["match"]);
+
+        // New wallpapers show up without touching the saved search.
+        insert(&conn, "new", (5120, 2880), false, "nature", 0);
+        assert_eq!(list_smart_collections(&conn).unwrap()[0].count, 2);
+
+        let wider = WallpaperFilter {
+            added_within_days: None,
+            ..filter
+        };
+        assert!(update_smart_collection(
+            &conn,
+            &id,
+            "All 4K nature",
+            &wider,
+            WallpaperSort::Oldest
+        )
+        .unwrap());
+        let collection = get_smart_collection(&conn, &id).unwrap().unwrap();
+        assert_eq!(
+            (collection.name.as_str(), collection.count),
+            ("All 4K nature", 3)
+        );
+        assert_eq!(collection.sort, WallpaperSort::Oldest);
+        assert_eq!(
+            matching_wallpapers(&conn, &collection).unwrap()[0].id,
+            "old"
+        );
+
+        assert!(delete_smart_collection(&conn, &id).unwrap());
+        assert!(list_smart_collections(&conn).unwrap().is_empty());
+    }
+}
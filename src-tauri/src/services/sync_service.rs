@@ -1,39 +1,507 @@
-use crate::services::db_services::{add_or_update_wallpaper, get_last_synced_mongo_id, Wallpaper};
+use crate::services::db_services::{
+    add_or_update_wallpaper, get_sync_cursor, get_sync_interval_ms, is_blocked,
+    remove_wallpaper_by_mongo_id, set_sync_cursor, DbPool, FocalPoint, ImageVariant, UpsertOutcome,
+    Wallpaper, WallpaperSource, DEFAULT_SYNC_INTERVAL_MS,
+};
+use crate::services::duplicate_service::{
+    find_duplicate_of, hash_image, save_hash, skip_duplicates_enabled, DEFAULT_MAX_DISTANCE,
+};
+use once_cell::sync::Lazy;
 use reqwest;
-use std::error::Error;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, time::Duration};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex;
 
-pub async fn fetch_from_mongo_and_cache(batch_size: u32) -> Result<(), Box<dyn Error>> {
-    // Get the last synced MongoDB wallpaper ID (if any)
-    let last_mongo_id = get_last_synced_mongo_id().unwrap_or(None);
+const API_BASE_URL: &str = "http://localhost:3000/api";
 
-    // Build the request URL (your backend must support this query pattern)
-    let url = if let Some(last_id) = last_mongo_id {
-        format!(
-            "http://localhost:3000/api/wallpapers?after={}&limit={}",
-            last_id, batch_size
-        )
+/// Page size used for every sync triggered by the app.
+pub const SYNC_BATCH_SIZE: u32 = 100;
+
+/// How often the worker re-checks the settings while periodic sync is off.
+const DISABLED_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Held for the duration of a sync so two never write to the DB at once.
+static SYNC_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// A single entry of the server's change feed. Tombstones only carry
+/// `mongo_id` and `deleted: true`, so every other field is optional; entries
+/// that aren't tombstones are only applied when they have an `id`.
+#[derive(Deserialize)]
+struct RemoteWallpaper {
+    #[serde(default)]
+    id: String,
+    mongo_id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    thumbnail: String,
+    #[serde(default)]
+    width: i32,
+    #[serde(default)]
+    height: i32,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    is_ai_generated: bool,
+    #[serde(default)]
+    popularity: i64,
+    #[serde(default)]
+    variants: Vec<ImageVariant>,
+    focal_point: Option<FocalPoint>,
+    updated_at: Option<String>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+impl RemoteWallpaper {
+    fn into_wallpaper(self) -> Wallpaper {
+        Wallpaper {
+            id: self.id,
+            mongo_id: Some(self.mongo_id),
+            title: self.title,
+            url: self.url,
+            thumbnail: self.thumbnail,
+            width: self.width,
+            height: self.height,
+            tags: self.tags,
+            is_ai_generated: self.is_ai_generated,
+            is_favorite: false,
+            is_available: true,
+            popularity: self.popularity,
+            variants: self.variants,
+            focal_point: self.focal_point,
+            dominant_color: None,
+            accent_color: None,
+            source: WallpaperSource::Remote,
+            file_path: None,
+            rating: None,
+            is_hidden: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ChangeFeedPage {
+    changes: Vec<RemoteWallpaper>,
+    next_cursor: Option<String>,
+    #[serde(default)]
+    has_more: bool,
+}
+
+/// Counts of what a sync changed in the local database.
+#[derive(Serialize, Default, Debug, Clone, Copy)]
+pub struct SyncReport {
+    pub added: u32,
+    pub updated: u32,
+    pub removed: u32,
+    /// New wallpapers left out as near-duplicates of ones already stored.
+    pub skipped: u32,
+    /// Changes to wallpapers on the blocklist, which were not applied.
+    pub blocked: u32,
+    /// Entries without an `id` that weren't tombstones, which were not applied.
+    pub invalid: u32,
+}
+
+/// Pulls every change since the last sync from the server's change feed,
+/// `batch_size` entries per request, and applies it to SQLite. Each page is
+/// written in a single transaction together with the cursor advance, so a
+/// failed page rolls back and is fetched again next time. A pooled
+/// connection is only held for the database work of a page, never across a
+/// request. `on_progress` is called with the running totals after each
+/// committed page.
+pub async fn fetch_from_mongo_and_cache<F>(
+    pool: &DbPool,
+    batch_size: u32,
+    mut on_progress: F,
+) -> Result<SyncReport, Box<dyn Error>>
+where
+    F: FnMut(&SyncReport),
+{
+    let client = reqwest::Client::new();
+    let url = format!("{}/wallpapers/changes", API_BASE_URL);
+    let mut report = SyncReport::default();
+    let mut cursor = get_sync_cursor(&*pool.get()?).unwrap_or(None);
+
+    loop {
+        let mut request = client.get(&url).query(&[("limit", batch_size)]);
+        if let Some(since) = &cursor {
+            request = request.query(&[("since", since)]);
+        }
+
+        // Make the HTTP request
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to fetch: {}", response.status()).into());
+        }
+
+        // Parse the JSON response
+        let page: ChangeFeedPage = response.json().await?;
+
+        // Hash new wallpapers' thumbnails up front, outside the transaction.
+        let mut to_hash = Vec::new();
+        {
+            let conn = pool.get()?;
+            if skip_duplicates_enabled(&conn)? {
+                for change in page
+                    .changes
+                    .iter()
+                    .filter(|c| !c.deleted && !c.id.trim().is_empty())
+                {
+                    if !is_stored(&conn, &change.mongo_id)?
+                        && !is_blocked(&conn, Some(&change.mongo_id), None)?
+                    {
+                        to_hash.push(change);
+                    }
+                }
+            }
+        }
+        let mut hashes = HashMap::new();
+        for change in to_hash {
+            if let Some(hash) = remote_hash(&client, change).await {
+                hashes.insert(change.mongo_id.clone(), hash);
+            }
+        }
+
+        let next = apply_page(&mut *pool.get()?, page, &hashes, &mut report)?;
+        on_progress(&report);
+
+        match next {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+
+    Ok(report)
+}
+
+/// Writes one change-feed page and its cursor advance in a single
+/// transaction, adding what it did to `report`. Nothing of a page that fails
+/// is kept, `report` included. `hashes` are perceptual hashes of new
+/// wallpapers by `mongo_id`. Returns the cursor of the next page, or `None`
+/// once the feed is exhausted.
+fn apply_page(
+    conn: &mut Connection,
+    page: ChangeFeedPage,
+    hashes: &HashMap<String, u64>,
+    report: &mut SyncReport,
+) -> rusqlite::Result<Option<String>> {
+    let mut totals = *report;
+    let tx = conn.transaction()?;
+
+    for change in page.changes {
+        if change.deleted {
+            if remove_wallpaper_by_mongo_id(&tx, &change.mongo_id)? {
+                totals.removed += 1;
+            }
+            continue;
+        }
+
+        if change.id.trim().is_empty() {
+            totals.invalid += 1;
+            continue;
+        }
+
+        let hash = hashes.get(&change.mongo_id).copied();
+        if let Some(hash) = hash {
+            if find_duplicate_of(&tx, hash, DEFAULT_MAX_DISTANCE, None)?.is_some() {
+                totals.skipped += 1;
+                continue;
+            }
+        }
+
+        let updated_at = change.updated_at.clone();
+        let wp = change.into_wallpaper();
+        match add_or_update_wallpaper(&tx, &wp, updated_at.as_deref())? {
+            UpsertOutcome::Added => totals.added += 1,
+            UpsertOutcome::Updated => totals.updated += 1,
+            UpsertOutcome::Blocked => {
+                totals.blocked += 1;
+                continue;
+            }
+        }
+        if let Some(hash) = hash {
+            save_hash(&tx, &wp.id, hash)?;
+        }
+    }
+
+    if let Some(next) = &page.next_cursor {
+        set_sync_cursor(&tx, next)?;
+    }
+    tx.commit()?;
+
+    *report = totals;
+    Ok(page.next_cursor.filter(|_| page.has_more))
+}
+
+fn is_stored(conn: &Connection, mongo_id: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT 1 FROM wallpapers WHERE mongo_id = ?1",
+        params![mongo_id],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|row| row.is_some())
+}
+
+/// Perceptual hash of a remote wallpaper, from its thumbnail when it has
+/// one. `None` if the image can't be fetched or decoded, which lets it in.
+async fn remote_hash(client: &reqwest::Client, change: &RemoteWallpaper) -> Option<u64> {
+    let url = if change.thumbnail.is_empty() {
+        &change.url
     } else {
-        format!("http://localhost:3000/api/wallpapers?limit={}", batch_size)
+        &change.thumbnail
     };
+    let response = client.get(url).send().await.ok()?.error_for_status().ok()?;
+    hash_image(&response.bytes().await.ok()?).ok()
+}
 
-    // Make the HTTP request
-    let response = reqwest::get(&url).await?;
-    if !response.status().is_success() {
-        return Err(format!("Failed to fetch: {}", response.status()).into());
+// ---------------------- Sync Worker ----------------------
+
+/// Runs one sync, emitting `sync-started`, `sync-progress`, `sync-finished`
+/// and `sync-failed` to the frontend. Waits for a sync already in flight to
+/// finish first.
+pub async fn sync_with_events(app: &AppHandle) -> Result<SyncReport, String> {
+    let _guard = SYNC_LOCK.lock().await;
+    run_sync(app).await
+}
+
+/// Like [`sync_with_events`], but returns `None` straight away if another
+/// sync is already running.
+pub async fn try_sync_with_events(app: &AppHandle) -> Option<Result<SyncReport, String>> {
+    let _guard = SYNC_LOCK.try_lock().ok()?;
+    Some(run_sync(app).await)
+}
+
+async fn run_sync(app: &AppHandle) -> Result<SyncReport, String> {
+    let _ = app.emit("sync-started", ());
+
+    let pool = app.state::<DbPool>().inner().clone();
+    let result = fetch_from_mongo_and_cache(&pool, SYNC_BATCH_SIZE, |report| {
+        let _ = app.emit("sync-progress", *report);
+    })
+    .await
+    .map_err(|e| e.to_string());
+
+    match &result {
+        Ok(report) => {
+            let _ = app.emit("sync-finished", *report);
+        }
+        Err(e) => {
+            let _ = app.emit("sync-failed", e.clone());
+        }
     }
 
-    // Parse the JSON response
-    let wallpapers: Vec<Wallpaper> = response.json().await?;
+    result
+}
+
+/// Spawns the periodic sync loop. The interval is re-read from settings
+/// before every cycle so changes apply without a restart.
+pub fn start_background_sync(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let pool = app.state::<DbPool>().inner().clone();
+        loop {
+            let interval_ms = pool
+                .get()
+                .map_err(|e| e.to_string())
+                .and_then(|conn| get_sync_interval_ms(&conn).map_err(|e| e.to_string()))
+                .unwrap_or(DEFAULT_SYNC_INTERVAL_MS);
+            if interval_ms == 0 {
+                tokio::time::sleep(DISABLED_POLL_INTERVAL).await;
+                continue;
+            }
+
+            // Failures reach the frontend as `sync-failed`.
+            let _ = try_sync_with_events(&app).await;
+
+            tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+        }
+    });
+}
 
-    println!(
-        "Wallpaper Response:\n{}",
-        serde_json::to_string_pretty(&wallpapers).unwrap_or_default()
-    );
+//
+// Tauri Commands
+//
 
-    // Add or update each wallpaper in SQLite
-    for wp in wallpapers {
-        add_or_update_wallpaper(&wp)?;
+#[tauri::command]
+pub async fn sync_now(app: AppHandle) -> Result<SyncReport, String> {
+    try_sync_with_events(&app)
+        .await
+        .unwrap_or_else(|| Err("A sync is already in progress".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::db_services::add_favorite;
+    use crate::services::migrations::migrate;
+
+    fn database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+        conn
+    }
+
+    fn page(
+        changes: serde_json::Value,
+        next_cursor: Option<&str>,
+        has_more: bool,
+    ) -> ChangeFeedPage {
+        serde_json::from_value(serde_json::json!({
+            "changes": changes,
+            "next_cursor": next_cursor,
+            "has_more": has_more,
+        }))
+        .unwrap()
     }
 
-    Ok(())
+    fn mongo_ids(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT mongo_id FROM wallpapers ORDER BY mongo_id")
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn a_failing_page_keeps_neither_rows_nor_cursor() {
+        let mut conn = database();
+        set_sync_cursor(&conn, "c0").unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER fail_bad BEFORE INSERT ON wallpapers WHEN NEW.mongo_id = 'bad'
+             BEGIN SELECT RAISE(ABORT, 'forced failure'); END;",
+        )
+        .unwrap();
+        let mut report = SyncReport::default();
+
+        let failing = page(
+            serde_json::json!([
+                { "id": "a", "mongo_id": "ma", "title": "A" },
+                { "id": "bad", "mongo_id": "bad", "title": "Bad" },
+            ]),
+            Some("c1"),
+            true,
+        );
+        assert!(apply_page(&mut conn, failing, &HashMap::new(), &mut report).is_err());
+        assert!(mongo_ids(&conn).is_empty());
+        assert_eq!(get_sync_cursor(&conn).unwrap().as_deref(), Some("c0"));
+        assert_eq!(report.added, 0);
+
+        let retried = page(
+            serde_json::json!([{ "id": "a", "mongo_id": "ma", "title": "A" }]),
+            Some("c1"),
+            false,
+        );
+        assert_eq!(
+            apply_page(&mut conn, retried, &HashMap::new(), &mut report).unwrap(),
+            None
+        );
+        assert_eq!(mongo_ids(&conn), ["ma"]);
+        assert_eq!(get_sync_cursor(&conn).unwrap().as_deref(), Some("c1"));
+        assert_eq!(report.added, 1);
+    }
+
+    fn wallpaper(conn: &Connection, mongo_id: &str) -> (String, bool, Option<String>) {
+        conn.query_row(
+            "SELECT title, is_available, updated_at FROM wallpapers WHERE mongo_id = ?1",
+            params![mongo_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn tombstones_remove_wallpapers_unless_they_are_kept() {
+        let mut conn = database();
+        let mut report = SyncReport::default();
+        let added = page(
+            serde_json::json!([
+                { "id": "a", "mongo_id": "ma", "title": "A" },
+                { "id": "b", "mongo_id": "mb", "title": "B" },
+            ]),
+            Some("c1"),
+            true,
+        );
+        apply_page(&mut conn, added, &HashMap::new(), &mut report).unwrap();
+        add_favorite(&conn, "b").unwrap();
+
+        let removed = page(
+            serde_json::json!([
+                { "mongo_id": "ma", "deleted": true },
+                { "mongo_id": "mb", "deleted": true },
+                { "mongo_id": "unknown", "deleted": true },
+            ]),
+            Some("c2"),
+            false,
+        );
+        apply_page(&mut conn, removed, &HashMap::new(), &mut report).unwrap();
+
+        assert_eq!(mongo_ids(&conn), ["mb"]);
+        assert!(!wallpaper(&conn, "mb").1);
+        assert_eq!((report.added, report.removed), (2, 2));
+    }
+
+    #[test]
+    fn later_changes_to_a_wallpaper_win() {
+        let mut conn = database();
+        let mut report = SyncReport::default();
+        let changes = page(
+            serde_json::json!([
+                { "id": "a", "mongo_id": "ma", "title": "First", "updated_at": "2026-01-01T00:00:00Z" },
+                { "id": "a", "mongo_id": "ma", "title": "Second", "updated_at": "2026-02-01T00:00:00Z" },
+            ]),
+            None,
+            false,
+        );
+        apply_page(&mut conn, changes, &HashMap::new(), &mut report).unwrap();
+
+        assert_eq!(
+            wallpaper(&conn, "ma"),
+            (
+                "Second".to_string(),
+                true,
+                Some("2026-02-01T00:00:00Z".to_string())
+            )
+        );
+        assert_eq!((report.added, report.updated), (1, 1));
+    }
+
+    #[test]
+    fn entries_without_an_id_are_not_applied() {
+        let mut conn = database();
+        let mut report = SyncReport::default();
+        let changes = page(
+            serde_json::json!([
+                { "mongo_id": "m1", "title": "No id" },
+                { "id": " ", "mongo_id": "m2", "title": "Blank id" },
+                { "id": "c", "mongo_id": "m3", "title": "C" },
+            ]),
+            None,
+            false,
+        );
+        apply_page(&mut conn, changes, &HashMap::new(), &mut report).unwrap();
+
+        assert_eq!(mongo_ids(&conn), ["m3"]);
+        assert_eq!((report.added, report.invalid), (1, 2));
+    }
+
+    #[test]
+    fn the_feed_ends_without_more_pages_or_a_cursor() {
+        let mut conn = database();
+        let mut report = SyncReport::default();
+        let mut next = |next_cursor, has_more| {
+            let empty = page(serde_json::json!([]), next_cursor, has_more);
+            apply_page(&mut conn, empty, &HashMap::new(), &mut report).unwrap()
+        };
+
+        assert_eq!(next(Some("c1"), true).as_deref(), Some("c1"));
+        assert_eq!(next(Some("c2"), false), None);
+        assert_eq!(next(None, true), None);
+        assert_eq!(get_sync_cursor(&conn).unwrap().as_deref(), Some("c2"));
+    }
 }
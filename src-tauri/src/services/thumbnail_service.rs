@@ -0,0 +1,252 @@
+use crate::services::image_pipeline::{decode, encode, OutputFormat};
+use crate::services::wallpaper_service::APP_INFO;
+use app_dirs2::{app_dir, AppDataType};
+use image::DynamicImage;
+use percent_encoding::percent_decode_str;
+use sanitize_filename::sanitize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tauri::http::{header, Request, Response, StatusCode, Uri};
+
+/// Scheme the webview loads thumbnails from.
+pub const THUMBNAIL_SCHEME: &str = "wallpaper-thumb";
+
+/// Longest side of each thumbnail generated, smallest first.
+pub const THUMBNAIL_SIZES: [u32; 3] = [128, 256, 512];
+
+const THUMBNAIL_QUALITY: u8 = 80;
+
+/// Thumbnails change only when regenerated, which changes their ETag, so
+/// the webview may reuse them for a week without asking.
+const CACHE_CONTROL: &str = "public, max-age=604800";
+
+pub fn thumbnail_dir() -> Result<PathBuf, String> {
+    app_dir(AppDataType::UserCache, &APP_INFO, "thumbnails")
+        .map_err(|e| format!("Failed to resolve thumbnail directory: {e}"))
+}
+
+fn thumbnail_path(dir: &Path, wallpaper_id: &str, size: u32) -> PathBuf {
+    dir.join(sanitize(wallpaper_id))
+        .join(format!("{size}.{}", OutputFormat::Webp.extension()))
+}
+
+fn missing_sizes(dir: &Path, wallpaper_id: &str) -> Vec<u32> {
+    THUMBNAIL_SIZES
+        .into_iter()
+        .filter(|&size| !thumbnail_path(dir, wallpaper_id, size).exists())
+        .collect()
+}
+
+/// Writes whichever thumbnails of `wallpaper_id` are missing, from the
+/// image in `bytes`. Images smaller than a size are stored as they are.
+pub fn ensure_thumbnails(dir: &Path, wallpaper_id: &str, bytes: &[u8]) -> Result<(), String> {
+    if missing_sizes(dir, wallpaper_id).is_empty() {
+        return Ok(());
+    }
+    ensure_thumbnails_of(dir, wallpaper_id, &decode(bytes)?)
+}
+
+/// Like [`ensure_thumbnails`], for an image that is already decoded.
+pub fn ensure_thumbnails_of(
+    dir: &Path,
+    wallpaper_id: &str,
+    img: &DynamicImage,
+) -> Result<(), String> {
+    let missing = missing_sizes(dir, wallpaper_id);
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir.join(sanitize(wallpaper_id))).map_err(|e| e.to_string())?;
+    for size in missing {
+        let thumbnail = if img.width().max(img.height()) > size {
+            img.thumbnail(size, size)
+        } else {
+            img.clone()
+        };
+        let encoded = encode(&thumbnail, OutputFormat::Webp, THUMBNAIL_QUALITY)?;
+
+        // Written aside and renamed so the scheme never serves half a file.
+        let path = thumbnail_path(dir, wallpaper_id, size);
+        let partial = path.with_extension("part");
+        fs::write(&partial, encoded).map_err(|e| e.to_string())?;
+        fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Deletes every thumbnail of `wallpaper_id`, e.g. once its image changed.
+pub fn remove_thumbnails(dir: &Path, wallpaper_id: &str) {
+    let _ = fs::remove_dir_all(dir.join(sanitize(wallpaper_id)));
+}
+
+/// The thumbnail of `wallpaper_id` closest to `size` that exists on disk.
+pub fn stored_thumbnail(dir: &Path, wallpaper_id: &str, size: u32) -> Option<PathBuf> {
+    let preferred = pick_size(size);
+    std::iter::once(preferred)
+        .chain(THUMBNAIL_SIZES.into_iter().filter(|&s| s != preferred))
+        .map(|size| thumbnail_path(dir, wallpaper_id, size))
+        .find(|path| path.exists())
+}
+
+/// The stored size to serve for a request: the smallest at least as large,
+/// or the largest there is.
+pub fn pick_size(requested: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .into_iter()
+        .find(|&size| size >= requested)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+/// Wallpaper id and size from `wallpaper-thumb://localhost/{id}/{size}`,
+/// the `http://wallpaper-thumb.localhost/{id}/{size}` form Windows uses, or
+/// the short `wallpaper-thumb://{id}/{size}`. Both parts may be
+/// percent-encoded together, as `convertFileSrc` does.
+fn parse_thumbnail_uri(uri: &Uri) -> Option<(String, u32)> {
+    let path = percent_decode_str(uri.path()).decode_utf8().ok()?;
+    let mut parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    if let Some(host) = uri.host() {
+        if host != "localhost" && !host.ends_with(".localhost") {
+            parts.insert(0, host);
+        }
+    }
+    match parts[..] {
+        [id, size] => Some((id.to_string(), size.parse().ok()?)),
+        _ => None,
+    }
+}
+
+fn empty(status: StatusCode) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Vec::new())
+        .unwrap_or_default()
+}
+
+/// Answers a request on the thumbnail scheme from the files in `dir`.
+pub fn thumbnail_response(dir: &Path, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let Some((wallpaper_id, requested)) = parse_thumbnail_uri(request.uri()) else {
+        return empty(StatusCode::BAD_REQUEST);
+    };
+    let path = thumbnail_path(dir, &wallpaper_id, pick_size(requested));
+    let Ok(metadata) = fs::metadata(&path) else {
+        return empty(StatusCode::NOT_FOUND);
+    };
+
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    let etag = format!("\"{:x}-{:x}\"", metadata.len(), modified);
+    let builder = Response::builder()
+        .header(header::CACHE_CONTROL, CACHE_CONTROL)
+        .header(header::ETAG, &etag)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+
+    let not_modified = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag));
+    if not_modified {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Vec::new())
+            .unwrap_or_default();
+    }
+
+    match fs::read(&path) {
+        Ok(bytes) => builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "image/webp")
+            .body(bytes)
+            .unwrap_or_default(),
+        Err(_) => empty(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Handler for the thumbnail scheme.
+pub fn serve_thumbnail(request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    match thumbnail_dir() {
+        Ok(dir) => thumbnail_response(&dir, request),
+        Err(_) => empty(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str, etag: Option<&str>) -> Request<Vec<u8>> {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(etag) = etag {
+            builder = builder.header(header::IF_NONE_MATCH, etag);
+        }
+        builder.body(Vec::new()).unwrap()
+    }
+
+    fn parse(uri: &str) -> Option<(String, u32)> {
+        parse_thumbnail_uri(&uri.parse().unwrap())
+    }
+
+    #[test]
+    fn parses_every_uri_form() {
+        let expected = Some(("abc-123".to_string(), 256));
+        assert_eq!(parse("wallpaper-thumb://localhost/abc-123/256"), expected);
+        assert_eq!(
+            parse("http://wallpaper-thumb.localhost/abc-123/256"),
+            expected
+        );
+        assert_eq!(parse("wallpaper-thumb://abc-123/256"), expected);
+        assert_eq!(parse("wallpaper-thumb://localhost/abc-123%2F256"), expected);
+        assert_eq!(parse("wallpaper-thumb://localhost/abc-123/big"), None);
+        assert_eq!(parse("wallpaper-thumb://localhost/abc-123"), None);
+    }
+
+    #[test]
+    fn picks_the_nearest_stored_size() {
+        assert_eq!(pick_size(64), 128);
+        assert_eq!(pick_size(256), 256);
+        assert_eq!(pick_size(300), 512);
+        assert_eq!(pick_size(4000), 512);
+    }
+
+    #[test]
+    fn generates_and_serves_with_etags() {
+        let dir = std::env::temp_dir().join(format!("wallpaper-thumbs-{}", uuid::Uuid::new_v4()));
+        let source = fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures/pipeline/gradient_64x48.png"),
+        )
+        .unwrap();
+        ensure_thumbnails(&dir, "w1", &source).unwrap();
+
+        let missing =
+            thumbnail_response(&dir, &request("wallpaper-thumb://localhost/w2/128", None));
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let response =
+            thumbnail_response(&dir, &request("wallpaper-thumb://localhost/w1/100", None));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/webp");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], CACHE_CONTROL);
+        let thumbnail = decode(response.body()).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (64, 48));
+
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let cached = thumbnail_response(
+            &dir,
+            &request("wallpaper-thumb://localhost/w1/100", Some(&etag)),
+        );
+        assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+        assert!(cached.body().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
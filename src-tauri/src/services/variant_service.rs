@@ -0,0 +1,150 @@
+use crate::services::db_services::{
+    get_preferred_resolution, get_wallpaper_by_url, DbPool, ImageVariant, VariantKind,
+};
+use tauri::{AppHandle, Manager};
+
+/// A screen size in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    /// Parses `"1920x1080"`. Returns `None` for `"auto"` and anything else
+    /// that isn't two positive numbers.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (width, height) = value.trim().split_once(['x', 'X', '×'])?;
+        let width: u32 = width.trim().parse().ok()?;
+        let height: u32 = height.trim().parse().ok()?;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some(Resolution { width, height })
+    }
+
+    /// Whether a `width`x`height` image fills this screen without upscaling.
+    fn is_covered_by(self, width: u32, height: u32) -> bool {
+        width >= self.width && height >= self.height
+    }
+}
+
+/// The resolution wallpapers are fetched and scaled for: the
+/// `preferredResolution` setting, or the primary monitor's size when the
+/// setting is `auto` or missing. `None` if neither is known.
+pub fn target_resolution(app: &AppHandle) -> Option<Resolution> {
+    let preferred = app
+        .state::<DbPool>()
+        .get()
+        .ok()
+        .and_then(|conn| get_preferred_resolution(&conn).ok().flatten());
+    if let Some(resolution) = preferred.as_deref().and_then(Resolution::parse) {
+        return Some(resolution);
+    }
+
+    let monitor = app.primary_monitor().ok().flatten()?;
+    let size = monitor.size();
+    Resolution::parse(&format!("{}x{}", size.width, size.height))
+}
+
+/// The smallest variant that still covers `target`, or the largest one if
+/// none does. Thumbnails are never picked.
+pub fn pick_variant(variants: &[ImageVariant], target: Resolution) -> Option<&ImageVariant> {
+    let area = |v: &&ImageVariant| u64::from(v.width) * u64::from(v.height);
+    let candidates = variants
+        .iter()
+        .filter(|v| v.kind != VariantKind::Thumbnail && v.width > 0 && v.height > 0);
+
+    candidates
+        .clone()
+        .filter(|v| target.is_covered_by(v.width, v.height))
+        .min_by_key(area)
+        .or_else(|| candidates.max_by_key(area))
+}
+
+/// The URL to download for `url`. If it belongs to a stored wallpaper, that
+/// is the best of its variants for `target`, with the wallpaper's own URL
+/// standing in for the original. Anything else is returned as is.
+pub fn resolve_url(app: &AppHandle, url: &str, target: Resolution) -> String {
+    let wallpaper = app
+        .state::<DbPool>()
+        .get()
+        .ok()
+        .and_then(|conn| get_wallpaper_by_url(&conn, url).ok().flatten());
+    let Some(wallpaper) = wallpaper else {
+        return url.to_string();
+    };
+
+    let mut variants = wallpaper.variants;
+    if !variants.iter().any(|v| v.kind == VariantKind::Original) {
+        variants.push(ImageVariant {
+            kind: VariantKind::Original,
+            url: wallpaper.url,
+            width: wallpaper.width.max(0) as u32,
+            height: wallpaper.height.max(0) as u32,
+        });
+    }
+
+    pick_variant(&variants, target)
+        .map(|v| v.url.clone())
+        .unwrap_or_else(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(kind: VariantKind, width: u32, height: u32) -> ImageVariant {
+        ImageVariant {
+            kind,
+            url: format!("{}x{}", width, height),
+            width,
+            height,
+        }
+    }
+
+    fn variants() -> Vec<ImageVariant> {
+        vec![
+            variant(VariantKind::Thumbnail, 400, 225),
+            variant(VariantKind::Hd, 1920, 1080),
+            variant(VariantKind::Qhd, 2560, 1440),
+            variant(VariantKind::Uhd, 3840, 2160),
+            variant(VariantKind::Original, 6000, 3375),
+        ]
+    }
+
+    #[test]
+    fn parses_resolutions() {
+        assert_eq!(
+            Resolution::parse("2560x1440"),
+            Some(Resolution {
+                width: 2560,
+                height: 1440
+            })
+        );
+        assert_eq!(Resolution::parse("auto"), None);
+        assert_eq!(Resolution::parse("0x1080"), None);
+    }
+
+    #[test]
+    fn picks_smallest_covering_variant() {
+        let all = variants();
+        let pick = |s| {
+            pick_variant(&all, Resolution::parse(s).unwrap())
+                .unwrap()
+                .url
+                .as_str()
+        };
+        assert_eq!(pick("1366x768"), "1920x1080");
+        assert_eq!(pick("1920x1080"), "1920x1080");
+        assert_eq!(pick("1920x1200"), "2560x1440");
+        assert_eq!(pick("3840x2160"), "3840x2160");
+        assert_eq!(pick("7680x4320"), "6000x3375");
+    }
+
+    #[test]
+    fn never_picks_thumbnails() {
+        let only_thumb = vec![variant(VariantKind::Thumbnail, 400, 225)];
+        assert!(pick_variant(&only_thumb, Resolution::parse("320x200").unwrap()).is_none());
+    }
+}
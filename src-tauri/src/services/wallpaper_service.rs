@@ -1,20 +1,39 @@
 use std::{
-    fs::{self, File},
-    io::copy,
-    path::PathBuf,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
     sync::{
-        mpsc::{self, Sender},
+        mpsc::{self, RecvTimeoutError, Sender},
         Arc, Mutex,
     },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
 use app_dirs2::{app_dir, AppDataType, AppInfo};
+use chrono::Local;
 use dirs::download_dir;
 use reqwest::blocking::get;
+use rusqlite::Connection;
 use sanitize_filename::sanitize;
-use tauri::command;
+use tauri::{command, AppHandle};
+
+use crate::services::color_scheme_service::export_applied_scheme;
+use crate::services::crop_service::plan_crop;
+use crate::services::db_services::{
+    get_setting, get_wallpaper_by_url, is_excluded_url, DbPool, Settings,
+};
+use crate::services::duplicate_service::ensure_hash;
+use crate::services::effects::Effect;
+use crate::services::effects_service::resolve_effects;
+use crate::services::image_pipeline::{process, Fit, OutputFormat, PipelineOptions};
+use crate::services::metadata::{exif_for_copy, with_exif};
+use crate::services::metadata_service::strip_location_enabled;
+use crate::services::overlay_service::{current_overlay, refresh_interval};
+use crate::services::palette_service::{ensure_palette, follow_applied_wallpaper};
+use crate::services::thumbnail_service::{ensure_thumbnails, thumbnail_dir};
+use crate::services::variant_service::{resolve_url, target_resolution};
+use tauri::Manager;
 
 #[cfg(target_os = "windows")]
 use wallpaper;
@@ -25,31 +44,181 @@ use wallpaper;
 #[cfg(target_os = "linux")]
 use std::process::Command;
 
-const APP_INFO: AppInfo = AppInfo {
+pub(crate) const APP_INFO: AppInfo = AppInfo {
     name: "WallpaperRemix",
     author: "AnimeshBhardwaj",
 };
 
 // ---------------------- Apply Wallpaper ----------------------
 
+/// Runs off the main thread: the pipeline downloads, decodes, crops and
+/// encodes full-size images and would otherwise freeze the window.
 #[command]
-pub fn apply_wallpaper(image_url: String) -> Result<(), String> {
-    let save_dir: PathBuf = app_dir(AppDataType::UserCache, &APP_INFO, "images")
-    .map_err(|e| format!("Failed to resolve app data directory: {e}"))?;
+pub async fn apply_wallpaper(app: AppHandle, image_url: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        set_wallpaper(&app, &image_url, None)?;
+        WALLPAPER_MANAGER.keep_overlay_current(app, image_url);
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
 
-    fs::create_dir_all(&save_dir).map_err(|e| format!("Failed to create directory: {e}"))?;
+/// Renders `image_url` for the screen and makes it the desktop background.
+/// `schedule_effects` is the effects chain of the rotation doing the apply.
+fn set_wallpaper(
+    app: &AppHandle,
+    image_url: &str,
+    schedule_effects: Option<&[Effect]>,
+) -> Result<(), String> {
+    let settings = load_settings(app);
+    let target = target_resolution(app);
+    let mut options = PipelineOptions::from_settings(&settings, target, Fit::Fill);
+    if !DESKTOP_FORMATS.contains(&options.format) {
+        options.format = DESKTOP_FORMATS[0];
+    }
 
-    let wallpaper_path = save_dir.join("wallpaper.jpg");
+    // Known wallpapers crop around their focal point, or reuse the crop chosen
+    // the first time they were applied at this aspect ratio.
+    let conn = app.state::<DbPool>().get().map_err(|e| e.to_string())?;
+    let stored = get_wallpaper_by_url(&conn, image_url).map_err(|e| e.to_string())?;
+    options.effects = resolve_effects(
+        &conn,
+        stored.as_ref().map(|w| w.id.as_str()),
+        schedule_effects,
+    )
+    .map_err(|e| e.to_string())?;
+    options.overlay =
+        current_overlay(&conn, Local::now().naive_local()).map_err(|e| e.to_string())?;
+    let crop_plan = match (&stored, target) {
+        (Some(wallpaper), Some(target)) => {
+            Some(plan_crop(&conn, wallpaper, target).map_err(|e| e.to_string())?)
+        }
+        _ => None,
+    };
+    if let Some(plan) = &crop_plan {
+        options.focus = plan.focus;
+    }
 
+    // Renders of known wallpapers are cached per effects chain, overlay and
+    // output settings, so rotating back to one skips the download and
+    // processing. The least recently applied renders go once the cache
+    // outgrows `RENDER_CACHE_BYTES`.
+    let wallpaper_path = match &stored {
+        Some(wallpaper) => {
+            let cache_dir = app_dir(AppDataType::UserCache, &APP_INFO, "renders")
+                .map_err(|e| format!("Failed to resolve app cache directory: {e}"))?;
+            let path = render_cache_path(&cache_dir, &wallpaper.id, &options);
+            if path.exists() {
+                touch(&path);
+            } else {
+                let source = load_source(app, image_url)?;
+                store_thumbnails(&conn, &wallpaper.id, &source);
+                let processed = process(&source, &options)?;
+                if let Some(plan) = &crop_plan {
+                    plan.remember(&conn, processed.crop)
+                        .map_err(|e| e.to_string())?;
+                }
+                fs::write(&path, processed.bytes).map_err(|e| e.to_string())?;
+                prune_render_cache(&cache_dir, RENDER_CACHE_BYTES);
+            }
+            path
+        }
+        None => {
+            let save_dir: PathBuf = app_dir(AppDataType::UserCache, &APP_INFO, "images")
+                .map_err(|e| format!("Failed to resolve app data directory: {e}"))?;
+            let path = save_dir.join(format!("wallpaper.{}", options.format.extension()));
+            fs::write(
+                &path,
+                process(&load_source(app, image_url)?, &options)?.bytes,
+            )
+            .map_err(|e| e.to_string())?;
+            path
+        }
+    };
+
+    set_desktop_wallpaper(&wallpaper_path)?;
+    if let Some(wallpaper) = &stored {
+        follow_applied_wallpaper(app, &conn, &wallpaper.id);
+    }
+    export_applied_scheme(app, &conn, &wallpaper_path);
+    Ok(())
+}
+
+/// Downloads or reads `image_url`.
+fn load_source(app: &AppHandle, image_url: &str) -> Result<Vec<u8>, String> {
     if image_url.starts_with("http://") || image_url.starts_with("https://") {
-        let mut resp = get(&image_url).map_err(|e| e.to_string())?;
-        let mut out = File::create(&wallpaper_path).map_err(|e| e.to_string())?;
-        copy(&mut resp, &mut out).map_err(|e| e.to_string())?;
+        fetch_for_screen(app, image_url)
     } else {
-        fs::copy(&image_url, &wallpaper_path)
-            .map_err(|e| format!("Failed to copy local image: {e}"))?;
+        fs::read(image_url).map_err(|e| format!("Failed to read local image: {e}"))
     }
+}
 
+/// Keeps local thumbnails and the palette of every wallpaper the app has the
+/// image of, so the gallery can show and filter them offline. A failure
+/// only costs the thumbnails or palette.
+fn store_thumbnails(conn: &Connection, wallpaper_id: &str, source: &[u8]) {
+    if let Ok(dir) = thumbnail_dir() {
+        if ensure_thumbnails(&dir, wallpaper_id, source).is_ok() {
+            let _ = ensure_palette(conn, &dir, wallpaper_id);
+            let _ = ensure_hash(conn, &dir, wallpaper_id);
+        }
+    }
+}
+
+fn render_cache_path(cache_dir: &Path, wallpaper_id: &str, options: &PipelineOptions) -> PathBuf {
+    cache_dir.join(format!(
+        "{}-{}.{}",
+        sanitize(wallpaper_id),
+        options.fingerprint(),
+        options.format.extension()
+    ))
+}
+
+/// Size the render cache is trimmed back to after each new render.
+const RENDER_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Marks a cached render as just used, so pruning keeps it longest.
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().append(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+/// Deletes the least recently used renders until the cache fits in
+/// `max_bytes`. The newest render is always kept, however large.
+fn prune_render_cache(cache_dir: &Path, max_bytes: u64) {
+    let Ok(entries) = fs::read_dir(cache_dir) else {
+        return;
+    };
+    let mut renders: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((meta.modified().ok()?, meta.len(), entry.path()))
+        })
+        .collect();
+    renders.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified));
+
+    let mut total = 0;
+    for (index, (_, len, path)) in renders.into_iter().enumerate() {
+        total += len;
+        if index > 0 && total > max_bytes {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Encodings the desktop accepts as a background; applied wallpapers in any
+/// other output format are written as the first. GNOME and Windows only
+/// read WebP with an optional codec installed.
+#[cfg(not(target_os = "macos"))]
+const DESKTOP_FORMATS: &[OutputFormat] = &[OutputFormat::Jpeg, OutputFormat::Png];
+#[cfg(target_os = "macos")]
+const DESKTOP_FORMATS: &[OutputFormat] =
+    &[OutputFormat::Jpeg, OutputFormat::Png, OutputFormat::Webp];
+
+fn set_desktop_wallpaper(wallpaper_path: &Path) -> Result<(), String> {
     #[cfg(any(target_os = "windows", target_os = "macos"))]
     {
         wallpaper::set_from_path(wallpaper_path.to_str().unwrap())
@@ -81,28 +250,97 @@ pub fn apply_wallpaper(image_url: String) -> Result<(), String> {
 
 // ---------------------- Download Wallpaper ----------------------
 
+/// Runs off the main thread, like `apply_wallpaper`.
 #[command]
-pub fn download_wallpaper(url: String, filename: String) -> Result<String, String> {
-    let download_path = download_dir()
-        .ok_or("Failed to resolve system download directory")?;
-
+pub async fn download_wallpaper(
+    app: AppHandle,
+    url: String,
+    filename: String,
+) -> Result<String, String> {
+    let download_path = download_dir().ok_or("Failed to resolve system download directory")?;
 
     fs::create_dir_all(&download_path).map_err(|e| e.to_string())?;
 
     let safe_filename = sanitize(&filename);
     let file_path = download_path.join(safe_filename);
+    tauri::async_runtime::spawn_blocking(move || {
+        save_wallpaper_file(&app, &url, &file_path)?;
+        Ok(file_path.to_string_lossy().into_owned())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Writes `url` to `file_path` for keeping: the whole picture, in the format
+/// the file name asks for.
+pub(crate) fn save_wallpaper_file(
+    app: &AppHandle,
+    url: &str,
+    file_path: &Path,
+) -> Result<(), String> {
+    let settings = load_settings(app);
+    let mut options = PipelineOptions::from_settings(&settings, target_resolution(app), Fit::Cover);
+    if let Some(format) = OutputFormat::from_path(file_path) {
+        options.format = format;
+    }
+
+    let bytes = load_source(app, url)?;
+    if let Ok(conn) = app.state::<DbPool>().get() {
+        if let Ok(Some(wallpaper)) = get_wallpaper_by_url(&conn, url) {
+            store_thumbnails(&conn, &wallpaper.id, &bytes);
+        }
+    }
+    let processed = process(&bytes, &options)?;
+
+    // The pipeline drops metadata; credits and camera details go back in,
+    // and the location too unless the user asked to keep it out.
+    let strip_location = app
+        .state::<DbPool>()
+        .get()
+        .ok()
+        .and_then(|conn| strip_location_enabled(&conn).ok())
+        .unwrap_or(true);
+    let output = match exif_for_copy(&bytes, strip_location) {
+        Some(exif) => with_exif(processed.bytes, exif),
+        None => processed.bytes,
+    };
+    fs::write(file_path, output).map_err(|e| e.to_string())
+}
+
+/// Downloads the variant of `url` best suited to the target resolution.
+fn fetch_for_screen(app: &AppHandle, url: &str) -> Result<Vec<u8>, String> {
+    let url = match target_resolution(app) {
+        Some(target) => resolve_url(app, url, target),
+        None => url.to_string(),
+    };
 
     let mut resp = get(&url).map_err(|e| e.to_string())?;
-    let mut out = File::create(&file_path).map_err(|e| e.to_string())?;
-    copy(&mut resp, &mut out).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    resp.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// How often to re-render the current wallpaper for its overlay, if at all.
+fn overlay_refresh(app: &AppHandle) -> Option<Duration> {
+    let conn = app.state::<DbPool>().get().ok()?;
+    refresh_interval(&conn).ok().flatten()
+}
 
-    Ok(file_path.to_string_lossy().into_owned())
+/// App settings, or none if the database can't be read.
+fn load_settings(app: &AppHandle) -> Settings {
+    app.state::<DbPool>()
+        .get()
+        .map_err(|e| e.to_string())
+        .and_then(|conn| get_setting(&conn))
+        .unwrap_or_default()
 }
 
 // ---------------------- Wallpaper Rotation ----------------------
 
 struct WallpaperManager {
     active: Arc<Mutex<bool>>,
+    /// Whether the running thread only re-renders one wallpaper's overlay.
+    overlay_only: Arc<Mutex<bool>>,
     stop_sender: Arc<Mutex<Option<Sender<()>>>>,
     thread_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
@@ -111,12 +349,52 @@ impl WallpaperManager {
     fn new() -> Self {
         Self {
             active: Arc::new(Mutex::new(false)),
+            overlay_only: Arc::new(Mutex::new(false)),
             stop_sender: Arc::new(Mutex::new(None)),
             thread_handle: Arc::new(Mutex::new(None)),
         }
     }
 
-    fn start_rotation(&self, paths: Vec<String>, interval_sec: u64) {
+    fn start_rotation(
+        &self,
+        app: AppHandle,
+        paths: Vec<String>,
+        interval_sec: u64,
+        effects: Option<Vec<Effect>>,
+    ) {
+        self.spawn(
+            app,
+            paths,
+            Duration::from_secs(interval_sec),
+            effects,
+            false,
+        );
+    }
+
+    /// Re-renders a wallpaper applied by hand while its overlay needs it.
+    /// A running rotation already does this for the wallpaper it applied.
+    fn keep_overlay_current(&self, app: AppHandle, path: String) {
+        if *self.active.lock().unwrap() && !*self.overlay_only.lock().unwrap() {
+            return;
+        }
+        match overlay_refresh(&app) {
+            Some(refresh) => self.spawn(app, vec![path], refresh, None, true),
+            None => self.stop_rotation(),
+        }
+    }
+
+    /// Applies `paths` in turn every `interval`, re-rendering the current
+    /// one in between as often as the overlay refresh asks. With
+    /// `overlay_only` the first path is already applied and the thread ends
+    /// once the overlay no longer needs refreshing.
+    fn spawn(
+        &self,
+        app: AppHandle,
+        paths: Vec<String>,
+        interval: Duration,
+        effects: Option<Vec<Effect>>,
+        overlay_only: bool,
+    ) {
         self.stop_rotation(); // Stop any existing rotation
 
         let active_flag = Arc::clone(&self.active);
@@ -125,23 +403,51 @@ impl WallpaperManager {
         let thread_handle = Arc::clone(&self.thread_handle);
 
         *active_flag.lock().unwrap() = true;
+        *self.overlay_only.lock().unwrap() = overlay_only;
         *stop_sender.lock().unwrap() = Some(tx);
 
         let handle = thread::spawn(move || {
-            let mut index = 0;
             let total = paths.len();
+            let mut index = usize::from(overlay_only);
+            // What is on the desktop, if the rotation put anything there.
+            let mut current = overlay_only.then_some(0);
+            let mut next_change = Instant::now();
+            if overlay_only {
+                next_change += interval;
+            }
+            // The current wallpaper was just rendered.
+            let mut fresh = overlay_only;
 
-            while *active_flag.lock().unwrap() {
-                if let Ok(_) = rx.try_recv() {
-                    break; // Stop signal
+            while *active_flag.lock().unwrap() && total > 0 {
+                let refresh = overlay_refresh(&app);
+                if overlay_only && refresh.is_none() {
+                    break;
                 }
 
-                if let Some(path) = paths.get(index % total) {
-                    let _ = apply_wallpaper(path.clone());
-                    index += 1;
+                let now = Instant::now();
+                let excluded = |i: usize| excluded_from_rotation(&app, &paths[i % total]);
+                if now >= next_change {
+                    current = next_in_rotation(total, index, excluded);
+                    if let Some(next) = current {
+                        let _ = set_wallpaper(&app, &paths[next % total], effects.as_deref());
+                    }
+                    index = current.unwrap_or(index) + 1;
+                    next_change = now + interval;
+                } else if !fresh {
+                    // Same wallpaper, newer overlay, unless it was hidden or
+                    // blocked meanwhile.
+                    if let Some(i) = current.filter(|&i| !excluded(i)) {
+                        let _ = set_wallpaper(&app, &paths[i % total], effects.as_deref());
+                    }
                 }
+                fresh = false;
 
-                thread::sleep(Duration::from_secs(interval_sec));
+                let until_change = next_change.saturating_duration_since(Instant::now());
+                let wait = refresh.map_or(until_change, |r| r.min(until_change));
+                match rx.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => break, // Stop signal
+                }
             }
         });
 
@@ -150,6 +456,7 @@ impl WallpaperManager {
 
     fn stop_rotation(&self) {
         *self.active.lock().unwrap() = false;
+        *self.overlay_only.lock().unwrap() = false;
 
         if let Some(sender) = self.stop_sender.lock().unwrap().take() {
             let _ = sender.send(());
@@ -161,12 +468,32 @@ impl WallpaperManager {
     }
 }
 
+/// The first of `total` rotation slots from `start` on, wrapping around, that
+/// `excluded` doesn't rule out. `None` when it rules them all out.
+fn next_in_rotation(total: usize, start: usize, excluded: impl Fn(usize) -> bool) -> Option<usize> {
+    (start..start + total).find(|&i| !excluded(i))
+}
+
+/// Whether the stored wallpaper at `path` was hidden or blocked.
+fn excluded_from_rotation(app: &AppHandle, path: &str) -> bool {
+    app.state::<DbPool>()
+        .get()
+        .ok()
+        .and_then(|conn| is_excluded_url(&conn, path).ok())
+        .unwrap_or(false)
+}
+
 use once_cell::sync::Lazy;
 static WALLPAPER_MANAGER: Lazy<WallpaperManager> = Lazy::new(WallpaperManager::new);
 
 #[command]
-pub fn start_wallpaper_rotation(paths: Vec<String>, interval_sec: u64) -> Result<(), String> {
-    WALLPAPER_MANAGER.start_rotation(paths, interval_sec);
+pub fn start_wallpaper_rotation(
+    app: AppHandle,
+    paths: Vec<String>,
+    interval_sec: u64,
+    effects: Option<Vec<Effect>>,
+) -> Result<(), String> {
+    WALLPAPER_MANAGER.start_rotation(app, paths, interval_sec, effects);
     Ok(())
 }
 
@@ -174,3 +501,56 @@ pub fn start_wallpaper_rotation(paths: Vec<String>, interval_sec: u64) -> Result
 pub fn stop_wallpaper_rotation() {
     WALLPAPER_MANAGER.stop_rotation();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_passes_over_excluded_wallpapers() {
+        let hidden = [false, true, true, false];
+        let excluded = |i: usize| hidden[i % hidden.len()];
+        assert_eq!(next_in_rotation(4, 0, excluded), Some(0));
+        assert_eq!(next_in_rotation(4, 1, excluded), Some(3));
+        // Wraps around; the slot keeps counting so `% total` finds the path.
+        assert_eq!(next_in_rotation(4, 4, excluded), Some(4));
+        assert_eq!(next_in_rotation(4, 5, excluded), Some(7));
+
+        // With everything excluded nothing is applied, so there is nothing
+        // for an overlay refresh to put back either.
+        assert_eq!(next_in_rotation(4, 2, |_| true), None);
+        assert_eq!(next_in_rotation(0, 0, |_| false), None);
+    }
+
+    #[test]
+    fn render_cache_drops_least_recently_used_renders() {
+        let dir = std::env::temp_dir().join(format!("wallpaper-renders-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        // Two renders of the same wallpaper, and an older one of another.
+        for (name, age) in [("a-1.jpg", 30), ("a-2.jpg", 10), ("b-1.jpg", 20)] {
+            let path = dir.join(name);
+            fs::write(&path, [0u8; 100]).unwrap();
+            fs::File::options()
+                .append(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+
+        // Applying the oldest again saves it from the next prune.
+        touch(&dir.join("a-1.jpg"));
+        prune_render_cache(&dir, 250);
+        assert!(dir.join("a-1.jpg").exists());
+        assert!(dir.join("a-2.jpg").exists());
+        assert!(!dir.join("b-1.jpg").exists());
+
+        // The newest render stays even when it alone is over the limit.
+        prune_render_cache(&dir, 50);
+        assert!(dir.join("a-1.jpg").exists());
+        assert!(!dir.join("a-2.jpg").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
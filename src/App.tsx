@@ -59,7 +59,7 @@ const App = () => {
   const { wallpapers, fetchWallpapers, fetchSetting } = useWallpaperStore();
 
   useEffect(() => {
-    fetchWallpapers(10, 0);
+    fetchWallpapers(10);
     fetchSetting(); 
   }, []);
 
@@ -5,6 +5,8 @@ type ImageProps = {
   id: string;
   src: string;
   thumbnail?: string;
+  /** Drawn over `thumbnail` when it loads, e.g. a local thumbnail. */
+  localThumbnail?: string;
   alt?: string;
   className?: string;
   fallbackSrc?: string;
@@ -24,6 +26,7 @@ const OptimizedImage: React.FC<ImageProps> = ({
   id,
   src,
   thumbnail,
+  localThumbnail,
   alt = '',
   className = '',
   fallbackSrc = '',
@@ -82,6 +85,10 @@ const OptimizedImage: React.FC<ImageProps> = ({
   };
 
   const blurImage = blurDataURL || thumbnail || src;
+  // A missing local thumbnail leaves its layer empty, showing the one below.
+  const blurLayers = localThumbnail
+    ? `url(${localThumbnail}), url(${blurImage})`
+    : `url(${blurImage})`;
 
   return (
     <div
@@ -98,7 +105,7 @@ const OptimizedImage: React.FC<ImageProps> = ({
         <div
           className="absolute inset-0 transition-opacity duration-700 z-0"
           style={{
-            backgroundImage: `url(${blurImage})`,
+            backgroundImage: blurLayers,
             backgroundSize: 'cover',
             backgroundPosition: 'center',
             filter: mainImageLoaded ? 'blur(0px)' : 'blur(20px)',
@@ -4,6 +4,7 @@ import { useTheme } from "@/contexts/ThemeContext";
 import OptimizedImage from "./OptimizedImage";
 import { useWallpaperStore } from "@/store/useWallpaperStore";
 import { cn } from "@/utils";
+import { thumbnailUrl as localThumbnailUrl } from "@/lib/wallpaperService";
 
   interface WallpaperCardProps {
     id: string;
@@ -51,6 +52,7 @@ import { cn } from "@/utils";
             id={id}
             src={imageUrl}
             thumbnail={thumbnailUrl}
+            localThumbnail={localThumbnailUrl(id, 512)}
             alt={title}
             lazy={true}
             className="w-full h-full object-cover transition-transform duration-300 group-hover:scale-105"
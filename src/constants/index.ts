@@ -33,6 +33,7 @@ export const appThemeOptions = [
 ];
 
  const resolutionOptions = [
+    { value: "auto", label: "Match screen" },
     "1366x768",
     "1440x900",
     "1600x900",
@@ -41,4 +42,24 @@ export const appThemeOptions = [
     "3840x2160",
   ];
 
-export { availableLanguages, translations, updateInterval, resolutionOptions };
+const imageFormatOptions = [
+  { value: "jpeg", label: "JPEG" },
+  { value: "png", label: "PNG (lossless)" },
+  { value: "webp", label: "WebP" },
+];
+
+const imageQualityOptions = [
+  { value: "100", label: "Maximum" },
+  { value: "90", label: "High" },
+  { value: "75", label: "Medium" },
+  { value: "60", label: "Low" },
+];
+
+export {
+  availableLanguages,
+  translations,
+  updateInterval,
+  resolutionOptions,
+  imageFormatOptions,
+  imageQualityOptions,
+};
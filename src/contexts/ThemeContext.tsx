@@ -1,5 +1,7 @@
 import { createContext, useContext, useEffect, useState, ReactNode } from "react";
+import { listen } from "@tauri-apps/api/event";
 import { useWallpaperStore } from "@/store/useWallpaperStore";
+import { getAccentColor } from "@/lib/wallpaperService";
 
 interface ThemeContextType {
   isDarkMode: boolean;
@@ -34,6 +36,18 @@ export const ThemeProvider = ({ children }: { children: ReactNode }) => {
     }
   }, [settings?.appTheme]);
 
+  // Follow the accent color of the applied wallpaper
+  useEffect(() => {
+    const applyAccent = (color: string | null) => {
+      if (color) document.documentElement.style.setProperty("--accent-color", color);
+    };
+    getAccentColor().then(applyAccent).catch(() => {});
+    const unlisten = listen<string>("accent-color-changed", (event) => applyAccent(event.payload));
+    return () => {
+      unlisten.then((off) => off());
+    };
+  }, []);
+
   const toggleDarkMode = () => setIsDarkMode(prev => !prev);
 
   return (
@@ -1,7 +1,16 @@
-import { invoke } from "@tauri-apps/api/core";
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
 import { getCurrentWindow } from '@tauri-apps/api/window';
 const appWindow = getCurrentWindow();
 import toast from "react-hot-toast";
+import { BlockedWallpaper, Collection, ColorSchemeSettings, Effect, ExportReport, FocalPoint, ImageMetadata, ImportReport, LibraryChange, LibraryFolder, OverlaySettings, Palette, SmartCollection, Wallpaper, WallpaperFilter, WallpaperPage, WallpaperSort } from "@/types";
+
+/**
+ * URL of a locally generated thumbnail, about `size` pixels on its longest
+ * side. Only wallpapers that were applied or downloaded have one, so keep
+ * the remote thumbnail as a fallback.
+ */
+export const thumbnailUrl = (id: string, size = 256): string =>
+  convertFileSrc(`${id}/${size}`, "wallpaper-thumb");
 
 /**
  * Applies a wallpaper given its absolute path.
@@ -38,19 +47,369 @@ export const downloadWallpaper = async (
   }
 };
 
+/**
+ * Sets the point a wallpaper is cropped around when applied, or clears it
+ * with `null` so the automatic crop is used.
+ */
+export const setFocalPoint = async (
+  id: string,
+  focalPoint: FocalPoint | null
+): Promise<void> => {
+  try {
+    await invoke("set_focal_point_command", { id, focalPoint });
+  } catch (err) {
+    console.error("Failed to set focal point:", err);
+    toast.error("Could not update the focal point.");
+    throw err;
+  }
+};
+
+/**
+ * Returns the effects chain applied to wallpapers without their own.
+ */
+export const getGlobalEffects = async (): Promise<Effect[]> => {
+  return invoke<Effect[]>("get_global_effects_command");
+};
+
+export const setGlobalEffects = async (effects: Effect[]): Promise<void> => {
+  await invoke("set_global_effects_command", { effects });
+};
+
+/**
+ * Returns a wallpaper's own effects chain, or `null` if it uses the
+ * rotation's or the global one.
+ */
+export const getWallpaperEffects = async (id: string): Promise<Effect[] | null> => {
+  return invoke<Effect[] | null>("get_wallpaper_effects_command", { id });
+};
+
+/**
+ * Gives a wallpaper its own effects chain, or clears it with `null`.
+ */
+export const setWallpaperEffects = async (
+  id: string,
+  effects: Effect[] | null
+): Promise<void> => {
+  await invoke("set_wallpaper_effects_command", { id, effects });
+};
+
+/**
+ * Returns a wallpaper's colors, or `null` until it has been applied or
+ * downloaded once.
+ */
+export const getPalette = async (id: string): Promise<Palette | null> => {
+  return invoke<Palette | null>("get_palette", { id });
+};
+
+/**
+ * Returns the accent color of the wallpaper applied last, if known.
+ */
+export const getAccentColor = async (): Promise<string | null> => {
+  return invoke<string | null>("get_accent_color");
+};
+
+/**
+ * Returns the overlay drawn on applied wallpapers.
+ */
+export const getOverlaySettings = async (): Promise<OverlaySettings> => {
+  return invoke<OverlaySettings>("get_overlay_settings_command");
+};
+
+/**
+ * Saves the overlay; it is drawn from the next apply or refresh on.
+ */
+export const setOverlaySettings = async (settings: OverlaySettings): Promise<void> => {
+  await invoke("set_overlay_settings_command", { settings });
+};
+
+/**
+ * Adds the images in a folder to the library, tagged with their folder
+ * names. Progress arrives as `import-progress` events.
+ */
+export const importFolder = async (path: string, recursive: boolean): Promise<ImportReport> => {
+  const toastId = toast.loading("Importing folder...");
+  try {
+    const report = await invoke<ImportReport>("import_folder", { path, recursive });
+    toast.success(`Imported ${report.imported} wallpapers.`, { id: toastId });
+    return report;
+  } catch (err) {
+    console.error("Failed to import folder:", err);
+    toast.error("Could not import folder.", { id: toastId });
+    throw err;
+  }
+};
+
+/**
+ * Returns the folders watched for new, renamed and deleted images.
+ */
+export const getLibraryFolders = async (): Promise<LibraryFolder[]> => {
+  return invoke<LibraryFolder[]>("list_library_folders_command");
+};
+
+/**
+ * Watches a folder and imports what is in it. Later changes arrive as
+ * `library-changed` events.
+ */
+export const addLibraryFolder = async (path: string, recursive: boolean): Promise<LibraryFolder> => {
+  return invoke<LibraryFolder>("add_library_folder_command", { path, recursive });
+};
+
+/**
+ * Stops watching a folder; wallpapers imported from it stay.
+ */
+export const removeLibraryFolder = async (path: string): Promise<boolean> => {
+  return invoke<boolean>("remove_library_folder_command", { path });
+};
+
+/**
+ * Rescans every library folder now instead of waiting for the periodic rescan.
+ */
+export const rescanLibrary = async (): Promise<LibraryChange> => {
+  return invoke<LibraryChange>("rescan_library_command");
+};
+
+/**
+ * Returns the camera, capture, location and credit details of an imported
+ * photo, or null when it has none.
+ */
+export const getWallpaperMetadata = async (id: string): Promise<ImageMetadata | null> => {
+  return invoke<ImageMetadata | null>("get_wallpaper_metadata", { id });
+};
+
+/** Rates a wallpaper 1–5 stars, or clears its rating with null. */
+export const setWallpaperRating = async (id: string, rating: number | null): Promise<void> => {
+  await invoke("set_wallpaper_rating_command", { id, rating });
+};
+
+/** Hides a wallpaper from listings and rotations, or shows it again. */
+export const setWallpaperHidden = async (id: string, hidden: boolean): Promise<void> => {
+  await invoke("set_wallpaper_hidden_command", { id, hidden });
+};
+
+/**
+ * Never shows the wallpaper again, even after it is synced or rescanned in
+ * again.
+ */
+export const blockWallpaper = async (id: string): Promise<void> => {
+  await invoke("block_wallpaper_command", { id });
+};
+
+/** Takes an entry off the blocklist and returns what is left on it. */
+export const unblockWallpaper = async (id: number): Promise<BlockedWallpaper[]> => {
+  return invoke<BlockedWallpaper[]>("unblock_wallpaper_command", { id });
+};
+
+export const getBlockedWallpapers = async (): Promise<BlockedWallpaper[]> => {
+  return invoke<BlockedWallpaper[]>("list_blocked_wallpapers_command");
+};
+
+/**
+ * Returns every collection, the built-in Favorites first.
+ */
+export const getCollections = async (): Promise<Collection[]> => {
+  return invoke<Collection[]>("list_collections_command");
+};
+
+export const createCollection = async (name: string): Promise<Collection> => {
+  return invoke<Collection>("create_collection_command", { name });
+};
+
+export const renameCollection = async (id: string, name: string): Promise<void> => {
+  await invoke("rename_collection_command", { id, name });
+};
+
+/**
+ * Deletes a collection. Its wallpapers stay in the library.
+ */
+export const deleteCollection = async (id: string): Promise<void> => {
+  await invoke("delete_collection_command", { id });
+};
+
+/**
+ * Puts collections in the given order; any left out follow.
+ */
+export const reorderCollections = async (ids: string[]): Promise<Collection[]> => {
+  return invoke<Collection[]>("reorder_collections_command", { ids });
+};
+
+export const getCollectionWallpapers = async (id: string): Promise<Wallpaper[]> => {
+  return invoke<Wallpaper[]>("get_collection_wallpapers_command", { id });
+};
+
+/**
+ * Appends wallpapers to a collection. Returns how many were new to it.
+ */
+export const addToCollection = async (id: string, wallpaperIds: string[]): Promise<number> => {
+  return invoke<number>("add_to_collection_command", { id, wallpaperIds });
+};
+
+export const removeFromCollection = async (id: string, wallpaperIds: string[]): Promise<number> => {
+  return invoke<number>("remove_from_collection_command", { id, wallpaperIds });
+};
+
+/**
+ * Puts a collection's wallpapers in the given order; any left out follow.
+ */
+export const reorderCollectionItems = async (id: string, wallpaperIds: string[]): Promise<Wallpaper[]> => {
+  return invoke<Wallpaper[]>("reorder_collection_items_command", { id, wallpaperIds });
+};
+
+/**
+ * Picks a collection's cover, or goes back to its first item with `null`.
+ */
+export const setCollectionCover = async (id: string, wallpaperId: string | null): Promise<void> => {
+  await invoke("set_collection_cover_command", { id, wallpaperId });
+};
+
+/**
+ * Rotates through a collection in its order every `intervalSec` seconds.
+ */
+export const startCollectionRotation = async (
+  id: string,
+  intervalSec: number,
+  effects?: Effect[]
+): Promise<void> => {
+  const toastId = toast.loading("Starting collection rotation...");
+  try {
+    await invoke("start_collection_rotation", { id, intervalSec, effects: effects ?? null });
+    toast.success("Collection rotation started!", { id: toastId });
+  } catch (err) {
+    console.error("Failed to start collection rotation:", err);
+    toast.error("Could not start collection rotation.", { id: toastId });
+    throw err;
+  }
+};
+
+/**
+ * Saves a collection's wallpapers into `destination`, or a folder named
+ * after it in Downloads. Progress arrives as `export-progress` events.
+ */
+export const exportCollection = async (id: string, destination?: string): Promise<ExportReport> => {
+  const toastId = toast.loading("Exporting collection...");
+  try {
+    const report = await invoke<ExportReport>("export_collection", { id, destination: destination ?? null });
+    toast.success(`Exported ${report.written.length} wallpapers.`, { id: toastId });
+    return report;
+  } catch (err) {
+    console.error("Failed to export collection:", err);
+    toast.error("Could not export collection.", { id: toastId });
+    throw err;
+  }
+};
+
+export const getSmartCollections = async (): Promise<SmartCollection[]> => {
+  return invoke<SmartCollection[]>("list_smart_collections_command");
+};
+
+export const createSmartCollection = async (
+  name: string,
+  filter: WallpaperFilter,
+  sort?: WallpaperSort
+): Promise<SmartCollection> => {
+  return invoke<SmartCollection>("create_smart_collection_command", { name, filter, sort: sort ?? null });
+};
+
+export const updateSmartCollection = async (
+  id: string,
+  name: string,
+  filter: WallpaperFilter,
+  sort?: WallpaperSort
+): Promise<SmartCollection> => {
+  return invoke<SmartCollection>("update_smart_collection_command", { id, name, filter, sort: sort ?? null });
+};
+
+export const deleteSmartCollection = async (id: string): Promise<void> => {
+  await invoke("delete_smart_collection_command", { id });
+};
+
+/**
+ * One page of the wallpapers a smart collection matches; pass the returned
+ * `next_cursor` back to get the next page.
+ */
+export const querySmartCollection = async (
+  id: string,
+  limit: number,
+  cursor?: string | null
+): Promise<WallpaperPage> => {
+  return invoke<WallpaperPage>("query_smart_collection_command", { id, limit, cursor: cursor ?? null });
+};
+
+/**
+ * Rotates through what a smart collection matches when it starts, every
+ * `intervalSec` seconds.
+ */
+export const startSmartCollectionRotation = async (
+  id: string,
+  intervalSec: number,
+  effects?: Effect[]
+): Promise<void> => {
+  const toastId = toast.loading("Starting collection rotation...");
+  try {
+    await invoke("start_smart_collection_rotation", { id, intervalSec, effects: effects ?? null });
+    toast.success("Collection rotation started!", { id: toastId });
+  } catch (err) {
+    console.error("Failed to start smart collection rotation:", err);
+    toast.error("Could not start collection rotation.", { id: toastId });
+    throw err;
+  }
+};
+
+/**
+ * Saves what a smart collection matches into `destination`, or a folder
+ * named after it in Downloads. Progress arrives as `export-progress` events.
+ */
+export const exportSmartCollection = async (id: string, destination?: string): Promise<ExportReport> => {
+  const toastId = toast.loading("Exporting collection...");
+  try {
+    const report = await invoke<ExportReport>("export_smart_collection", { id, destination: destination ?? null });
+    toast.success(`Exported ${report.written.length} wallpapers.`, { id: toastId });
+    return report;
+  } catch (err) {
+    console.error("Failed to export smart collection:", err);
+    toast.error("Could not export collection.", { id: toastId });
+    throw err;
+  }
+};
+
+/**
+ * Returns groups of visually identical wallpapers, each with the one worth
+ * keeping first. `maxDistance` is how many of the 64 hash bits may differ.
+ */
+export const findDuplicates = async (maxDistance?: number): Promise<Wallpaper[][]> => {
+  return invoke<Wallpaper[][]>("find_duplicates_command", { maxDistance: maxDistance ?? null });
+};
+
+/**
+ * Returns the terminal and desktop color scheme export settings.
+ */
+export const getColorSchemeSettings = async (): Promise<ColorSchemeSettings> => {
+  return invoke<ColorSchemeSettings>("get_color_scheme_settings_command");
+};
+
+/**
+ * Saves the color scheme export settings. When enabled, each applied
+ * wallpaper writes its scheme and sends a `color-scheme-exported` event.
+ */
+export const setColorSchemeSettings = async (settings: ColorSchemeSettings): Promise<void> => {
+  await invoke("set_color_scheme_settings_command", { settings });
+};
+
 /**
  * Starts rotating wallpapers from a list of paths every X seconds.
  * Frontend will receive `wallpaper-rotated` events with the active path.
+ * `effects` replaces the global chain for wallpapers in this rotation.
  */
 export const startWallpaperRotation = async (
   paths: string[],
-  intervalSec: number
+  intervalSec: number,
+  effects?: Effect[]
 ): Promise<void> => {
     const toastId = toast.loading("Starting wallpaper rotation...");
   try {
     await invoke("start_wallpaper_rotation", {
       paths,
       intervalSec,
+      effects: effects ?? null,
       window: appWindow,
     });
     toast.success("Wallpaper rotation started!", { id: toastId });
@@ -3,7 +3,13 @@ import { Switch } from "@/components/ui/switch";
 import { Settings, Image, Globe } from "lucide-react";
 import ConfirmDialog from "@/components/ui/ConfirmDialog";
 import { useWallpaperStore } from "@/store/useWallpaperStore";
-import { appThemeOptions, resolutionOptions, updateInterval } from "@/constants";
+import {
+  appThemeOptions,
+  imageFormatOptions,
+  imageQualityOptions,
+  resolutionOptions,
+  updateInterval,
+} from "@/constants";
 import { enable, disable, isEnabled } from "@tauri-apps/plugin-autostart";
 import { useTheme } from "@/contexts/ThemeContext";
 import LanguageSwitcher from "@/components/LanguageSwitcher";
@@ -89,10 +95,24 @@ const SettingsPage = () => {
             <DropdownRow
               isDarkMode={isDarkMode}
               label="Preferred Resolution"
-              value={settings ? settings.preferredResolution : "1920x1080"}
+              value={settings ? settings.preferredResolution : "auto"}
               options={resolutionOptions}
               onChange={(val) => updateSetting("preferredResolution", val, "wallpaper")}
             />
+            <DropdownRow
+              isDarkMode={isDarkMode}
+              label="Image format"
+              value={settings ? settings.imageFormat : "jpeg"}
+              options={imageFormatOptions}
+              onChange={(val) => updateSetting("imageFormat", val as "jpeg" | "png" | "webp", "wallpaper")}
+            />
+            <DropdownRow
+              isDarkMode={isDarkMode}
+              label="Image quality"
+              value={settings ? settings.imageQuality : "90"}
+              options={imageQualityOptions}
+              onChange={(val) => updateSetting("imageQuality", val, "wallpaper")}
+            />
             {/* <ToggleRow
               isDarkMode={isDarkMode}
               label="Save wallpaper automatically"
@@ -1,4 +1,4 @@
-import { Wallpaper } from "@/types";
+import { TagCount, Wallpaper, WallpaperPage } from "@/types";
 import { invoke } from "@tauri-apps/api/core";
 import { Store } from "@tauri-apps/plugin-store";
 import { create } from "zustand";
@@ -13,6 +13,9 @@ type Settings = {
   experimental: boolean;
   language: string;
   preferredResolution: string;
+  imageFormat: "jpeg" | "png" | "webp";
+  imageQuality: string;
+  syncInterval: string;
 };
 
 type SettingKey = keyof Settings;
@@ -25,11 +28,11 @@ interface WallpaperState {
   settings: Settings | null;
   loading: boolean;
   error: string | null;
-  page: number;
+  nextCursor: string | null;
   hasMore: boolean;
   settingsLoaded: boolean;
 
-  fetchWallpapers: (limit?: number, offset?: number) => Promise<void>;
+  fetchWallpapers: (limit?: number, cursor?: string | null) => Promise<void>;
   loadMoreWallpapers: () => Promise<void>;
   setWallpapers: (items: Wallpaper[]) => void;
   clearWallpapers: () => void;
@@ -59,35 +62,33 @@ export const useWallpaperStore = create<WallpaperState>((set, get) => ({
     autoStart: false,
     experimental: false,
     language: "en-US",
-    preferredResolution: "1920x1080",
+    preferredResolution: "auto",
+    imageFormat: "jpeg",
+    imageQuality: "90",
+    syncInterval: "3600000",
   },
   loading: false,
   error: null,
-  page: 0,
+  nextCursor: null,
   hasMore: true,
   settingsLoaded: false,
 
-  fetchWallpapers: async (limit = 100, offset = 0) => {
+  fetchWallpapers: async (limit = 100, cursor = null) => {
     set({ loading: true, error: null });
     try {
-      const result = await invoke<Wallpaper[]>("get_wallpapers", { limit, offset });
-
-      const allTags = Array.from(
-        new Set(
-          result
-            .flatMap((wp) => wp.tags?.split(",") || [])
-            .map((tag) => tag.trim())
-            .filter(Boolean)
-        )
-      ).sort();
-
-      set({
-        wallpapers: result,
+      const page = await invoke<WallpaperPage>("get_wallpapers", { limit, cursor });
+
+      const tagCounts = await invoke<TagCount[]>("list_tags_command");
+      const allTags = tagCounts.map((tag) => tag.name);
+
+      set((state) => ({
+        // A cursor continues the current list; no cursor starts over.
+        wallpapers: cursor ? [...state.wallpapers, ...page.wallpapers] : page.wallpapers,
         tags: allTags,
         loading: false,
-        hasMore: result.length === limit,
-        page: offset / limit + 1,
-      });
+        nextCursor: page.next_cursor,
+        hasMore: page.next_cursor !== null,
+      }));
 
       await get().persistState();
     } catch (e: any) {
@@ -96,12 +97,10 @@ export const useWallpaperStore = create<WallpaperState>((set, get) => ({
   },
 
   loadMoreWallpapers: async () => {
-    const { page, hasMore } = get();
-    if (!hasMore) return;
+    const { nextCursor, hasMore, loading } = get();
+    if (!hasMore || !nextCursor || loading) return;
 
-    const limit = 100;
-    const offset = page * limit;
-    await get().fetchWallpapers(limit, offset);
+    await get().fetchWallpapers(100, nextCursor);
   },
 
   setWallpapers: (items) => {
@@ -109,7 +108,7 @@ export const useWallpaperStore = create<WallpaperState>((set, get) => ({
   },
 
   clearWallpapers: () => {
-    set({ wallpapers: [], page: 0, hasMore: true });
+    set({ wallpapers: [], nextCursor: null, hasMore: true });
   },
 
   updateFavorite: async (id, isFavorite) => {
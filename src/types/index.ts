@@ -1,10 +1,10 @@
 import { Language, LanguageValue } from './language';
 import { TranslationKey } from './translationKey';
 import { Page } from './page';
-import { Wallpaper } from './wallpaper';
+import { BlockedWallpaper, Collection, ColorSchemeExport, ColorSchemeSettings, Effect, ExportReport, SchemeTarget, FocalPoint, ImageMetadata, ImportProgress, ImportReport, LibraryChange, LibraryFolder, OverlayItem, OverlaySettings, Palette, SmartCollection, Swatch, TagCount, Wallpaper, WallpaperFilter, WallpaperPage, WallpaperSort } from './wallpaper';
 import { Category } from './category';
 
-export type { Language, LanguageValue, TranslationKey, Page, Wallpaper, Category };
+export type { Language, LanguageValue, TranslationKey, Page, Wallpaper, WallpaperPage, FocalPoint, Effect, OverlayItem, OverlaySettings, ColorSchemeSettings, ColorSchemeExport, SchemeTarget, ImportReport, ImportProgress, LibraryFolder, LibraryChange, ImageMetadata, Collection, ExportReport, BlockedWallpaper, SmartCollection, WallpaperFilter, WallpaperSort, Palette, Swatch, TagCount, Category };
 
 
     
@@ -9,4 +9,233 @@ export interface Wallpaper {
   tags: string;
   is_ai_generated: boolean;
   is_favorite?: boolean;
-}
\ No newline at end of file
+  is_available?: boolean;
+  popularity?: number;
+  variants?: ImageVariant[];
+  focal_point?: FocalPoint | null;
+  /** `#rrggbb`, once a palette has been extracted. */
+  dominant_color?: string | null;
+  accent_color?: string | null;
+  /** `local` for imported files, whose `url` is their path. */
+  source?: "remote" | "local";
+  file_path?: string | null;
+  /** 1–5 stars, null until rated. */
+  rating?: number | null;
+  /** Left out of listings and rotations until shown again. */
+  is_hidden?: boolean;
+}
+
+/** Fractions of the image width and height, from the top-left corner. */
+export interface FocalPoint {
+  x: number;
+  y: number;
+}
+
+export interface ImageVariant {
+  kind: "thumbnail" | "1080p" | "1440p" | "4k" | "original";
+  url: string;
+  width: number;
+  height: number;
+}
+export interface Swatch {
+  color: string;
+  /** Share of the image, 0–1. */
+  population: number;
+}
+
+export interface Palette {
+  dominant: string;
+  accent: string;
+  colors: Swatch[];
+}
+
+export interface TagCount {
+  name: string;
+  count: number;
+}
+export interface WallpaperPage {
+  wallpapers: Wallpaper[];
+  next_cursor: string | null;
+}
+
+/** One step of an image effects chain; amounts are fractions. */
+export type Effect =
+  | { type: "blur"; sigma: number }
+  | { type: "brightness"; amount: number }
+  | { type: "contrast"; amount: number }
+  | { type: "saturation"; amount: number }
+  | { type: "grayscale" }
+  | { type: "sepia"; amount: number }
+  | { type: "tint"; color: string; amount: number }
+  | { type: "vignette"; amount: number };
+
+export type OverlayAnchor =
+  | "top-left" | "top" | "top-right"
+  | "left" | "center" | "right"
+  | "bottom-left" | "bottom" | "bottom-right";
+
+/** A widget drawn on applied wallpapers; `size` is a fraction of the image height. */
+export type OverlayItem = (
+  | { type: "date"; format?: string | null }
+  | { type: "clock"; format?: string | null }
+  | { type: "quote" }
+  | { type: "text"; text: string }
+  | { type: "calendar" }
+) & {
+  position?: OverlayAnchor;
+  color?: string;
+  size?: number;
+  bold?: boolean;
+  shadow?: boolean;
+};
+
+export interface OverlaySettings {
+  enabled: boolean;
+  items: OverlayItem[];
+  /** Empty uses the bundled quotes. */
+  quotes: string[];
+  /** Seconds between re-renders, 0 for never. */
+  refresh_interval_sec: number;
+}
+
+export type BuiltinSchemeTemplate = "xresources" | "kitty" | "alacritty" | "foot" | "json" | "css";
+
+/**
+ * Where an exported color scheme goes. User templates fill `{background}`,
+ * `{foreground}`, `{cursor}`, `{color0}`–`{color15}` (with `.strip` or
+ * `.rgb`) and `{wallpaper}`.
+ */
+export interface SchemeTarget {
+  template: { builtin: BuiltinSchemeTemplate } | { file: string };
+  /** `~` and relative paths allowed; required for file templates. */
+  output?: string | null;
+}
+
+export interface ColorSchemeSettings {
+  enabled: boolean;
+  targets: SchemeTarget[];
+}
+
+/** Payload of the `color-scheme-exported` event. */
+export interface ColorSchemeExport {
+  scheme: {
+    background: string;
+    foreground: string;
+    cursor: string;
+    colors: string[];
+  } | null;
+  written: string[];
+  failed: string[];
+}
+
+export interface ImportReport {
+  imported: number;
+  /** Files imported before, refreshed. */
+  updated: number;
+  skipped: { path: string; reason: string }[];
+}
+
+/** Payload of the `import-progress` event. */
+export interface ImportProgress {
+  processed: number;
+  total: number;
+  imported: number;
+  updated: number;
+  skipped: number;
+}
+
+/** A folder kept in sync with the library. */
+export interface LibraryFolder {
+  path: string;
+  recursive: boolean;
+}
+
+/** Payload of the `library-changed` event. */
+export interface LibraryChange {
+  added: number;
+  updated: number;
+  removed: number;
+  renamed: number;
+}
+
+/** What an imported photo says about itself. */
+export interface ImageMetadata {
+  camera_make: string | null;
+  camera_model: string | null;
+  lens_model: string | null;
+  /** `YYYY-MM-DDTHH:MM:SS` in the camera's local time. */
+  captured_at: string | null;
+  latitude: number | null;
+  longitude: number | null;
+  altitude: number | null;
+  author: string | null;
+  copyright: string | null;
+  description: string | null;
+  keywords: string[];
+}
+
+/** A user-made group of wallpapers, or the built-in Favorites. */
+export interface Collection {
+  id: string;
+  name: string;
+  is_builtin: boolean;
+  item_count: number;
+  /** The cover picked by the user, if any. */
+  cover_wallpaper_id: string | null;
+  /** The picked cover, else the first item. */
+  cover: Wallpaper | null;
+}
+
+/** Structured wallpaper filter; unset fields don't narrow the result. */
+export interface WallpaperFilter {
+  min_width?: number | null;
+  min_height?: number | null;
+  aspect_ratios?: ("4:3" | "16:10" | "16:9" | "21:9" | "32:9" | "1:1")[];
+  orientation?: "landscape" | "portrait" | "square" | null;
+  is_ai_generated?: boolean | null;
+  favorites_only?: boolean;
+  /** Unrated wallpapers never match. */
+  min_rating?: number | null;
+  /** Hidden wallpapers are left out unless set; blocked ones always are. */
+  include_hidden?: boolean;
+  tags?: string[];
+  tag_match?: "all" | "any";
+  added_after?: string | null;
+  added_before?: string | null;
+  /** Counted from when the filter runs, not when it was saved. */
+  added_within_days?: number | null;
+  color?: string | null;
+}
+
+export type WallpaperSort =
+  | { by: "newest" | "oldest" | "title" | "resolution" | "popularity" }
+  | { by: "random"; seed: number };
+
+/** A saved search, evaluated against the library whenever it is read. */
+export interface SmartCollection {
+  id: string;
+  name: string;
+  filter: WallpaperFilter;
+  sort: WallpaperSort;
+  /** Wallpapers matching right now. */
+  count: number;
+}
+
+/** A "never show again" entry; the wallpaper itself may be gone. */
+export interface BlockedWallpaper {
+  id: number;
+  title: string;
+  mongo_id: string | null;
+  file_path: string | null;
+  blocked_at: string;
+  /** While the wallpaper is still stored. */
+  thumbnail: string | null;
+}
+
+/** Result of an export, also the payload of `export-progress` events. */
+export interface ExportReport {
+  directory: string;
+  written: string[];
+  failed: string[];
+  total: number;
+}